@@ -0,0 +1,9 @@
+def pt_div(a, b):
+    quotient = abs(a) // abs(b)
+    return quotient if (a < 0) == (b < 0) else -quotient
+
+a: int = 0
+b: int = 0
+a = int(input())
+b = int(input())
+print(pt_div(a, b), end="")
@@ -0,0 +1,30 @@
+def pt_str(value):
+    if isinstance(value, str):
+        return value
+    return pt_repr(value)
+
+def pt_repr(value):
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if isinstance(value, float):
+        if value != value:
+            return "NaN"
+        return "%.0f" % value if value.is_integer() else repr(value)
+    if isinstance(value, str):
+        return '"' + value + '"'
+    if isinstance(value, list):
+        return "[" + ", ".join(pt_repr(v) for v in value) + "]"
+    if isinstance(value, dict):
+        return "{" + ", ".join(sorted(pt_repr(k) + ": " + pt_repr(v) for k, v in value.items())) + "}"
+    if value is None:
+        return "null"
+    return str(value)
+
+def fatorial(n):
+    if n < 2:
+        return 1
+    return n * fatorial(n - 1)
+n: int = 0
+n = int(input())
+print(pt_str(fatorial(n)), end="")
+print()
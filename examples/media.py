@@ -0,0 +1,38 @@
+def pt_str(value):
+    if isinstance(value, str):
+        return value
+    return pt_repr(value)
+
+def pt_repr(value):
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if isinstance(value, float):
+        if value != value:
+            return "NaN"
+        return "%.0f" % value if value.is_integer() else repr(value)
+    if isinstance(value, str):
+        return '"' + value + '"'
+    if isinstance(value, list):
+        return "[" + ", ".join(pt_repr(v) for v in value) + "]"
+    if isinstance(value, dict):
+        return "{" + ", ".join(sorted(pt_repr(k) + ": " + pt_repr(v) for k, v in value.items())) + "}"
+    if value is None:
+        return "null"
+    return str(value)
+
+a: float = 0.0
+b: float = 0.0
+c: float = 0.0
+a = float(input())
+b = float(input())
+c = float(input())
+media: float = 0.0
+media = (a + b + c) / 3.0
+print("media: ", end="")
+print(pt_str(media), end="")
+print()
+if media > 6.9:
+    print("aprovado", end="")
+else:
+    print("reprovado", end="")
+print()
@@ -0,0 +1,13 @@
+i: int = 0
+num: int = 0
+print("Entre com um numero maior do que 15: ", end="")
+num = int(input())
+if num > 14:
+    i = 15
+    while i < num + 1:
+        print(i, end="")
+        print(" ", end="")
+        i = i + 15
+else:
+    print("NAO EXISTE", end="")
+print()
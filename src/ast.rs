@@ -3,6 +3,14 @@ use std::fmt;
 #[derive(Debug, PartialEq)]
 pub struct Program {
     pub statements: Vec<Statement>,
+    // Source line of each statement. Empty for code that doesn't come from a source file.
+    pub lines: Vec<usize>,
+}
+
+impl Program {
+    pub fn line(&self, index: usize) -> Option<usize> {
+        self.lines.get(index).copied()
+    }
 }
 
 impl fmt::Display for Program {
@@ -17,6 +25,14 @@ impl fmt::Display for Program {
 #[derive(Debug, PartialEq, Clone)]
 pub struct BlockStatement {
     pub statements: Vec<Statement>,
+    // Source line of each statement, like `Program::lines`.
+    pub lines: Vec<usize>,
+}
+
+impl BlockStatement {
+    pub fn line(&self, index: usize) -> Option<usize> {
+        self.lines.get(index).copied()
+    }
 }
 
 impl fmt::Display for BlockStatement {
@@ -76,7 +92,16 @@ pub enum Expression {
     While(Box<Expression>, BlockStatement),
     Assign(Box<Expression>, Box<Expression>),
     FunctionLiteral(Vec<String>, BlockStatement),
-    Call(Box<Expression>, Vec<Expression>),
+    // Where the call starts, for tracebacks. `None` for code that doesn't come from a source
+    // file, like `Program::lines`.
+    Call(Box<Expression>, Vec<Expression>, Option<Position>),
+}
+
+// A place in the source code. Both start at 1, and the column counts chars.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
 }
 
 impl fmt::Display for Expression {
@@ -119,7 +144,7 @@ impl fmt::Display for Expression {
             Expression::FunctionLiteral(parameters, body) => {
                 write!(f, "fn({}) {}", parameters.join(", "), body)
             }
-            Expression::Call(function, arguments) => {
+            Expression::Call(function, arguments, _) => {
                 write!(f, "{}({})", function, comma_separated(arguments))
             }
         }
@@ -0,0 +1,639 @@
+// Control-flow graphs. The main program and each function body are lowered into basic blocks:
+// statements that always run in sequence, ended by a jump. `se` and `enquanto` branch on their
+// condition and `retorne` jumps to the exit block. Statements after a `retorne` get a block
+// without predecessors, so unreachable code can be found.
+use crate::ast::{BlockStatement, Expression, Program, Statement};
+use crate::formatter;
+use std::collections::BTreeSet;
+use std::fmt;
+
+pub type BlockId = usize;
+
+// The first block that runs.
+pub const ENTRY: BlockId = 0;
+
+#[derive(Debug, PartialEq)]
+pub enum Terminator<'a> {
+    Goto(BlockId),
+    // Jumps to the first block when the condition is true, to the second one otherwise.
+    Branch(&'a Expression, BlockId, BlockId),
+    // Jumps to the exit block.
+    Return(Option<&'a Expression>),
+    // Ends the exit block.
+    Exit,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BasicBlock<'a> {
+    // Statements without control flow: declarations, assignments, `leia`, `imprima`, calls.
+    pub statements: Vec<&'a Statement>,
+    pub terminator: Terminator<'a>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cfg<'a> {
+    // The function name, `<anônima>` for functions that aren't assigned with `let`, or `None`
+    // for the main program.
+    pub name: Option<String>,
+    pub parameters: Vec<String>,
+    pub blocks: Vec<BasicBlock<'a>>,
+    // The last block, where the program or function ends.
+    pub exit: BlockId,
+}
+
+// The graphs of the main program, then of every function in the order they are defined.
+pub fn build(program: &Program) -> Vec<Cfg<'_>> {
+    let mut functions = vec![];
+    let main = Builder::build(None, vec![], &program.statements, &mut functions);
+    let mut cfgs = vec![main];
+    cfgs.extend(functions);
+    cfgs
+}
+
+impl<'a> Cfg<'a> {
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        match self.blocks[block].terminator {
+            Terminator::Goto(target) => vec![target],
+            Terminator::Branch(_, yes, no) => vec![yes, no],
+            Terminator::Return(_) => vec![self.exit],
+            Terminator::Exit => vec![],
+        }
+    }
+
+    pub fn predecessors(&self, block: BlockId) -> Vec<BlockId> {
+        (0..self.blocks.len())
+            .filter(|&b| self.successors(b).contains(&block))
+            .collect()
+    }
+
+    // Whether each block can be reached from the entry.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = vec![ENTRY];
+        while let Some(block) = pending.pop() {
+            if !reachable[block] {
+                reachable[block] = true;
+                pending.extend(self.successors(block));
+            }
+        }
+        reachable
+    }
+
+    // Blocks with statements or a `retorne` that never run.
+    pub fn unreachable_code(&self) -> Vec<BlockId> {
+        let reachable = self.reachable();
+        (0..self.blocks.len())
+            .filter(|&b| !reachable[b])
+            .filter(|&b| {
+                let block = &self.blocks[b];
+                !block.statements.is_empty() || matches!(block.terminator, Terminator::Return(_))
+            })
+            .collect()
+    }
+}
+
+// Like:
+//
+// funcao f(n)
+// b0:
+//     x = n;
+//     se (x > 1) b1 senao b2
+// b1:
+//     retorne x
+// ...
+impl fmt::Display for Cfg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.name {
+            Some(name) => writeln!(f, "funcao {}({})", name, self.parameters.join(", "))?,
+            None => writeln!(f, "programa")?,
+        }
+        let reachable = self.reachable();
+        for (id, block) in self.blocks.iter().enumerate() {
+            if reachable[id] {
+                writeln!(f, "b{}:", id)?;
+            } else {
+                writeln!(f, "b{}: // inalcançável", id)?;
+            }
+            for stmt in &block.statements {
+                writeln!(f, "    {}", formatter::statement_line(stmt))?;
+            }
+            match block.terminator {
+                Terminator::Goto(target) => writeln!(f, "    goto b{}", target)?,
+                Terminator::Branch(condition, yes, no) => writeln!(
+                    f,
+                    "    se ({}) b{} senao b{}",
+                    formatter::expression_line(condition),
+                    yes,
+                    no
+                )?,
+                Terminator::Return(Some(value)) => {
+                    writeln!(f, "    retorne {}", formatter::expression_line(value))?
+                }
+                Terminator::Return(None) => writeln!(f, "    retorne")?,
+                Terminator::Exit => writeln!(f, "    fim")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Builder<'a, 'f> {
+    blocks: Vec<BasicBlock<'a>>,
+    // The block statements are added to.
+    current: BlockId,
+    // Graphs of the functions found so far.
+    functions: &'f mut Vec<Cfg<'a>>,
+}
+
+impl<'a, 'f> Builder<'a, 'f> {
+    fn build(
+        name: Option<String>,
+        parameters: Vec<String>,
+        statements: &'a [Statement],
+        functions: &'f mut Vec<Cfg<'a>>,
+    ) -> Cfg<'a> {
+        let mut builder = Builder {
+            blocks: vec![],
+            current: ENTRY,
+            functions,
+        };
+        builder.new_block();
+        builder.statements(statements);
+        let exit = builder.new_block();
+        builder.jump(Terminator::Goto(exit));
+        builder.blocks[exit].terminator = Terminator::Exit;
+        Cfg {
+            name,
+            parameters,
+            blocks: builder.blocks,
+            exit,
+        }
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock {
+            statements: vec![],
+            // Replaced when the block ends.
+            terminator: Terminator::Exit,
+        });
+        self.blocks.len() - 1
+    }
+
+    // Ends the current block.
+    fn jump(&mut self, terminator: Terminator<'a>) {
+        self.blocks[self.current].terminator = terminator;
+    }
+
+    fn statements(&mut self, statements: &'a [Statement]) {
+        for stmt in statements {
+            self.statement(stmt);
+        }
+    }
+
+    fn block(&mut self, block: &'a BlockStatement) {
+        self.statements(&block.statements);
+    }
+
+    fn statement(&mut self, stmt: &'a Statement) {
+        match stmt {
+            Statement::Expression(Expression::If(condition, consequence, alternative)) => {
+                self.functions_in(condition);
+                let branch = self.current;
+                let consequence_start = self.new_block();
+                self.current = consequence_start;
+                self.block(consequence);
+                let mut ends = vec![self.current];
+                let alternative_start = alternative.as_ref().map(|alternative| {
+                    let start = self.new_block();
+                    self.current = start;
+                    self.block(alternative);
+                    ends.push(self.current);
+                    start
+                });
+                let after = self.new_block();
+                self.blocks[branch].terminator = Terminator::Branch(
+                    condition,
+                    consequence_start,
+                    alternative_start.unwrap_or(after),
+                );
+                for end in ends {
+                    self.blocks[end].terminator = Terminator::Goto(after);
+                }
+                self.current = after;
+            }
+            Statement::Expression(Expression::While(condition, body)) => {
+                self.functions_in(condition);
+                let header = self.new_block();
+                self.jump(Terminator::Goto(header));
+                let body_start = self.new_block();
+                self.current = body_start;
+                self.block(body);
+                self.jump(Terminator::Goto(header));
+                let after = self.new_block();
+                self.blocks[header].terminator = Terminator::Branch(condition, body_start, after);
+                self.current = after;
+            }
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.functions_in(value);
+                }
+                self.jump(Terminator::Return(value.as_ref()));
+                // Anything after it is unreachable.
+                self.current = self.new_block();
+            }
+            Statement::Let(name, Expression::FunctionLiteral(parameters, body)) => {
+                self.function(Some(name), parameters, body);
+                self.blocks[self.current].statements.push(stmt);
+            }
+            Statement::Let(_, value) | Statement::Print(Some(value)) => {
+                self.functions_in(value);
+                self.blocks[self.current].statements.push(stmt);
+            }
+            Statement::Expression(exp) => {
+                self.functions_in(exp);
+                self.blocks[self.current].statements.push(stmt);
+            }
+            _ => self.blocks[self.current].statements.push(stmt),
+        }
+    }
+
+    fn function(&mut self, name: Option<&str>, parameters: &[String], body: &'a BlockStatement) {
+        let name = name.unwrap_or("<anônima>").to_string();
+        // Added after the functions inside it, so keep its place.
+        let index = self.functions.len();
+        let cfg = Builder::build(
+            Some(name),
+            parameters.to_vec(),
+            &body.statements,
+            self.functions,
+        );
+        self.functions.insert(index, cfg);
+    }
+
+    // Builds the graphs of the anonymous functions in an expression.
+    fn functions_in(&mut self, exp: &'a Expression) {
+        match exp {
+            Expression::FunctionLiteral(parameters, body) => self.function(None, parameters, body),
+            Expression::Array(values) => values.iter().for_each(|v| self.functions_in(v)),
+            Expression::Hash(pairs) => {
+                for (key, value) in pairs {
+                    self.functions_in(key);
+                    self.functions_in(value);
+                }
+            }
+            Expression::Index(left, index) => {
+                self.functions_in(left);
+                self.functions_in(index);
+            }
+            Expression::Prefix(_, right) => self.functions_in(right),
+            Expression::Infix(_, left, right) | Expression::Assign(left, right) => {
+                self.functions_in(left);
+                self.functions_in(right);
+            }
+            Expression::Call(function, arguments, _) => {
+                self.functions_in(function);
+                arguments.iter().for_each(|a| self.functions_in(a));
+            }
+            _ => {}
+        }
+    }
+}
+
+// -- Dataflow analyses
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+// A dataflow analysis: facts flow through the blocks in `direction` until nothing changes.
+pub trait Analysis<'a> {
+    type Fact: Clone + PartialEq;
+
+    fn direction(&self) -> Direction;
+
+    // The fact every block starts with.
+    fn bottom(&self, cfg: &Cfg<'a>) -> Self::Fact;
+
+    // The fact at the start of the entry block for forward analyses, or at the end of the exit
+    // block for backward ones.
+    fn boundary(&self, cfg: &Cfg<'a>) -> Self::Fact;
+
+    // Combines the facts where paths meet.
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact;
+
+    // The fact after the block from the fact before it, or the other way for backward
+    // analyses.
+    fn transfer(&self, block: &BasicBlock<'a>, fact: &Self::Fact) -> Self::Fact;
+}
+
+// The facts at the start and at the end of each block.
+#[derive(Debug, PartialEq)]
+pub struct Solution<F> {
+    pub start: Vec<F>,
+    pub end: Vec<F>,
+}
+
+pub fn solve<'a, A: Analysis<'a>>(cfg: &Cfg<'a>, analysis: &A) -> Solution<A::Fact> {
+    let len = cfg.blocks.len();
+    let mut start = vec![analysis.bottom(cfg); len];
+    let mut end = vec![analysis.bottom(cfg); len];
+    let forward = analysis.direction() == Direction::Forward;
+    // Where facts come from: predecessors for forward analyses, successors for backward ones.
+    let (boundary_block, neighbors) = if forward {
+        (
+            ENTRY,
+            (0..len).map(|b| cfg.predecessors(b)).collect::<Vec<_>>(),
+        )
+    } else {
+        (
+            cfg.exit,
+            (0..len).map(|b| cfg.successors(b)).collect::<Vec<_>>(),
+        )
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in 0..len {
+            // Facts enter a block on one side and leave it on the other.
+            let (facts_in, facts_out) = if forward {
+                (&mut start, &mut end)
+            } else {
+                (&mut end, &mut start)
+            };
+            let fact = if block == boundary_block {
+                analysis.boundary(cfg)
+            } else {
+                neighbors[block]
+                    .iter()
+                    .fold(analysis.bottom(cfg), |fact, &neighbor| {
+                        analysis.join(&fact, &facts_out[neighbor])
+                    })
+            };
+            let result = analysis.transfer(&cfg.blocks[block], &fact);
+            if facts_out[block] != result {
+                facts_out[block] = result;
+                changed = true;
+            }
+            facts_in[block] = fact;
+        }
+    }
+    Solution { start, end }
+}
+
+// Variables that surely received a value on every path: parameters, `let`, `leia` and
+// assignments. Declarations like `int x;` don't count, although they set a default value, so
+// uses of variables that were never given a value can be found.
+pub struct DefinitelyAssigned;
+
+impl<'a> Analysis<'a> for DefinitelyAssigned {
+    // `None` is every variable, the start for blocks no path reached yet.
+    type Fact = Option<BTreeSet<String>>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn bottom(&self, _cfg: &Cfg<'a>) -> Self::Fact {
+        None
+    }
+
+    fn boundary(&self, cfg: &Cfg<'a>) -> Self::Fact {
+        Some(cfg.parameters.iter().cloned().collect())
+    }
+
+    fn join(&self, a: &Self::Fact, b: &Self::Fact) -> Self::Fact {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(a.intersection(b).cloned().collect()),
+            (Some(fact), None) | (None, Some(fact)) => Some(fact.clone()),
+            (None, None) => None,
+        }
+    }
+
+    fn transfer(&self, block: &BasicBlock<'a>, fact: &Self::Fact) -> Self::Fact {
+        let mut assigned = fact.clone()?;
+        for stmt in &block.statements {
+            match stmt {
+                Statement::Let(name, _) | Statement::Read(name) => {
+                    assigned.insert(name.clone());
+                }
+                Statement::Expression(Expression::Assign(name, _)) => {
+                    if let Expression::Identifier(name) = name.as_ref() {
+                        assigned.insert(name.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(assigned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build, solve, Analysis, BasicBlock, Cfg, DefinitelyAssigned, Direction, Terminator,
+    };
+    use crate::ast::Program;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input.to_string())).parse_program()
+    }
+
+    fn dump(input: &str) -> String {
+        build(&parse(input))
+            .iter()
+            .map(|cfg| cfg.to_string())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn branches() {
+        let input = "int x;
+leia x;
+se (x > 0) {
+    imprima(\"positivo\");
+} senao {
+    se (x < 0) { imprima(\"negativo\"); }
+}
+imprima;";
+        let expected = "programa
+b0:
+    int x;
+    leia x;
+    se (x > 0) b1 senao b2
+b1:
+    imprima(\"positivo\");
+    goto b5
+b2:
+    se (x < 0) b3 senao b4
+b3:
+    imprima(\"negativo\");
+    goto b4
+b4:
+    goto b5
+b5:
+    imprima;
+    goto b6
+b6:
+    fim
+";
+        assert_eq!(dump(input), expected);
+    }
+
+    #[test]
+    fn loops() {
+        let input = "int i;
+enquanto (i < 3) {
+    i = i + 1;
+}
+enquanto (FALSO) {}";
+        let expected = "programa
+b0:
+    int i;
+    goto b1
+b1:
+    se (i < 3) b2 senao b3
+b2:
+    i = i + 1;
+    goto b1
+b3:
+    goto b4
+b4:
+    se (FALSO) b5 senao b6
+b5:
+    goto b4
+b6:
+    goto b7
+b7:
+    fim
+";
+        assert_eq!(dump(input), expected);
+    }
+
+    #[test]
+    fn functions() {
+        let input = "let f = funcao(n) {
+    se (n < 2) { retorne 1; imprima(n); }
+    retorne n * f(n - 1);
+    imprima(n);
+};
+imprima(funcao() { retorne; }());";
+        let expected = "programa
+b0:
+    let f = funcao(n) { ... };
+    imprima(funcao() { ... }());
+    goto b1
+b1:
+    fim
+
+funcao f(n)
+b0:
+    se (n < 2) b1 senao b3
+b1:
+    retorne 1
+b2: // inalcançável
+    imprima(n);
+    goto b3
+b3:
+    retorne n * f(n - 1)
+b4: // inalcançável
+    imprima(n);
+    goto b5
+b5:
+    fim
+
+funcao <anônima>()
+b0:
+    retorne
+b1: // inalcançável
+    goto b2
+b2:
+    fim
+";
+        assert_eq!(dump(input), expected);
+        let program = parse(input);
+        let cfgs = build(&program);
+        assert_eq!(cfgs[1].unreachable_code(), vec![2, 4]);
+        assert_eq!(cfgs[2].unreachable_code(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn definitely_assigned() {
+        let program = parse(
+            "int x; int y; leia x;
+            se (x > 0) { y = 1; let z = 2; } senao { y = 2; }
+            imprima(y);",
+        );
+        let cfgs = build(&program);
+        let solution = solve(&cfgs[0], &DefinitelyAssigned);
+        let names = |block: usize| {
+            solution.start[block]
+                .as_ref()
+                .unwrap()
+                .iter()
+                .cloned()
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(names(0), Vec::<String>::new());
+        assert_eq!(names(1), vec!["x"]);
+        // `z` is only assigned on one path.
+        assert_eq!(names(3), vec!["x", "y"]);
+
+        let program = parse("let f = funcao(a, b) { enquanto (a) { let c = 1; } retorne c; };");
+        let cfgs = build(&program);
+        let solution = solve(&cfgs[1], &DefinitelyAssigned);
+        assert_eq!(
+            solution.start[cfgs[1].exit],
+            Some(vec!["a".to_string(), "b".to_string()].into_iter().collect())
+        );
+    }
+
+    // Whether a path from the block reaches a `retorne`.
+    struct ReachesReturn;
+
+    impl<'a> Analysis<'a> for ReachesReturn {
+        type Fact = bool;
+
+        fn direction(&self) -> Direction {
+            Direction::Backward
+        }
+
+        fn bottom(&self, _cfg: &Cfg<'a>) -> bool {
+            false
+        }
+
+        fn boundary(&self, _cfg: &Cfg<'a>) -> bool {
+            false
+        }
+
+        fn join(&self, a: &bool, b: &bool) -> bool {
+            *a || *b
+        }
+
+        fn transfer(&self, block: &BasicBlock<'a>, fact: &bool) -> bool {
+            *fact || matches!(block.terminator, Terminator::Return(_))
+        }
+    }
+
+    #[test]
+    fn backward() {
+        let program = parse(
+            "let f = funcao(n) {
+                enquanto (n > 0) { se (n == 5) { retorne n; } n = n - 1; }
+                imprima(n);
+            };",
+        );
+        let cfgs = build(&program);
+        let solution = solve(&cfgs[1], &ReachesReturn);
+        // The loop can return, what runs after it can't.
+        assert_eq!(
+            solution.start,
+            vec![true, true, true, true, true, true, false, false]
+        );
+    }
+}
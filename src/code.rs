@@ -3,6 +3,7 @@ use std::fmt;
 pub struct Bytecode {
     pub instructions: Instructions,
     pub constants: Vec<Constant>,
+    pub lines: LineTable,
 }
 
 impl Bytecode {
@@ -10,6 +11,7 @@ impl Bytecode {
         Bytecode {
             instructions,
             constants,
+            lines: LineTable::new(),
         }
     }
 }
@@ -17,6 +19,44 @@ impl Bytecode {
 // Instructions are a series of bytes.
 pub type Instructions = Vec<u8>;
 
+// Maps instruction offsets to source lines.
+//
+// Only the offsets where the line changes are stored, so an instruction belongs to the last
+// entry at or before its offset.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct LineTable {
+    // (offset, line), sorted by offset.
+    entries: Vec<(usize, usize)>,
+}
+
+impl LineTable {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Records that the instructions starting at `offset` come from `line`. Offsets must be
+    // added in increasing order.
+    pub fn add(&mut self, offset: usize, line: usize) {
+        match self.entries.last_mut() {
+            Some((_, last_line)) if *last_line == line => {}
+            Some((last_offset, last_line)) if *last_offset == offset => *last_line = line,
+            _ => self.entries.push((offset, line)),
+        }
+    }
+
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        match self.entries.binary_search_by_key(&offset, |&(o, _)| o) {
+            Ok(i) => Some(self.entries[i].1),
+            Err(0) => None,
+            Err(i) => Some(self.entries[i - 1].1),
+        }
+    }
+}
+
 // Generates an enum that can be easily converted from u8.
 //
 // ```
@@ -69,38 +109,92 @@ macro_rules! byte_enum {
     };
 }
 
+// The comment above each opcode is its stack effect, as `[before] -> [after]` with the top of
+// the stack on the right. Operands are read from the instruction itself, not from the stack.
+//
+// New opcodes must be appended to keep the byte value of the existing ones.
 byte_enum!(
     OpCode,
     [
+        // [] -> [constants[index]]
         Constant,
+        // [a] -> []
         Pop,
+        // [a, b] -> [a + b]; strings are concatenated.
         Add,
+        // [a, b] -> [a - b]
         Sub,
+        // [a, b] -> [a * b]
         Mul,
+        // [a, b] -> [a / b]
         Div,
+        // [] -> [true]
         True,
+        // [] -> [false]
         False,
+        // [a, b] -> [a == b]
         Equal,
+        // [a, b] -> [a != b]
         NotEqual,
+        // [a, b] -> [a > b]; strings compare lexicographically.
         GreaterThan,
+        // [a] -> [-a]
         Minus,
+        // [a] -> [!a]
         Bang,
+        // [a] -> []; jumps to the operand offset when `a` is not truthy.
         JumpIfNotTruthy,
+        // [] -> []; jumps to the operand offset.
         Jump,
+        // [] -> [null]
         Null,
+        // [] -> [globals[index]]
         GetGlobal,
+        // [a] -> []; globals[index] = a
         SetGlobal,
+        // [a1, ..., an] -> [[a1, ..., an]]
         Array,
+        // [k1, v1, ..., kn, vn] -> [{k1: v1, ..., kn: vn}]; the operand counts keys and values.
         Hash,
+        // [a, i] -> [a[i]]
         Index,
+        // [f, a1, ..., an] -> [f(a1, ..., an)]
         Call,
+        // [a] -> []; returns `a` to the caller.
         ReturnValue,
+        // [] -> []; returns null to the caller.
         Return,
+        // [] -> [locals[index]]
         GetLocal,
+        // [a] -> []; locals[index] = a
         SetLocal,
+        // [] -> [builtins[index]]
         GetBuiltin,
+        // [f1, ..., fn] -> [closure]; captures n free variables of constants[index].
         Closure,
-        GetFree
+        // [] -> [free[index]]
+        GetFree,
+        // [a, b] -> [a < b]; strings compare lexicographically.
+        LessThan,
+        // The compiler emits none of the opcodes from LessEqual to Or: the lexer and parser have
+        // no `<=`, `>=`, `%`, `^`, `e` or `ou`, so no program can use them yet. They are part of
+        // the instruction set so that its byte values stay put when the grammar gains them.
+        // [a, b] -> [a <= b]; strings compare lexicographically.
+        LessEqual,
+        // [a, b] -> [a >= b]; strings compare lexicographically.
+        GreaterEqual,
+        // [a, b] -> [a % b]
+        Modulo,
+        // [a, b] -> [a ^ b]
+        Power,
+        // [a, b] -> [a e b]; both operands are evaluated.
+        And,
+        // [a, b] -> [a ou b]; both operands are evaluated.
+        Or,
+        // [a] -> []; writes `a`, without a newline.
+        Print,
+        // [a] -> [b]; reads a line as `b`, a value of the same type as `a`.
+        Read
     ]
 );
 
@@ -117,6 +211,12 @@ impl OpCode {
 }
 
 pub fn print_instructions(insts: &[u8]) -> String {
+    print_instructions_with_lines(insts, &LineTable::new())
+}
+
+// Same as `print_instructions`, with each instruction annotated with its source line when
+// `lines` has one.
+pub fn print_instructions_with_lines(insts: &[u8], lines: &LineTable) -> String {
     let mut result = String::new();
     let mut i = 0;
     while i < insts.len() {
@@ -125,23 +225,180 @@ pub fn print_instructions(insts: &[u8]) -> String {
             if i > 0 {
                 result.push('\n');
             }
+            let (operands, offset) = match read_operands(&def, insts, i + 1) {
+                Some(operands) => operands,
+                None => {
+                    result.push_str(&format!("{:04} ERROR: truncated {}", i, def.name));
+                    break;
+                }
+            };
             result.push_str(&format!("{:04} ", i));
+            let line = lines.line_at(i);
             i += 1;
-            let (operands, offset) = read_operands(&def, insts, i);
             result.push_str(&def.name);
             for operand in operands {
                 result.push_str(&format!(" {}", operand));
             }
+            if let Some(line) = line {
+                result.push_str(&format!(" [line {}]", line));
+            }
             i += offset;
         } else {
-            // TODO: Return result?
-            return "".to_string();
+            if i > 0 {
+                result.push('\n');
+            }
+            result.push_str(&format!("{:04} ERROR: unknown opcode {}", i, op_code));
+            i += 1;
+        }
+    }
+    result
+}
+
+// Produces a full listing of a compiled program: the constant pool followed by the main
+// instructions and every compiled function in the pool. Jump targets are printed as labels
+// and constant operands are annotated with the value they refer to. Instructions are annotated
+// with their source line when the program was compiled with debug info.
+pub fn disassemble(bytecode: &Bytecode) -> String {
+    let mut result = String::from("constants:\n");
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        let value = match constant {
+            Constant::CompiledFunction(_) => format!("<fn {}>", index),
+            _ => constant.to_string(),
+        };
+        result.push_str(&format!(
+            "{:04} {} {}\n",
+            index,
+            constant.type_name(),
+            value
+        ));
+    }
+
+    result.push_str("\nmain:\n");
+    disassemble_instructions(
+        &bytecode.instructions,
+        &bytecode.lines,
+        &bytecode.constants,
+        &mut result,
+    );
+
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        if let Constant::CompiledFunction(cf) = constant {
+            result.push_str(&format!(
+                "\n<fn {}> ({} locals, {} parameters):\n",
+                index, cf.num_locals, cf.num_parameters
+            ));
+            disassemble_instructions(
+                &cf.instructions,
+                &cf.lines,
+                &bytecode.constants,
+                &mut result,
+            );
         }
     }
     result
 }
 
-fn read_operands(def: &Definition, insts: &[u8], start: usize) -> (Vec<usize>, usize) {
+fn disassemble_instructions(
+    insts: &[u8],
+    lines: &LineTable,
+    constants: &[Constant],
+    result: &mut String,
+) {
+    let labels = jump_labels(insts);
+    let mut i = 0;
+    while i < insts.len() {
+        if let Some(label) = labels.iter().position(|&target| target == i) {
+            result.push_str(&format!("L{}:\n", label));
+        }
+        let def = match lookup_definition(insts[i]) {
+            Some(def) => def,
+            None => {
+                result.push_str(&format!("{:04} ERROR: unknown opcode {}\n", i, insts[i]));
+                i += 1;
+                continue;
+            }
+        };
+        let (operands, offset) = match read_operands(&def, insts, i + 1) {
+            Some(operands) => operands,
+            None => {
+                result.push_str(&format!("{:04} ERROR: truncated {}\n", i, def.name));
+                break;
+            }
+        };
+
+        result.push_str(&format!("{:04} {}", i, def.name));
+        let line = lines.line_at(i);
+        match OpCode::from_byte(insts[i]) {
+            Some(OpCode::Jump) | Some(OpCode::JumpIfNotTruthy) => {
+                let label = labels.iter().position(|&target| target == operands[0]);
+                result.push_str(&format!(" L{}", label.unwrap_or_default()));
+            }
+            Some(OpCode::Constant) | Some(OpCode::Closure) => {
+                for operand in &operands {
+                    result.push_str(&format!(" {}", operand));
+                }
+                match constants.get(operands[0]) {
+                    Some(Constant::CompiledFunction(_)) => {
+                        result.push_str(&format!(" ; <fn {}>", operands[0]))
+                    }
+                    Some(constant) => result.push_str(&format!(" ; {}", constant)),
+                    None => result.push_str(" ; ERROR: constant out of range"),
+                }
+            }
+            _ => {
+                for operand in &operands {
+                    result.push_str(&format!(" {}", operand));
+                }
+            }
+        }
+        if let Some(line) = line {
+            result.push_str(&format!(" [line {}]", line));
+        }
+        result.push('\n');
+        i += 1 + offset;
+    }
+    // A jump may target the end of the instructions.
+    if let Some(label) = labels.iter().position(|&target| target == insts.len()) {
+        result.push_str(&format!("L{}:\n", label));
+    }
+}
+
+// Collects the sorted, deduplicated targets of every jump in `insts`.
+fn jump_labels(insts: &[u8]) -> Vec<usize> {
+    let mut targets = vec![];
+    let mut i = 0;
+    while i < insts.len() {
+        let def = match lookup_definition(insts[i]) {
+            Some(def) => def,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let width: usize = def.widths.iter().sum();
+        if i + 1 + width > insts.len() {
+            break;
+        }
+        match OpCode::from_byte(insts[i]) {
+            Some(OpCode::Jump) | Some(OpCode::JumpIfNotTruthy) => {
+                targets.push(read_uint16(insts, i + 1) as usize);
+            }
+            _ => {}
+        }
+        i += 1 + width;
+    }
+    targets.sort_unstable();
+    targets.dedup();
+    targets
+}
+
+// The operands of the instruction whose operands start at `start`, and their width. `None`
+// when the instructions end before its last operand.
+fn read_operands(def: &Definition, insts: &[u8], start: usize) -> Option<(Vec<usize>, usize)> {
+    let width: usize = def.widths.iter().sum();
+    if start + width > insts.len() {
+        return None;
+    }
     let mut offset = 0;
     let mut operands = Vec::with_capacity(def.widths.len());
     for width in &def.widths {
@@ -156,7 +413,7 @@ fn read_operands(def: &Definition, insts: &[u8], start: usize) -> (Vec<usize>, u
         }
         offset += width;
     }
-    (operands, offset)
+    Some((operands, offset))
 }
 
 pub fn read_uint16(insts: &[u8], start: usize) -> u16 {
@@ -181,12 +438,30 @@ pub fn make_u16_u8(op_code: OpCode, first: u16, second: u8) -> Instructions {
     vec![op_code as u8, bytes[0], bytes[1], second]
 }
 
+// Appends `instruction` to `insts`, recording that it comes from `line`. Returns the offset
+// of the instruction.
+//
+// ```
+// emit(&mut insts, &mut lines, make_u16(OpCode::Constant, 0), 3);
+// ```
+pub fn emit(
+    insts: &mut Instructions,
+    lines: &mut LineTable,
+    instruction: Instructions,
+    line: usize,
+) -> usize {
+    let offset = insts.len();
+    lines.add(offset, line);
+    insts.extend(instruction);
+    offset
+}
+
 pub struct Definition {
     pub name: String,
     pub widths: Vec<usize>,
 }
 
-fn lookup_definition(byte: u8) -> Option<Definition> {
+pub fn lookup_definition(byte: u8) -> Option<Definition> {
     OpCode::from_byte(byte).map(|op_code| match op_code {
         OpCode::Constant => Definition {
             name: "OpConstant".to_string(),
@@ -304,6 +579,42 @@ fn lookup_definition(byte: u8) -> Option<Definition> {
             name: "OpGetFree".to_string(),
             widths: vec![1],
         },
+        OpCode::LessThan => Definition {
+            name: "OpLessThan".to_string(),
+            widths: vec![],
+        },
+        OpCode::LessEqual => Definition {
+            name: "OpLessEqual".to_string(),
+            widths: vec![],
+        },
+        OpCode::GreaterEqual => Definition {
+            name: "OpGreaterEqual".to_string(),
+            widths: vec![],
+        },
+        OpCode::Modulo => Definition {
+            name: "OpModulo".to_string(),
+            widths: vec![],
+        },
+        OpCode::Power => Definition {
+            name: "OpPower".to_string(),
+            widths: vec![],
+        },
+        OpCode::And => Definition {
+            name: "OpAnd".to_string(),
+            widths: vec![],
+        },
+        OpCode::Or => Definition {
+            name: "OpOr".to_string(),
+            widths: vec![],
+        },
+        OpCode::Print => Definition {
+            name: "OpPrint".to_string(),
+            widths: vec![],
+        },
+        OpCode::Read => Definition {
+            name: "OpRead".to_string(),
+            widths: vec![],
+        },
     })
 }
 
@@ -331,7 +642,8 @@ impl fmt::Display for Constant {
         match self {
             Constant::Integer(value) => write!(f, "{}", value),
             Constant::Float(value) => write!(f, "{}", value),
-            Constant::String(value) => write!(f, "\"{}\"", value),
+            // Escaped, to keep a listing one instruction per line.
+            Constant::String(value) => write!(f, "{:?}", value),
             Constant::CompiledFunction(cf) => write!(f, "{}", cf),
         }
     }
@@ -342,6 +654,7 @@ pub struct CompiledFunction {
     pub instructions: Instructions,
     pub num_locals: u8,
     pub num_parameters: u8,
+    pub lines: LineTable,
 }
 
 impl fmt::Display for CompiledFunction {
@@ -351,14 +664,17 @@ impl fmt::Display for CompiledFunction {
             "compiled function ({} locals, {} parameters): {}",
             self.num_locals,
             self.num_parameters,
-            print_instructions(&self.instructions)
+            print_instructions_with_lines(&self.instructions, &self.lines)
         )
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::code::{make, make_u16, make_u16_u8, make_u8, print_instructions, OpCode};
+    use crate::code::{
+        disassemble, emit, make, make_u16, make_u16_u8, make_u8, print_instructions,
+        print_instructions_with_lines, Bytecode, CompiledFunction, Constant, LineTable, OpCode,
+    };
 
     #[test]
     fn test_print_instructions() {
@@ -380,4 +696,133 @@ mod tests {
 
         assert_eq!(&print_instructions(&insts), expected);
     }
+
+    #[test]
+    fn test_print_unknown_opcode() {
+        let insts = [make(OpCode::True), vec![255], make(OpCode::Pop)].concat();
+        let expected = "0000 OpTrue
+0001 ERROR: unknown opcode 255
+0002 OpPop";
+
+        assert_eq!(&print_instructions(&insts), expected);
+    }
+
+    #[test]
+    fn test_print_truncated() {
+        let insts = [make(OpCode::True), vec![OpCode::Constant as u8, 0]].concat();
+        let expected = "0000 OpTrue
+0001 ERROR: truncated OpConstant";
+
+        assert_eq!(&print_instructions(&insts), expected);
+        assert!(disassemble(&Bytecode::new(insts, vec![]))
+            .ends_with("0001 ERROR: truncated OpConstant\n"));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let func = CompiledFunction {
+            instructions: [make_u8(OpCode::GetLocal, 0), make(OpCode::ReturnValue)].concat(),
+            num_locals: 1,
+            num_parameters: 1,
+            lines: LineTable::new(),
+        };
+        let bytecode = Bytecode::new(
+            [
+                make(OpCode::True),
+                make_u16(OpCode::JumpIfNotTruthy, 10),
+                make_u16(OpCode::Constant, 0),
+                make_u16(OpCode::Jump, 11),
+                make(OpCode::Null),
+                make(OpCode::Pop),
+                make_u16_u8(OpCode::Closure, 2, 0),
+                make(OpCode::Pop),
+            ]
+            .concat(),
+            vec![
+                Constant::Integer(10),
+                Constant::String("foo".to_string()),
+                Constant::CompiledFunction(func),
+            ],
+        );
+        let expected = "constants:
+0000 INTEGER 10
+0001 STRING \"foo\"
+0002 COMPILED_FUNCTION <fn 2>
+
+main:
+0000 OpTrue
+0001 OpJumpIfNotTruthy L0
+0004 OpConstant 0 ; 10
+0007 OpJump L1
+L0:
+0010 OpNull
+L1:
+0011 OpPop
+0012 OpClosure 2 0 ; <fn 2>
+0016 OpPop
+
+<fn 2> (1 locals, 1 parameters):
+0000 OpGetLocal 0
+0002 OpReturnValue
+";
+
+        assert_eq!(disassemble(&bytecode), expected);
+    }
+
+    #[test]
+    fn test_line_table() {
+        let mut insts = vec![];
+        let mut lines = LineTable::new();
+        emit(&mut insts, &mut lines, make_u16(OpCode::Constant, 0), 1);
+        emit(&mut insts, &mut lines, make_u16(OpCode::Constant, 1), 1);
+        emit(&mut insts, &mut lines, make(OpCode::Add), 1);
+        emit(&mut insts, &mut lines, make(OpCode::Pop), 2);
+        emit(&mut insts, &mut lines, make(OpCode::Null), 4);
+
+        assert_eq!(lines.line_at(0), Some(1));
+        assert_eq!(lines.line_at(6), Some(1));
+        assert_eq!(lines.line_at(7), Some(2));
+        assert_eq!(lines.line_at(100), Some(4));
+        assert_eq!(LineTable::new().line_at(0), None);
+
+        let expected = "0000 OpConstant 0 [line 1]
+0003 OpConstant 1 [line 1]
+0006 OpAdd [line 1]
+0007 OpPop [line 2]
+0008 OpNull [line 4]";
+        assert_eq!(&print_instructions_with_lines(&insts, &lines), expected);
+    }
+
+    #[test]
+    fn test_operator_opcodes() {
+        let insts = [
+            make(OpCode::LessThan),
+            make(OpCode::LessEqual),
+            make(OpCode::GreaterEqual),
+            make(OpCode::Modulo),
+            make(OpCode::Power),
+            make(OpCode::And),
+            make(OpCode::Or),
+            make(OpCode::Print),
+            make(OpCode::Read),
+        ]
+        .concat();
+        let expected = "0000 OpLessThan
+0001 OpLessEqual
+0002 OpGreaterEqual
+0003 OpModulo
+0004 OpPower
+0005 OpAnd
+0006 OpOr
+0007 OpPrint
+0008 OpRead";
+
+        assert_eq!(&print_instructions(&insts), expected);
+        // Existing opcodes keep their byte values.
+        assert_eq!(OpCode::GetFree as u8, 28);
+        assert_eq!(OpCode::from_byte(29), Some(OpCode::LessThan));
+        assert_eq!(OpCode::from_byte(35), Some(OpCode::Or));
+        assert_eq!(OpCode::from_byte(37), Some(OpCode::Read));
+        assert_eq!(OpCode::from_byte(38), None);
+    }
 }
@@ -0,0 +1,586 @@
+use crate::ast::{BlockStatement, Expression, Infix, Prefix, Program, Statement};
+use crate::code::{self, Bytecode, CompiledFunction, Constant, Instructions, LineTable, OpCode};
+use crate::object::builtin::BUILTINS;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+// Compiles a program to bytecode, for `yapc disasm`.
+//
+// Variables of the program are globals, and parameters and variables of functions are
+// locals. Functions capture the locals of enclosing functions they use as free variables.
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    UndefinedVariable(String),
+    // Builtins and free variables can't be assigned to.
+    Assignment(String),
+    // An operand doesn't fit in its instruction, like the 256th argument of a call.
+    TooMany(&'static str),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::UndefinedVariable(name) => write!(f, "undefined variable: {}", name),
+            CompileError::Assignment(name) => write!(
+                f,
+                "cannot assign to `{}`, a builtin or a variable of an enclosing function",
+                name
+            ),
+            CompileError::TooMany(what) => write!(f, "too many {}", what),
+        }
+    }
+}
+
+pub fn compile(program: &Program) -> Result<Bytecode> {
+    let mut compiler = Compiler {
+        scopes: vec![Scope::default()],
+        constants: vec![],
+        line: None,
+    };
+    compiler.statements(&program.statements, &program.lines)?;
+    let scope = compiler.scopes.pop().unwrap();
+    let mut bytecode = Bytecode::new(scope.instructions, compiler.constants);
+    bytecode.lines = scope.lines;
+    Ok(bytecode)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SymbolScope {
+    Global,
+    Local,
+    Builtin,
+    Free,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Symbol {
+    scope: SymbolScope,
+    index: usize,
+}
+
+// The program, or a function being compiled.
+#[derive(Default)]
+struct Scope {
+    instructions: Instructions,
+    lines: LineTable,
+    symbols: HashMap<String, Symbol>,
+    definitions: usize,
+    // The symbols of the enclosing scope captured by this function, by free index.
+    free: Vec<Symbol>,
+}
+
+struct Compiler {
+    scopes: Vec<Scope>,
+    constants: Vec<Constant>,
+    // Line of the statement being compiled.
+    line: Option<usize>,
+}
+
+impl Compiler {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, instruction: Instructions) -> usize {
+        let line = self.line;
+        let scope = self.scope();
+        match line {
+            Some(line) => code::emit(&mut scope.instructions, &mut scope.lines, instruction, line),
+            None => {
+                let offset = scope.instructions.len();
+                scope.instructions.extend(instruction);
+                offset
+            }
+        }
+    }
+
+    fn position(&mut self) -> usize {
+        self.scope().instructions.len()
+    }
+
+    // Points the jump at `jump` to the current position.
+    fn patch_jump(&mut self, jump: usize) -> Result<()> {
+        let target =
+            u16::try_from(self.position()).map_err(|_| CompileError::TooMany("instructions"))?;
+        let bytes = target.to_be_bytes();
+        let instructions = &mut self.scope().instructions;
+        instructions[jump + 1] = bytes[0];
+        instructions[jump + 2] = bytes[1];
+        Ok(())
+    }
+
+    fn add_constant(&mut self, constant: Constant) -> Result<()> {
+        let index =
+            u16::try_from(self.constants.len()).map_err(|_| CompileError::TooMany("constants"))?;
+        self.constants.push(constant);
+        self.emit(code::make_u16(OpCode::Constant, index));
+        Ok(())
+    }
+
+    // Declaring a variable again in the same scope reuses it.
+    fn define(&mut self, name: &str) -> Symbol {
+        let global = self.scopes.len() == 1;
+        let scope = self.scope();
+        if let Some(symbol) = scope.symbols.get(name) {
+            return *symbol;
+        }
+        let symbol = Symbol {
+            scope: if global {
+                SymbolScope::Global
+            } else {
+                SymbolScope::Local
+            },
+            index: scope.definitions,
+        };
+        scope.definitions += 1;
+        scope.symbols.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    // Looks `name` up from the scope at `level` outwards. Locals of enclosing functions become
+    // free variables of the functions in between.
+    fn resolve(&mut self, name: &str, level: usize) -> Option<Symbol> {
+        if let Some(symbol) = self.scopes[level].symbols.get(name) {
+            return Some(*symbol);
+        }
+        if level == 0 {
+            let index = BUILTINS.iter().position(|builtin| builtin.name == name)?;
+            return Some(Symbol {
+                scope: SymbolScope::Builtin,
+                index,
+            });
+        }
+        let outer = self.resolve(name, level - 1)?;
+        if matches!(outer.scope, SymbolScope::Global | SymbolScope::Builtin) {
+            return Some(outer);
+        }
+        let scope = &mut self.scopes[level];
+        scope.free.push(outer);
+        let symbol = Symbol {
+            scope: SymbolScope::Free,
+            index: scope.free.len() - 1,
+        };
+        scope.symbols.insert(name.to_string(), symbol);
+        Some(symbol)
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<Symbol> {
+        self.resolve(name, self.scopes.len() - 1)
+            .ok_or_else(|| CompileError::UndefinedVariable(name.to_string()))
+    }
+
+    fn load(&mut self, symbol: Symbol) -> Result<()> {
+        let instruction = match symbol.scope {
+            SymbolScope::Global => code::make_u16(OpCode::GetGlobal, global_index(symbol)?),
+            SymbolScope::Local => code::make_u8(OpCode::GetLocal, local_index(symbol)?),
+            SymbolScope::Builtin => code::make_u8(OpCode::GetBuiltin, symbol.index as u8),
+            SymbolScope::Free => code::make_u8(OpCode::GetFree, local_index(symbol)?),
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn store(&mut self, name: &str, symbol: Symbol) -> Result<()> {
+        let instruction = match symbol.scope {
+            SymbolScope::Global => code::make_u16(OpCode::SetGlobal, global_index(symbol)?),
+            SymbolScope::Local => code::make_u8(OpCode::SetLocal, local_index(symbol)?),
+            SymbolScope::Builtin | SymbolScope::Free => {
+                return Err(CompileError::Assignment(name.to_string()))
+            }
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn statements(&mut self, statements: &[Statement], lines: &[usize]) -> Result<()> {
+        for (i, statement) in statements.iter().enumerate() {
+            self.line = lines.get(i).copied();
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Let(name, value) => {
+                // Defined first, so functions can call themselves.
+                let symbol = self.define(name);
+                self.expression(value)?;
+                self.store(name, symbol)
+            }
+            Statement::Int(name) | Statement::Float(name) | Statement::String(name) => {
+                let symbol = self.define(name);
+                self.add_constant(match statement {
+                    Statement::Int(_) => Constant::Integer(0),
+                    Statement::Float(_) => Constant::Float(0.0),
+                    _ => Constant::String(String::new()),
+                })?;
+                self.store(name, symbol)
+            }
+            Statement::Return(Some(value)) => {
+                self.expression(value)?;
+                self.emit(code::make(OpCode::ReturnValue));
+                Ok(())
+            }
+            Statement::Return(None) => {
+                self.emit(code::make(OpCode::Return));
+                Ok(())
+            }
+            Statement::Print(value) => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.add_constant(Constant::String("\n".to_string()))?,
+                }
+                self.emit(code::make(OpCode::Print));
+                Ok(())
+            }
+            // The current value tells `OpRead` the type to read.
+            Statement::Read(name) => {
+                let symbol = self.lookup(name)?;
+                self.load(symbol)?;
+                self.emit(code::make(OpCode::Read));
+                self.store(name, symbol)
+            }
+            Statement::Expression(exp) => {
+                self.expression(exp)?;
+                self.emit(code::make(OpCode::Pop));
+                Ok(())
+            }
+        }
+    }
+
+    // Leaves the value of the block on the stack: the value of its last statement when it is
+    // an expression, null otherwise.
+    fn block_value(&mut self, block: &BlockStatement) -> Result<()> {
+        let line = self.line;
+        let last = block.statements.len().saturating_sub(1);
+        self.statements(&block.statements[..last], &block.lines)?;
+        match block.statements.last() {
+            Some(Statement::Expression(exp)) => {
+                self.line = block.line(last);
+                self.expression(exp)?;
+            }
+            Some(statement) => {
+                self.line = block.line(last);
+                self.statement(statement)?;
+                self.emit(code::make(OpCode::Null));
+            }
+            None => {
+                self.emit(code::make(OpCode::Null));
+            }
+        }
+        self.line = line;
+        Ok(())
+    }
+
+    fn expression(&mut self, exp: &Expression) -> Result<()> {
+        match exp {
+            Expression::Identifier(name) => match self.lookup(name) {
+                Ok(symbol) => self.load(symbol)?,
+                Err(_) if name == "null" => {
+                    self.emit(code::make(OpCode::Null));
+                }
+                Err(err) => return Err(err),
+            },
+            Expression::IntegerLiteral(value) => self.add_constant(Constant::Integer(*value))?,
+            Expression::FloatLiteral(value) => self.add_constant(Constant::Float(*value))?,
+            Expression::StringLiteral(value) => {
+                self.add_constant(Constant::String(value.clone()))?
+            }
+            Expression::Boolean(value) => {
+                self.emit(code::make(if *value {
+                    OpCode::True
+                } else {
+                    OpCode::False
+                }));
+            }
+            // The default value of each type.
+            Expression::Int => self.add_constant(Constant::Integer(0))?,
+            Expression::Float => self.add_constant(Constant::Float(0.0))?,
+            Expression::String => self.add_constant(Constant::String(String::new()))?,
+            Expression::Array(values) => {
+                for value in values {
+                    self.expression(value)?;
+                }
+                let len = u16::try_from(values.len())
+                    .map_err(|_| CompileError::TooMany("array elements"))?;
+                self.emit(code::make_u16(OpCode::Array, len));
+            }
+            Expression::Hash(pairs) => {
+                for (key, value) in pairs {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+                let len = u16::try_from(pairs.len() * 2)
+                    .map_err(|_| CompileError::TooMany("hash entries"))?;
+                self.emit(code::make_u16(OpCode::Hash, len));
+            }
+            Expression::Index(left, index) => {
+                self.expression(left)?;
+                self.expression(index)?;
+                self.emit(code::make(OpCode::Index));
+            }
+            Expression::Prefix(prefix, right) => {
+                self.expression(right)?;
+                self.emit(code::make(match prefix {
+                    Prefix::Bang => OpCode::Bang,
+                    Prefix::Minus => OpCode::Minus,
+                }));
+            }
+            Expression::Infix(Infix::Assign, left, right) => self.assign(left, right)?,
+            Expression::Infix(infix, left, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.emit(code::make(match infix {
+                    Infix::Eq => OpCode::Equal,
+                    Infix::NotEq => OpCode::NotEqual,
+                    Infix::Lt => OpCode::LessThan,
+                    Infix::Gt => OpCode::GreaterThan,
+                    Infix::Plus => OpCode::Add,
+                    Infix::Minus => OpCode::Sub,
+                    Infix::Asterisk => OpCode::Mul,
+                    Infix::Slash => OpCode::Div,
+                    Infix::Assign => unreachable!(),
+                }));
+            }
+            Expression::Assign(left, right) => self.assign(left, right)?,
+            Expression::If(condition, consequence, alternative) => {
+                self.expression(condition)?;
+                let jump_if_not = self.emit(code::make_u16(OpCode::JumpIfNotTruthy, 0));
+                self.block_value(consequence)?;
+                let jump = self.emit(code::make_u16(OpCode::Jump, 0));
+                self.patch_jump(jump_if_not)?;
+                match alternative {
+                    Some(alternative) => self.block_value(alternative)?,
+                    None => {
+                        self.emit(code::make(OpCode::Null));
+                    }
+                }
+                self.patch_jump(jump)?;
+            }
+            Expression::While(condition, body) => {
+                let start = u16::try_from(self.position())
+                    .map_err(|_| CompileError::TooMany("instructions"))?;
+                self.expression(condition)?;
+                let jump_if_not = self.emit(code::make_u16(OpCode::JumpIfNotTruthy, 0));
+                let line = self.line;
+                self.statements(&body.statements, &body.lines)?;
+                self.line = line;
+                self.emit(code::make_u16(OpCode::Jump, start));
+                self.patch_jump(jump_if_not)?;
+                self.emit(code::make(OpCode::Null));
+            }
+            Expression::FunctionLiteral(parameters, body) => self.function(parameters, body)?,
+            Expression::Call(function, arguments, _) => {
+                self.expression(function)?;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                let len = u8::try_from(arguments.len())
+                    .map_err(|_| CompileError::TooMany("arguments"))?;
+                self.emit(code::make_u8(OpCode::Call, len));
+            }
+        }
+        Ok(())
+    }
+
+    // Stores the value and leaves it on the stack, as the value of the assignment.
+    fn assign(&mut self, left: &Expression, value: &Expression) -> Result<()> {
+        let name = match left {
+            Expression::Identifier(name) => name,
+            _ => return Err(CompileError::Assignment(left.to_string())),
+        };
+        let symbol = self.lookup(name)?;
+        self.expression(value)?;
+        self.store(name, symbol)?;
+        self.load(symbol)
+    }
+
+    fn function(&mut self, parameters: &[String], body: &BlockStatement) -> Result<()> {
+        self.scopes.push(Scope::default());
+        for parameter in parameters {
+            self.define(parameter);
+        }
+        let line = self.line;
+        self.block_value(body)?;
+        self.line = line;
+        self.emit(code::make(OpCode::ReturnValue));
+        let scope = self.scopes.pop().unwrap();
+
+        for symbol in &scope.free {
+            self.load(*symbol)?;
+        }
+        let function = CompiledFunction {
+            instructions: scope.instructions,
+            num_locals: u8::try_from(scope.definitions)
+                .map_err(|_| CompileError::TooMany("local variables"))?,
+            num_parameters: u8::try_from(parameters.len())
+                .map_err(|_| CompileError::TooMany("parameters"))?,
+            lines: scope.lines,
+        };
+        let index =
+            u16::try_from(self.constants.len()).map_err(|_| CompileError::TooMany("constants"))?;
+        self.constants.push(Constant::CompiledFunction(function));
+        let free =
+            u8::try_from(scope.free.len()).map_err(|_| CompileError::TooMany("free variables"))?;
+        self.emit(code::make_u16_u8(OpCode::Closure, index, free));
+        Ok(())
+    }
+}
+
+fn global_index(symbol: Symbol) -> Result<u16> {
+    u16::try_from(symbol.index).map_err(|_| CompileError::TooMany("global variables"))
+}
+
+fn local_index(symbol: Symbol) -> Result<u8> {
+    u8::try_from(symbol.index).map_err(|_| CompileError::TooMany("local variables"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, CompileError};
+    use crate::code::disassemble;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn listing(input: &str) -> String {
+        let program = Parser::new(Lexer::new(input.to_string())).parse_program();
+        disassemble(&compile(&program).unwrap())
+    }
+
+    #[test]
+    fn statements() {
+        let input = "int x;
+leia x;
+se (x > 1) { imprima(x * 2); } senao { imprima; }";
+        let expected = "constants:
+0000 INTEGER 0
+0001 INTEGER 1
+0002 INTEGER 2
+0003 STRING \"\\n\"
+
+main:
+0000 OpConstant 0 ; 0 [line 1]
+0003 OpSetGlobal 0 [line 1]
+0006 OpGetGlobal 0 [line 2]
+0009 OpRead [line 2]
+0010 OpSetGlobal 0 [line 2]
+0013 OpGetGlobal 0 [line 3]
+0016 OpConstant 1 ; 1 [line 3]
+0019 OpGreaterThan [line 3]
+0020 OpJumpIfNotTruthy L0 [line 3]
+0023 OpGetGlobal 0 [line 3]
+0026 OpConstant 2 ; 2 [line 3]
+0029 OpMul [line 3]
+0030 OpPrint [line 3]
+0031 OpNull [line 3]
+0032 OpJump L1 [line 3]
+L0:
+0035 OpConstant 3 ; \"\\n\" [line 3]
+0038 OpPrint [line 3]
+0039 OpNull [line 3]
+L1:
+0040 OpPop [line 3]
+";
+        assert_eq!(listing(input), expected);
+    }
+
+    #[test]
+    fn loops_and_assignments() {
+        let input = "int i;
+enquanto (i < 3) {
+    i = i + 1;
+}";
+        let expected = "main:
+0000 OpConstant 0 ; 0 [line 1]
+0003 OpSetGlobal 0 [line 1]
+L0:
+0006 OpGetGlobal 0 [line 2]
+0009 OpConstant 1 ; 3 [line 2]
+0012 OpLessThan [line 2]
+0013 OpJumpIfNotTruthy L1 [line 2]
+0016 OpGetGlobal 0 [line 3]
+0019 OpConstant 2 ; 1 [line 3]
+0022 OpAdd [line 3]
+0023 OpSetGlobal 0 [line 3]
+0026 OpGetGlobal 0 [line 3]
+0029 OpPop [line 3]
+0030 OpJump L0 [line 2]
+L1:
+0033 OpNull [line 2]
+0034 OpPop [line 2]
+";
+        assert!(listing(input).ends_with(expected), "{}", listing(input));
+    }
+
+    #[test]
+    fn functions() {
+        let input = "let soma = funcao(a) {
+    let f = funcao(b) { retorne a + b + len(\"x\"); };
+    retorne f;
+};
+soma(1)(2);";
+        let expected = "constants:
+0000 STRING \"x\"
+0001 COMPILED_FUNCTION <fn 1>
+0002 COMPILED_FUNCTION <fn 2>
+0003 INTEGER 1
+0004 INTEGER 2
+
+main:
+0000 OpClosure 2 0 ; <fn 2> [line 1]
+0004 OpSetGlobal 0 [line 1]
+0007 OpGetGlobal 0 [line 5]
+0010 OpConstant 3 ; 1 [line 5]
+0013 OpCall 1 [line 5]
+0015 OpConstant 4 ; 2 [line 5]
+0018 OpCall 1 [line 5]
+0020 OpPop [line 5]
+
+<fn 1> (1 locals, 1 parameters):
+0000 OpGetFree 0 [line 2]
+0002 OpGetLocal 0 [line 2]
+0004 OpAdd [line 2]
+0005 OpGetBuiltin 0 [line 2]
+0007 OpConstant 0 ; \"x\" [line 2]
+0010 OpCall 1 [line 2]
+0012 OpAdd [line 2]
+0013 OpReturnValue [line 2]
+0014 OpNull [line 2]
+0015 OpReturnValue [line 2]
+
+<fn 2> (2 locals, 1 parameters):
+0000 OpGetLocal 0 [line 2]
+0002 OpClosure 1 1 ; <fn 1> [line 2]
+0006 OpSetLocal 1 [line 2]
+0008 OpGetLocal 1 [line 3]
+0010 OpReturnValue [line 3]
+0011 OpNull [line 3]
+0012 OpReturnValue [line 1]
+";
+        assert_eq!(listing(input), expected);
+    }
+
+    #[test]
+    fn errors() {
+        let compile_error = |input: &str| {
+            let program = Parser::new(Lexer::new(input.to_string())).parse_program();
+            match compile(&program) {
+                Ok(_) => panic!("compiled `{}`", input),
+                Err(err) => err,
+            }
+        };
+        assert_eq!(
+            compile_error("imprima(x);"),
+            CompileError::UndefinedVariable("x".to_string())
+        );
+        assert_eq!(
+            compile_error("let f = funcao(a) { let g = funcao() { a = 1; }; };").to_string(),
+            "cannot assign to `a`, a builtin or a variable of an enclosing function"
+        );
+    }
+}
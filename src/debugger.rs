@@ -0,0 +1,390 @@
+// Step-through debugger. It stops before a statement when stepping or on a breakpoint, shows
+// the line and the watch expressions, and reads commands until told to go on.
+use crate::ast::{Expression, Statement};
+use crate::evaluator::{Evaluator, Hook};
+use crate::formatter;
+use crate::lexer::Lexer;
+use crate::object::{Environment, EvalError};
+use crate::parser::Parser;
+use crate::repl::portugol_value;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Read, Write};
+use std::rc::Rc;
+
+const PROMPT: &str = "(dbg) ";
+
+const HELP: &str = "comandos:
+    entrar, s           executa até o próximo comando, entrando em funções
+    proximo, n          executa até o próximo comando desta função
+    terminar, f         executa até sair desta função
+    continuar, c        executa até um ponto de parada
+    pare, b <linha>     cria um ponto de parada na linha
+    remova, d <linha>   remove o ponto de parada da linha
+    vars, v             mostra as variáveis locais e as externas
+    mostre, p <expr>    mostra o valor de uma expressão
+    observe, w <expr>   mostra o valor da expressão a cada parada
+    fim, q              encerra o programa
+    ajuda, h            mostra esta mensagem";
+
+// Where the program runs until it stops again.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    Continue,
+    StepInto,
+    // Stops in the function running at this depth, or after it returns.
+    StepOver(usize),
+    StepOut(usize),
+}
+
+pub struct Debugger<'a> {
+    commands: Rc<RefCell<dyn BufRead + 'a>>,
+    output: &'a mut dyn Write,
+    // The lines of the program, to show where it stopped.
+    source: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+    watches: Vec<(String, Expression)>,
+    mode: Mode,
+    // Number of functions running.
+    depth: usize,
+}
+
+impl<'a> Debugger<'a> {
+    // Stops before the first statement.
+    pub fn new(
+        source: &str,
+        commands: Rc<RefCell<dyn BufRead + 'a>>,
+        output: &'a mut dyn Write,
+    ) -> Self {
+        Debugger {
+            commands,
+            output,
+            source: source.lines().map(str::to_string).collect(),
+            breakpoints: BTreeSet::new(),
+            watches: vec![],
+            mode: Mode::StepInto,
+            depth: 0,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, line: usize) {
+        self.breakpoints.insert(line);
+    }
+
+    fn should_stop(&self, line: Option<usize>) -> bool {
+        let stepped = match self.mode {
+            Mode::Continue => false,
+            Mode::StepInto => true,
+            Mode::StepOver(depth) => self.depth <= depth,
+            Mode::StepOut(depth) => self.depth < depth,
+        };
+        stepped || line.is_some_and(|line| self.breakpoints.contains(&line))
+    }
+
+    // Shows where the program is, then runs commands until one resumes it.
+    fn stop(
+        &mut self,
+        evaluator: &mut Evaluator,
+        statement: &Statement,
+        line: Option<usize>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> io::Result<Result<(), EvalError>> {
+        let source = &self.source;
+        match line.and_then(|line| Some((line, source.get(line - 1)?))) {
+            Some((line, text)) => writeln!(self.output, "linha {}: {}", line, text.trim())?,
+            None => writeln!(self.output, "{}", formatter::statement_line(statement))?,
+        }
+        for (text, exp) in &self.watches {
+            let value = evaluator.eval_expression(exp, Rc::clone(env));
+            writeln!(self.output, "    {} = {}", text, value_text(value))?;
+        }
+        loop {
+            write!(self.output, "{}", PROMPT)?;
+            self.output.flush()?;
+            let mut command = String::new();
+            // Without more commands, the program runs to the end.
+            if self.commands.borrow_mut().read_line(&mut command)? == 0 {
+                writeln!(self.output)?;
+                self.mode = Mode::Continue;
+                return Ok(Ok(()));
+            }
+            let command = command.trim();
+            let (name, argument) = match command.split_once(char::is_whitespace) {
+                Some((name, argument)) => (name, argument.trim()),
+                None => (command, ""),
+            };
+            self.mode = match name {
+                "entrar" | "s" => Mode::StepInto,
+                "proximo" | "n" => Mode::StepOver(self.depth),
+                "terminar" | "f" => Mode::StepOut(self.depth),
+                "continuar" | "c" => Mode::Continue,
+                "fim" | "q" => return Ok(Err(EvalError::Stopped)),
+                _ => {
+                    self.command(name, argument, evaluator, env)?;
+                    continue;
+                }
+            };
+            return Ok(Ok(()));
+        }
+    }
+
+    // Runs a command that doesn't resume the program.
+    fn command(
+        &mut self,
+        name: &str,
+        argument: &str,
+        evaluator: &mut Evaluator,
+        env: &Rc<RefCell<Environment>>,
+    ) -> io::Result<()> {
+        match name {
+            "pare" | "b" | "remova" | "d" => match argument.parse::<usize>() {
+                Ok(line) if name == "pare" || name == "b" => {
+                    self.breakpoints.insert(line);
+                    writeln!(self.output, "ponto de parada na linha {}", line)
+                }
+                Ok(line) if self.breakpoints.remove(&line) => {
+                    writeln!(self.output, "ponto de parada da linha {} removido", line)
+                }
+                Ok(line) => writeln!(self.output, "não há ponto de parada na linha {}", line),
+                Err(_) => writeln!(self.output, "ERRO: linha inválida `{}`", argument),
+            },
+            "vars" | "v" => self.variables(env),
+            "mostre" | "p" | "observe" | "w" => {
+                let exp = match parse_expression(argument) {
+                    Some(exp) => exp,
+                    None => {
+                        return writeln!(self.output, "ERRO: expressão inválida `{}`", argument)
+                    }
+                };
+                let value = evaluator.eval_expression(&exp, Rc::clone(env));
+                writeln!(self.output, "    {} = {}", argument, value_text(value))?;
+                if name == "observe" || name == "w" {
+                    self.watches.push((argument.to_string(), exp));
+                }
+                Ok(())
+            }
+            "ajuda" | "h" => writeln!(self.output, "{}", HELP),
+            "" => Ok(()),
+            _ => writeln!(
+                self.output,
+                "ERRO: comando desconhecido `{}`, veja ajuda",
+                name
+            ),
+        }
+    }
+
+    // The environment chain, from the function running to the program.
+    fn variables(&mut self, env: &Rc<RefCell<Environment>>) -> io::Result<()> {
+        let mut env = Some(Rc::clone(env));
+        let mut level = 0;
+        while let Some(current) = env {
+            let title = match level {
+                0 => "locais".to_string(),
+                1 => "externas".to_string(),
+                _ => format!("externas ({})", level),
+            };
+            writeln!(self.output, "{}:", title)?;
+            for (name, value) in current.borrow().variables() {
+                writeln!(self.output, "    {} = {}", name, portugol_value(value))?;
+            }
+            env = current.borrow().outer();
+            level += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Hook for Debugger<'_> {
+    fn statement(
+        &mut self,
+        evaluator: &mut Evaluator,
+        statement: &Statement,
+        line: Option<usize>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), EvalError> {
+        if !self.should_stop(line) {
+            return Ok(());
+        }
+        self.stop(evaluator, statement, line, env)
+            .map_err(EvalError::Io)?
+    }
+
+    fn enter_function(&mut self) {
+        self.depth += 1;
+    }
+
+    fn leave_function(&mut self) {
+        self.depth -= 1;
+    }
+}
+
+fn value_text(value: Result<crate::object::Object, EvalError>) -> String {
+    match value {
+        Ok(value) => portugol_value(&value),
+        Err(err) => format!("ERRO: {}", err),
+    }
+}
+
+fn parse_expression(text: &str) -> Option<Expression> {
+    let mut parser = Parser::new(Lexer::new(format!("{};", text)));
+    let program = parser.parse_program();
+    match program.statements.as_slice() {
+        [Statement::Expression(exp)] if parser.errors().is_empty() => Some(exp.clone()),
+        _ => None,
+    }
+}
+
+// `leia` input read from the same place as the debugger commands, like stdin. It reads a line
+// at a time, so the lines after it are left for the debugger.
+pub struct SharedInput<'a>(pub Rc<RefCell<dyn BufRead + 'a>>);
+
+impl Read for SharedInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut input = self.0.borrow_mut();
+        let available = input.fill_buf()?;
+        let len = available
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(available.len(), |i| i + 1)
+            .min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        input.consume(len);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Debugger, SharedInput};
+    use crate::evaluator::Evaluator;
+    use crate::lexer::Lexer;
+    use crate::object::{Environment, EvalError};
+    use crate::parser::Parser;
+    use std::cell::RefCell;
+    use std::io::BufReader;
+    use std::rc::Rc;
+
+    const PROGRAM: &str = "int total;
+let soma = funcao(a, b) {
+    let s = a + b;
+    retorne s;
+};
+total = soma(1, 2);
+total = soma(total, 3);
+imprima(total);";
+
+    // What the debugger wrote, the result of the program and its traceback.
+    fn debug(
+        source: &str,
+        commands: &str,
+        breakpoints: &[usize],
+    ) -> (String, Result<(), EvalError>, Option<String>) {
+        let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+        let commands = Rc::new(RefCell::new(commands.as_bytes()));
+        let mut transcript = vec![];
+        let mut program_output = vec![];
+        let mut input = BufReader::new(SharedInput(commands.clone()));
+        let (result, traceback) = {
+            let mut debugger = Debugger::new(source, commands, &mut transcript);
+            for line in breakpoints {
+                debugger.add_breakpoint(*line);
+            }
+            let mut evaluator = Evaluator::new(&mut input, &mut program_output);
+            evaluator.set_hook(&mut debugger);
+            let result = evaluator
+                .eval(&program, Rc::new(RefCell::new(Environment::new())))
+                .map(|_| ());
+            (result, evaluator.traceback().map(|t| t.to_string()))
+        };
+        (String::from_utf8(transcript).unwrap(), result, traceback)
+    }
+
+    #[test]
+    fn stepping() {
+        let (transcript, result, _) = debug(PROGRAM, "n\nn\nn\ns\ns\nv\nf\nc\n", &[]);
+        result.unwrap();
+        assert_eq!(
+            transcript,
+            "linha 1: int total;
+(dbg) linha 2: let soma = funcao(a, b) {
+(dbg) linha 6: total = soma(1, 2);
+(dbg) linha 7: total = soma(total, 3);
+(dbg) linha 3: let s = a + b;
+(dbg) linha 4: retorne s;
+(dbg) locais:
+    a = 3
+    b = 3
+    s = 6
+externas:
+    soma = funcao(a, b)
+    total = 3
+(dbg) linha 8: imprima(total);
+(dbg) "
+        );
+    }
+
+    #[test]
+    fn breakpoints_and_watches() {
+        let commands = "w s * 10\nc\np a + b\nb 8\nd 3\nc\nc\n";
+        let (transcript, result, _) = debug(PROGRAM, commands, &[3]);
+        result.unwrap();
+        assert_eq!(
+            transcript,
+            "linha 1: int total;
+(dbg)     s * 10 = ERRO: identifier not found: s
+(dbg) linha 3: let s = a + b;
+    s * 10 = ERRO: identifier not found: s
+(dbg)     a + b = 3
+(dbg) ponto de parada na linha 8
+(dbg) ponto de parada da linha 3 removido
+(dbg) linha 8: imprima(total);
+    s * 10 = ERRO: identifier not found: s
+(dbg) "
+        );
+    }
+
+    #[test]
+    fn input_and_quit() {
+        let source = "int x;\nleia x;\nimprima(x);";
+        // The `leia` input comes between the commands.
+        let (transcript, result, _) = debug(source, "n\nn\n42\np x\nq\n", &[]);
+        assert_eq!(
+            transcript,
+            "linha 1: int x;
+(dbg) linha 2: leia x;
+(dbg) linha 3: imprima(x);
+(dbg)     x = 42
+(dbg) "
+        );
+        assert_eq!(result.unwrap_err().to_string(), "stopped by the debugger");
+
+        let (transcript, _, _) = debug(source, "x\np )\nb dez\nd 5\n", &[]);
+        assert_eq!(
+            transcript,
+            "linha 1: int x;
+(dbg) ERRO: comando desconhecido `x`, veja ajuda
+(dbg) ERRO: expressão inválida `)`
+(dbg) ERRO: linha inválida `dez`
+(dbg) não há ponto de parada na linha 5
+(dbg) \n"
+        );
+    }
+
+    #[test]
+    fn errors_in_expressions() {
+        let source = "let f = funcao(n) {
+    retorne 1 / n;
+};
+int x;
+imprima(x);
+imprima(1 / x);";
+        // The error of `f(0)` is not where the program fails.
+        let (transcript, result, traceback) = debug(source, "c\np f(0)\nc\n", &[6]);
+        assert!(transcript.contains("f(0) = ERRO: division by zero"));
+        assert_eq!(result.unwrap_err().to_string(), "division by zero");
+        assert_eq!(
+            traceback.unwrap(),
+            "Rastreamento (chamada mais recente por último):\n  linha 6, em <programa>\n"
+        );
+    }
+}
@@ -1,175 +1,696 @@
-use crate::ast::{BlockStatement, Expression, Infix, Prefix, Program, Statement};
+use crate::ast::{BlockStatement, Expression, Infix, Position, Prefix, Program, Statement};
+use crate::formatter;
 use crate::object::{
     assert_argument_count, builtin, Environment, EvalError, EvalResult, HashKey, Object,
 };
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::panic;
 use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-// Evaluate a program
+// Evaluate a program, reading `leia` input from stdin and printing to stdout.
 pub fn eval(program: &Program, env: Rc<RefCell<Environment>>) -> EvalResult {
-    let mut result = Object::Null;
-    for statement in &program.statements {
-        result = eval_statement(statement, Rc::clone(&env))?;
-
-        // Stop evaluation if return
-        if let Object::Return(value) = result {
-            // Unwrap the returned value because here's the root of the program.
-            return Ok(*value);
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout();
+    Evaluator::new(&mut input, &mut output).eval(program, env)
+}
+
+// The deepest chain of function calls, like the recursion limit of Python. A call takes a few
+// kilobytes of native stack, tens of kilobytes in debug builds, so programs need a thread with
+// a stack of `STACK_SIZE` bytes (see `with_stack`) to reach the limit before overflowing it.
+pub const MAX_DEPTH: usize = 1000;
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+// Runs `f`, which evaluates programs, on a thread with a stack of `STACK_SIZE` bytes.
+pub fn with_stack<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send,
+    T: Send,
+{
+    thread::scope(|scope| {
+        let thread = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("failed to start the evaluator thread");
+        thread
+            .join()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    })
+}
+
+// A row of a desk-check table: the state after a statement runs. `se` and `enquanto` get a
+// row each time their condition is evaluated instead, with its value, like
+// `enquanto (i < 3): VERDADEIRO`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraceStep {
+    pub line: Option<usize>,
+    pub statement: String,
+    // The variables in scope, sorted by name. Functions are left out.
+    pub variables: Vec<(String, Object)>,
+}
+
+// The name of functions called without one, like `funcao(x) { ... }(1)` or `fs[0](1)`.
+const ANONYMOUS: &str = "<anônima>";
+
+// A function that was running when an error happened: the name it was called by and where it
+// was running, like a line of a Python traceback. The program is the first frame. Every frame
+// but the last is at the call of the next one, which also has a column. The last one is at the
+// statement that failed.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    pub function: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+// The frames from the program to the function where the error happened.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Traceback {
+    pub frames: Vec<Frame>,
+}
+
+// Repeats of the same frame shown before the rest are counted, like in recursive functions.
+const REPEATED_FRAMES: usize = 3;
+
+impl fmt::Display for Traceback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Rastreamento (chamada mais recente por último):")?;
+        let mut repeated = 0;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 && self.frames[i - 1] == *frame {
+                repeated += 1;
+            } else {
+                write_repeated(f, repeated)?;
+                repeated = 0;
+            }
+            if repeated < REPEATED_FRAMES {
+                match (frame.line, frame.column) {
+                    (Some(line), Some(column)) => writeln!(
+                        f,
+                        "  linha {}, coluna {}, em {}",
+                        line, column, frame.function
+                    )?,
+                    (Some(line), None) => writeln!(f, "  linha {}, em {}", line, frame.function)?,
+                    (None, _) => writeln!(f, "  em {}", frame.function)?,
+                }
+            }
         }
+        write_repeated(f, repeated)
     }
-    Ok(result)
 }
 
-fn eval_block_statement(block: &BlockStatement, env: Rc<RefCell<Environment>>) -> EvalResult {
-    let mut result = Object::Null;
-    for statement in &block.statements {
-        result = eval_statement(statement, Rc::clone(&env))?;
+fn write_repeated(f: &mut fmt::Formatter, repeated: usize) -> fmt::Result {
+    if repeated >= REPEATED_FRAMES {
+        writeln!(
+            f,
+            "  [a linha anterior se repete mais {} vezes]",
+            repeated + 1 - REPEATED_FRAMES
+        )?;
+    }
+    Ok(())
+}
+
+// Called by the evaluator as the program runs, like a debugger. See `Evaluator::set_hook`.
+pub trait Hook {
+    // Before each statement runs. Returning an error stops the program. While the hook runs,
+    // the evaluator can evaluate expressions in `env` without calling the hook again.
+    fn statement(
+        &mut self,
+        evaluator: &mut Evaluator,
+        statement: &Statement,
+        line: Option<usize>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), EvalError>;
 
-        // Stop evaluation if return
-        if let Object::Return(_) = result {
-            // Don't unwrap the returned value to keep propagating the return.
-            return Ok(result);
+    // When a function starts running, and when it returns or fails.
+    fn enter_function(&mut self) {}
+    fn leave_function(&mut self) {}
+}
+
+// Where `leia` reads from and `imprima` writes to, and how long the program may run.
+pub struct Evaluator<'a> {
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+    // Statements and loop iterations run so far.
+    steps: u64,
+    step_limit: Option<u64>,
+    time_limit: Option<Duration>,
+    deadline: Option<Instant>,
+    // Line of the statement being run.
+    line: Option<usize>,
+    // The steps recorded so far, when tracing.
+    trace: Option<Vec<TraceStep>>,
+    hook: Option<&'a mut dyn Hook>,
+    // The functions running: the name each one was called by and the line and column of the
+    // call.
+    calls: Vec<(String, Option<usize>, Option<usize>)>,
+    // Where the last error of `eval` happened.
+    traceback: Option<Traceback>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(input: &'a mut dyn BufRead, output: &'a mut dyn Write) -> Self {
+        Evaluator {
+            input,
+            output,
+            steps: 0,
+            step_limit: None,
+            time_limit: None,
+            deadline: None,
+            line: None,
+            trace: None,
+            hook: None,
+            calls: vec![],
+            traceback: None,
         }
     }
-    Ok(result)
-}
 
-fn eval_statement(statement: &Statement, env: Rc<RefCell<Environment>>) -> EvalResult {
-    match statement {
-        Statement::Expression(exp) => eval_expression(exp, env),
-        Statement::Return(Some(exp)) => {
-            let result = eval_expression(exp, env)?;
-            Ok(Object::Return(Box::new(result)))
+    // Fails with `EvalError::StepLimit` after running more than `limit` steps.
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = Some(limit);
+    }
+
+    // Fails with `EvalError::TimeLimit` when a call to `eval` runs longer than `limit`.
+    pub fn set_time_limit(&mut self, limit: Duration) {
+        self.time_limit = Some(limit);
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // Records a `TraceStep` for every statement that runs.
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.trace = if tracing { Some(vec![]) } else { None };
+    }
+
+    pub fn trace(&self) -> &[TraceStep] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    pub fn set_hook(&mut self, hook: &'a mut dyn Hook) {
+        self.hook = Some(hook);
+    }
+
+    // The calls that led to the error, when the last call to `eval` failed while running a
+    // statement.
+    pub fn traceback(&self) -> Option<&Traceback> {
+        self.traceback.as_ref()
+    }
+
+    // Evaluate a program
+    pub fn eval(&mut self, program: &Program, env: Rc<RefCell<Environment>>) -> EvalResult {
+        self.deadline = self.time_limit.map(|limit| Instant::now() + limit);
+        self.traceback = None;
+        let mut result = Object::Null;
+        for (i, statement) in program.statements.iter().enumerate() {
+            result = self.eval_statement(statement, program.line(i), Rc::clone(&env))?;
+
+            // Stop evaluation if return
+            if let Object::Return(value) = result {
+                // Unwrap the returned value because here's the root of the program.
+                return Ok(*value);
+            }
         }
-        Statement::Return(None) => Ok(Object::Return(Box::new(Object::Null))),
-        Statement::Print(Some(exp)) => {
-            let result = eval_expression(exp, env)?;
-            Ok(Object::Print(Box::new(result)))
+        self.output.flush().map_err(EvalError::Io)?;
+        Ok(result)
+    }
+
+    fn eval_block_statement(
+        &mut self,
+        block: &BlockStatement,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let mut result = Object::Null;
+        for (i, statement) in block.statements.iter().enumerate() {
+            result = self.eval_statement(statement, block.line(i), Rc::clone(&env))?;
+
+            // Stop evaluation if return
+            if let Object::Return(_) = result {
+                // Don't unwrap the returned value to keep propagating the return.
+                return Ok(result);
+            }
         }
-        Statement::Print(None) => Ok(Object::Return(Box::new(Object::Null))),
-        Statement::Read(name) => eval_identifier(name, env),
-        Statement::Let(name, exp) => {
-            let result = eval_expression(exp, Rc::clone(&env))?;
-            // TODO: Is this `clone()` the right way to do?
-            env.borrow_mut().set(name, result.clone());
-            Ok(result)
+        Ok(result)
+    }
+
+    // Counts a step, failing when a limit is exceeded.
+    fn step(&mut self) -> Result<(), EvalError> {
+        self.steps += 1;
+        if let Some(limit) = self.step_limit {
+            if self.steps > limit {
+                return Err(EvalError::StepLimit(limit));
+            }
+        }
+        if let (Some(deadline), Some(limit)) = (self.deadline, self.time_limit) {
+            if Instant::now() > deadline {
+                return Err(EvalError::TimeLimit(limit));
+            }
+        }
+        Ok(())
+    }
+
+    fn eval_statement(
+        &mut self,
+        statement: &Statement,
+        line: Option<usize>,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let result = self.step_statement(statement, line, env);
+        // The first statement that fails is the innermost one, where the error happened.
+        if result.is_err() && self.traceback.is_none() {
+            let mut frames = vec![];
+            let mut function = "<programa>";
+            for (name, call_line, call_column) in &self.calls {
+                frames.push(Frame {
+                    function: function.to_string(),
+                    line: *call_line,
+                    column: *call_column,
+                });
+                function = name;
+            }
+            frames.push(Frame {
+                function: function.to_string(),
+                line,
+                column: None,
+            });
+            self.traceback = Some(Traceback { frames });
         }
-        Statement::Int(name) => {
-            let result = Object::Integer(0);
-            env.borrow_mut().set(name, result.clone());
-            Ok(result)
+        result
+    }
+
+    fn step_statement(
+        &mut self,
+        statement: &Statement,
+        line: Option<usize>,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        self.step()?;
+        self.line = line;
+        // Taken out while it runs, so expressions it evaluates don't call it again.
+        if let Some(hook) = self.hook.take() {
+            // An error in an expression it evaluates isn't where the program fails.
+            let traceback = self.traceback.take();
+            let result = hook.statement(self, statement, line, &env);
+            self.traceback = traceback;
+            self.hook = Some(hook);
+            result?;
         }
-        Statement::Float(name) => {
-            let result = Object::Float(0.0);
-            env.borrow_mut().set(name, result.clone());
-            Ok(result)
+        let result = self.run_statement(statement, Rc::clone(&env))?;
+        // `se` and `enquanto` are recorded with their condition.
+        let is_conditional = matches!(
+            statement,
+            Statement::Expression(Expression::If(..) | Expression::While(..))
+        );
+        if self.trace.is_some() && !is_conditional {
+            self.record(line, formatter::statement_line(statement), &env);
         }
-        Statement::String(name) => {
-            let result = Object::String(String::new());
-            env.borrow_mut().set(name, result.clone());
-            Ok(result)
+        Ok(result)
+    }
+
+    fn record(&mut self, line: Option<usize>, statement: String, env: &Rc<RefCell<Environment>>) {
+        if let Some(trace) = &mut self.trace {
+            let mut variables = env.borrow().variables_in_scope();
+            variables.retain(|(_, value)| !matches!(value, Object::Function(..)));
+            trace.push(TraceStep {
+                line,
+                statement,
+                variables,
+            });
         }
     }
-}
 
-fn eval_expression(expression: &Expression, env: Rc<RefCell<Environment>>) -> EvalResult {
-    match expression {
-        Expression::Int => Ok(Object::Integer(0)),
-        Expression::Float => Ok(Object::Float(0.0)),
-        Expression::String => Ok(Object::String(String::new())),
-        Expression::IntegerLiteral(value) => Ok(Object::Integer(*value)),
-        Expression::FloatLiteral(value) => Ok(Object::Float(*value)),
-        Expression::StringLiteral(s) => Ok(Object::String(s.to_string())),
-        Expression::Boolean(value) => Ok(Object::Boolean(*value)),
-        Expression::Array(values) => eval_array_literal(values, env),
-        Expression::Hash(pairs) => eval_hash_literal(pairs, env),
-        Expression::Index(left, index) => eval_index_expression(left, index, env),
-        Expression::Prefix(prefix, exp) => eval_prefix_expression(prefix, exp.as_ref(), env),
-        Expression::Infix(infix, left, right) => {
-            eval_infix_expression(infix, left.as_ref(), right.as_ref(), env)
+    fn record_condition(
+        &mut self,
+        keyword: &str,
+        condition: &Expression,
+        value: bool,
+        line: Option<usize>,
+        env: &Rc<RefCell<Environment>>,
+    ) {
+        if self.trace.is_some() {
+            let statement = format!(
+                "{} ({}): {}",
+                keyword,
+                formatter::expression_line(condition),
+                if value { "VERDADEIRO" } else { "FALSO" }
+            );
+            self.record(line, statement, env);
         }
-        Expression::If(condition, consequence, alternative) => {
-            eval_if_expression(condition.as_ref(), consequence, alternative.as_ref(), env)
+    }
+
+    fn run_statement(
+        &mut self,
+        statement: &Statement,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        match statement {
+            Statement::Expression(exp) => self.eval_expression(exp, env),
+            Statement::Return(Some(exp)) => {
+                let result = self.eval_expression(exp, env)?;
+                Ok(Object::Return(Box::new(result)))
+            }
+            Statement::Return(None) => Ok(Object::Return(Box::new(Object::Null))),
+            // `imprima` doesn't end the line by itself.
+            Statement::Print(Some(exp)) => {
+                let result = self.eval_expression(exp, env)?;
+                match &result {
+                    Object::String(s) => write!(self.output, "{}", s),
+                    obj => write!(self.output, "{}", obj),
+                }
+                .map_err(EvalError::Io)?;
+                Ok(Object::Null)
+            }
+            Statement::Print(None) => {
+                writeln!(self.output).map_err(EvalError::Io)?;
+                Ok(Object::Null)
+            }
+            Statement::Read(name) => self.eval_read(name, env),
+            Statement::Let(name, exp) => {
+                let result = self.eval_expression(exp, Rc::clone(&env))?;
+                // TODO: Is this `clone()` the right way to do?
+                env.borrow_mut().set(name, result.clone());
+                Ok(result)
+            }
+            Statement::Int(name) => {
+                let result = Object::Integer(0);
+                env.borrow_mut().set(name, result.clone());
+                Ok(result)
+            }
+            Statement::Float(name) => {
+                let result = Object::Float(0.0);
+                env.borrow_mut().set(name, result.clone());
+                Ok(result)
+            }
+            Statement::String(name) => {
+                let result = Object::String(String::new());
+                env.borrow_mut().set(name, result.clone());
+                Ok(result)
+            }
         }
-        Expression::Assign(ident, value) => {
-            eval_infix_expression(&Infix::Assign, ident, value, env)
+    }
+
+    // Reads a line into a variable, converted to the type of its current value.
+    fn eval_read(&mut self, name: &str, env: Rc<RefCell<Environment>>) -> EvalResult {
+        let current = eval_identifier(name, Rc::clone(&env))?;
+        // Show what was printed so far, like a prompt, before waiting for input.
+        self.output.flush().map_err(EvalError::Io)?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line).map_err(EvalError::Io)? == 0 {
+            return Err(EvalError::EndOfInput);
         }
-        Expression::While(condition, consequence) => {
-            eval_while_expression(condition.as_ref(), consequence, env)
+        let line = line.trim_end_matches(['\n', '\r']);
+        let invalid = || EvalError::InvalidInput(line.to_string(), current.type_name().to_string());
+        let value = match current {
+            Object::Integer(_) => Object::Integer(line.trim().parse().map_err(|_| invalid())?),
+            Object::Float(_) => Object::Float(line.trim().parse().map_err(|_| invalid())?),
+            _ => Object::String(line.to_string()),
+        };
+        env.borrow_mut().assign(name, value.clone());
+        Ok(value)
+    }
+
+    pub fn eval_expression(
+        &mut self,
+        expression: &Expression,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        match expression {
+            Expression::Int => Ok(Object::Integer(0)),
+            Expression::Float => Ok(Object::Float(0.0)),
+            Expression::String => Ok(Object::String(String::new())),
+            Expression::IntegerLiteral(value) => Ok(Object::Integer(*value)),
+            Expression::FloatLiteral(value) => Ok(Object::Float(*value)),
+            Expression::StringLiteral(s) => Ok(Object::String(s.to_string())),
+            Expression::Boolean(value) => Ok(Object::Boolean(*value)),
+            Expression::Array(values) => self.eval_array_literal(values, env),
+            Expression::Hash(pairs) => self.eval_hash_literal(pairs, env),
+            Expression::Index(left, index) => self.eval_index_expression(left, index, env),
+            Expression::Prefix(prefix, exp) => {
+                self.eval_prefix_expression(prefix, exp.as_ref(), env)
+            }
+            Expression::Infix(infix, left, right) => {
+                self.eval_infix_expression(infix, left.as_ref(), right.as_ref(), env)
+            }
+            Expression::If(condition, consequence, alternative) => {
+                self.eval_if_expression(condition.as_ref(), consequence, alternative.as_ref(), env)
+            }
+            Expression::Assign(ident, value) => self.eval_assign_expression(ident, value, env),
+            Expression::While(condition, consequence) => {
+                self.eval_while_expression(condition.as_ref(), consequence, env)
+            }
+            Expression::Identifier(name) => eval_identifier(name, env),
+            Expression::FunctionLiteral(params, body) => {
+                // TODO: Pass a mutable reference of env...
+                Ok(Object::Function(params.to_vec(), body.clone(), env))
+            }
+            Expression::Call(func, args, position) => {
+                let function = self.eval_expression(func, Rc::clone(&env))?;
+                let arguments = self.eval_expressions(args, env)?;
+                let name = match func.as_ref() {
+                    Expression::Identifier(name) => name,
+                    _ => ANONYMOUS,
+                };
+                self.apply_function(function, arguments, name, *position)
+            }
         }
-        Expression::Identifier(name) => eval_identifier(name, env),
-        Expression::FunctionLiteral(params, body) => {
-            // TODO: Pass a mutable reference of env...
-            Ok(Object::Function(params.to_vec(), body.clone(), env))
+    }
+
+    fn eval_array_literal(
+        &mut self,
+        exps: &[Expression],
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let values = self.eval_expressions(exps, env)?;
+        Ok(Object::Array(values))
+    }
+
+    fn eval_hash_literal(
+        &mut self,
+        pairs: &[(Expression, Expression)],
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let mut map = HashMap::new();
+        for (k, v) in pairs {
+            let key = self.eval_expression(k, env.clone())?;
+            let value = self.eval_expression(v, env.clone())?;
+            let hash_key = HashKey::from_object(&key)?;
+            map.insert(hash_key, value);
         }
-        Expression::Call(func, args) => {
-            let function = eval_expression(func, Rc::clone(&env))?;
-            let arguments = eval_expressions(args, env)?;
-            apply_function(function, arguments)
+        Ok(Object::Hash(map))
+    }
+
+    fn eval_index_expression(
+        &mut self,
+        left: &Expression,
+        index: &Expression,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let left_evaluated = self.eval_expression(left, env.clone())?;
+        let index_evaluated = self.eval_expression(index, env)?;
+        match (left_evaluated, index_evaluated) {
+            (Object::Array(array), Object::Integer(value)) => {
+                Ok(or_null(array.get(value as usize)))
+            }
+            (Object::Hash(pairs), Object::Integer(value)) => {
+                Ok(or_null(pairs.get(&HashKey::Integer(value))))
+            }
+            (Object::Hash(pairs), Object::String(value)) => {
+                Ok(or_null(pairs.get(&HashKey::String(value))))
+            }
+            (Object::Hash(pairs), Object::Boolean(value)) => {
+                Ok(or_null(pairs.get(&HashKey::Boolean(value))))
+            }
+            (Object::Hash(_), key) => Err(EvalError::UnsupportedHashKey(Box::new(key))),
+            (l, i) => Err(EvalError::UnknownIndexOperator(Box::new(l), Box::new(i))),
         }
     }
-}
 
-fn eval_array_literal(exps: &[Expression], env: Rc<RefCell<Environment>>) -> EvalResult {
-    let values = eval_expressions(exps, env)?;
-    Ok(Object::Array(values))
-}
+    fn apply_function(
+        &mut self,
+        function: Object,
+        arguments: Vec<Object>,
+        name: &str,
+        position: Option<Position>,
+    ) -> EvalResult {
+        match function {
+            Object::Function(params, body, env) => {
+                assert_argument_count(params.len(), &arguments)?;
+                if self.calls.len() >= MAX_DEPTH {
+                    return Err(EvalError::StackOverflow(MAX_DEPTH));
+                }
+                let new_env = extend_function_env(params, arguments, env);
+                if let Some(hook) = &mut self.hook {
+                    hook.enter_function();
+                }
+                let line = self.line;
+                // Code that doesn't come from a source file only has the line of the statement.
+                let (call_line, call_column) = match position {
+                    Some(position) => (Some(position.line), Some(position.column)),
+                    None => (line, None),
+                };
+                self.calls.push((name.to_string(), call_line, call_column));
+                let evaluated = self.eval_block_statement(&body, new_env);
+                self.calls.pop();
+                // Back at the line of the call.
+                self.line = line;
+                if let Some(hook) = &mut self.hook {
+                    hook.leave_function();
+                }
+                unwrap_return_value(evaluated?)
+            }
+            Object::Builtin(func) => func(arguments),
+            _ => Err(EvalError::NotCallable(Box::new(function))),
+        }
+    }
 
-fn eval_hash_literal(
-    pairs: &[(Expression, Expression)],
-    env: Rc<RefCell<Environment>>,
-) -> EvalResult {
-    let mut map = HashMap::new();
-    for (k, v) in pairs {
-        let key = eval_expression(k, env.clone())?;
-        let value = eval_expression(v, env.clone())?;
-        let hash_key = HashKey::from_object(&key)?;
-        map.insert(hash_key, value);
-    }
-    Ok(Object::Hash(map))
-}
+    fn eval_prefix_expression(
+        &mut self,
+        prefix: &Prefix,
+        exp: &Expression,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let obj = self.eval_expression(exp, env)?;
 
-fn eval_index_expression(
-    left: &Expression,
-    index: &Expression,
-    env: Rc<RefCell<Environment>>,
-) -> EvalResult {
-    let left_evaluated = eval_expression(left, env.clone())?;
-    let index_evaluated = eval_expression(index, env)?;
-    match (left_evaluated, index_evaluated) {
-        (Object::Array(array), Object::Integer(value)) => Ok(or_null(array.get(value as usize))),
-        (Object::Hash(pairs), Object::Integer(value)) => {
-            Ok(or_null(pairs.get(&HashKey::Integer(value))))
+        match prefix {
+            // `!` works like JavaScript :P
+            Prefix::Bang => Ok(Object::Boolean(!obj.is_truthy())),
+            Prefix::Minus => match obj {
+                Object::Integer(value) => match value.checked_neg() {
+                    Some(value) => Ok(Object::Integer(value)),
+                    None => Err(EvalError::IntegerOverflow(format!("-({})", value))),
+                },
+                Object::Float(value) => Ok(Object::Float(-value)),
+                _ => Err(EvalError::UnknownPrefixOperator(
+                    prefix.clone(),
+                    Box::new(obj),
+                )),
+            },
         }
-        (Object::Hash(pairs), Object::String(value)) => {
-            Ok(or_null(pairs.get(&HashKey::String(value))))
+    }
+
+    fn eval_infix_expression(
+        &mut self,
+        infix: &Infix,
+        left_exp: &Expression,
+        right_exp: &Expression,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let left_obj = self.eval_expression(left_exp, Rc::clone(&env))?;
+        let right_obj = self.eval_expression(right_exp, env)?;
+
+        match (left_obj, right_obj) {
+            (Object::Boolean(left), Object::Boolean(right)) => {
+                eval_boolean_infix_expression(infix, left, right)
+            }
+            (Object::Integer(left), Object::Integer(right)) => {
+                eval_integer_infix_expression(infix, left, right)
+            }
+            (Object::Integer(left), Object::Float(right)) => {
+                eval_float_infix_expression(infix, left as f64, right)
+            }
+            (Object::Float(left), Object::Integer(right)) => {
+                eval_float_infix_expression(infix, left, right as f64)
+            }
+            (Object::Float(left), Object::Float(right)) => {
+                eval_float_infix_expression(infix, left, right)
+            }
+            (Object::String(left), Object::String(right)) => {
+                eval_string_infix_expression(infix, &left, &right)
+            }
+            (left, right) => Err(EvalError::TypeMismatch(
+                infix.clone(),
+                Box::new(left),
+                Box::new(right),
+            )),
         }
-        (Object::Hash(pairs), Object::Boolean(value)) => {
-            Ok(or_null(pairs.get(&HashKey::Boolean(value))))
+    }
+
+    // The value is checked against the current one, like an `=` infix expression, then
+    // stored where the variable was declared.
+    fn eval_assign_expression(
+        &mut self,
+        ident: &Expression,
+        value: &Expression,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let result = self.eval_infix_expression(&Infix::Assign, ident, value, Rc::clone(&env))?;
+        if let Expression::Identifier(name) = ident {
+            env.borrow_mut().assign(name, result.clone());
         }
-        (Object::Hash(_), key) => Err(EvalError::UnsupportedHashKey(key)),
-        (l, i) => Err(EvalError::UnknownIndexOperator(l, i)),
+        Ok(result)
     }
-}
 
-fn or_null(option: Option<&Object>) -> Object {
-    option.cloned().unwrap_or(Object::Null)
-}
+    fn eval_if_expression(
+        &mut self,
+        condition: &Expression,
+        consequence: &BlockStatement,
+        alternative: Option<&BlockStatement>,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let line = self.line;
+        let result = self.eval_expression(condition, Rc::clone(&env))?;
+        self.record_condition("se", condition, result.is_truthy(), line, &env);
 
-fn apply_function(function: Object, arguments: Vec<Object>) -> EvalResult {
-    match function {
-        Object::Function(params, body, env) => {
-            assert_argument_count(params.len(), &arguments)?;
-            let new_env = extend_function_env(params, arguments, env);
-            let evaluated = eval_block_statement(&body, new_env)?;
-            unwrap_return_value(evaluated)
+        if result.is_truthy() {
+            self.eval_block_statement(consequence, env)
+        } else {
+            alternative
+                .map(|a| self.eval_block_statement(a, env))
+                .unwrap_or(Ok(Object::Null))
         }
-        Object::Builtin(func) => func(arguments),
-        _ => Err(EvalError::NotCallable(function.clone())),
+    }
+
+    fn eval_while_expression(
+        &mut self,
+        condition: &Expression,
+        consequence: &BlockStatement,
+        env: Rc<RefCell<Environment>>,
+    ) -> EvalResult {
+        let line = self.line;
+        let mut result = Object::Null;
+        loop {
+            let value = self
+                .eval_expression(condition, Rc::clone(&env))?
+                .is_truthy();
+            self.record_condition("enquanto", condition, value, line, &env);
+            if !value {
+                break;
+            }
+            // Count iterations too, the body may be empty.
+            self.step()?;
+            result = self.eval_block_statement(consequence, Rc::clone(&env))?;
+
+            // Stop the loop if return
+            if let Object::Return(_) = result {
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    fn eval_expressions(
+        &mut self,
+        exps: &[Expression],
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<Vec<Object>, EvalError> {
+        let mut results = vec![];
+        for exp in exps {
+            results.push(self.eval_expression(exp, Rc::clone(&env))?);
+        }
+        Ok(results)
     }
 }
 
+fn or_null(option: Option<&Object>) -> Object {
+    option.cloned().unwrap_or(Object::Null)
+}
+
 fn extend_function_env(
     params: Vec<String>,
     arguments: Vec<Object>,
@@ -190,78 +711,32 @@ fn unwrap_return_value(obj: Object) -> EvalResult {
     }
 }
 
-fn eval_prefix_expression(
-    prefix: &Prefix,
-    exp: &Expression,
-    env: Rc<RefCell<Environment>>,
-) -> EvalResult {
-    let obj = eval_expression(exp, env)?;
-
-    match prefix {
-        // `!` works like JavaScript :P
-        Prefix::Bang => Ok(Object::Boolean(!obj.is_truthy())),
-        Prefix::Minus => match obj {
-            Object::Integer(value) => Ok(Object::Integer(-value)),
-            Object::Float(value) => Ok(Object::Float(-value)),
-            _ => Err(EvalError::UnknownPrefixOperator(prefix.clone(), obj)),
-        },
-    }
-}
-
-fn eval_infix_expression(
-    infix: &Infix,
-    left_exp: &Expression,
-    right_exp: &Expression,
-    env: Rc<RefCell<Environment>>,
-) -> EvalResult {
-    let left_obj = eval_expression(left_exp, Rc::clone(&env))?;
-    let right_obj = eval_expression(right_exp, env)?;
-
-    match (left_obj, right_obj) {
-        (Object::Boolean(left), Object::Boolean(right)) => {
-            eval_boolean_infix_expression(infix, left, right)
-        }
-        (Object::Integer(left), Object::Integer(right)) => {
-            eval_integer_infix_expression(infix, left, right)
-        }
-        (Object::Integer(left), Object::Float(right)) => {
-            eval_float_infix_expression(infix, left as f64, right)
-        }
-        (Object::Float(left), Object::Integer(right)) => {
-            eval_float_infix_expression(infix, left, right as f64)
-        }
-        (Object::Float(left), Object::Float(right)) => {
-            eval_float_infix_expression(infix, left, right)
-        }
-        (Object::String(left), Object::String(right)) => {
-            eval_string_infix_expression(infix, &left, &right)
-        }
-        (left, right) => Err(EvalError::TypeMismatch(infix.clone(), left, right)),
-    }
-}
-
 fn eval_boolean_infix_expression(infix: &Infix, left: bool, right: bool) -> EvalResult {
     match infix {
         Infix::Eq => Ok(Object::Boolean(left == right)),
         Infix::NotEq => Ok(Object::Boolean(left != right)),
+        Infix::Assign => Ok(Object::Boolean(right)),
         _ => Err(EvalError::UnknownInfixOperator(
             infix.clone(),
-            Object::Boolean(left),
-            Object::Boolean(right),
+            Box::new(Object::Boolean(left)),
+            Box::new(Object::Boolean(right)),
         )),
     }
 }
 
 fn eval_integer_infix_expression(infix: &Infix, left: i64, right: i64) -> EvalResult {
+    let overflow = || EvalError::IntegerOverflow(format!("{} {} {}", left, infix, right));
     Ok(match infix {
         Infix::Eq => Object::Boolean(left == right),
         Infix::NotEq => Object::Boolean(left != right),
         Infix::Lt => Object::Boolean(left < right),
         Infix::Gt => Object::Boolean(left > right),
-        Infix::Plus => Object::Integer(left + right),
-        Infix::Minus => Object::Integer(left - right),
-        Infix::Asterisk => Object::Integer(left * right),
-        Infix::Slash => Object::Integer(left / right),
+        Infix::Plus => Object::Integer(left.checked_add(right).ok_or_else(overflow)?),
+        Infix::Minus => Object::Integer(left.checked_sub(right).ok_or_else(overflow)?),
+        Infix::Asterisk => Object::Integer(left.checked_mul(right).ok_or_else(overflow)?),
+        Infix::Slash if right == 0 => return Err(EvalError::DivisionByZero),
+        // Only `i64::MIN / -1` overflows.
+        Infix::Slash => Object::Integer(left.checked_div(right).ok_or_else(overflow)?),
         Infix::Assign => Object::Integer(right),
     })
 }
@@ -291,42 +766,12 @@ fn eval_string_infix_expression(infix: &Infix, left: &str, right: &str) -> EvalR
         Infix::Eq => Ok(Object::Boolean(left.eq(right))),
         _ => Err(EvalError::UnknownInfixOperator(
             infix.clone(),
-            Object::String(left.to_string()),
-            Object::String(right.to_string()),
+            Box::new(Object::String(left.to_string())),
+            Box::new(Object::String(right.to_string())),
         )),
     }
 }
 
-fn eval_if_expression(
-    condition: &Expression,
-    consequence: &BlockStatement,
-    alternative: Option<&BlockStatement>,
-    env: Rc<RefCell<Environment>>,
-) -> EvalResult {
-    let result = eval_expression(condition, Rc::clone(&env))?;
-
-    if result.is_truthy() {
-        eval_block_statement(consequence, env)
-    } else {
-        alternative
-            .map(|a| eval_block_statement(a, env))
-            .unwrap_or(Ok(Object::Null))
-    }
-}
-
-fn eval_while_expression(
-    condition: &Expression,
-    consequence: &BlockStatement,
-    env: Rc<RefCell<Environment>>,
-) -> EvalResult {
-    let result = eval_expression(condition, Rc::clone(&env))?;
-
-    if result.is_truthy() {
-        return eval_block_statement(consequence, env);
-    }
-    Ok(Object::Null)
-}
-
 fn eval_identifier(name: &str, env: Rc<RefCell<Environment>>) -> EvalResult {
     if let Some(obj) = env.borrow().get(name) {
         return Ok(obj);
@@ -337,25 +782,15 @@ fn eval_identifier(name: &str, env: Rc<RefCell<Environment>>) -> EvalResult {
     Err(EvalError::IdentifierNotFound(name.to_string()))
 }
 
-fn eval_expressions(
-    exps: &[Expression],
-    env: Rc<RefCell<Environment>>,
-) -> Result<Vec<Object>, EvalError> {
-    let mut results = vec![];
-    for exp in exps {
-        results.push(eval_expression(exp, Rc::clone(&env))?);
-    }
-    Ok(results)
-}
-
 #[cfg(test)]
 mod evalator_tests {
-    use crate::evaluator;
+    use crate::evaluator::{self, Evaluator, Frame};
     use crate::lexer::Lexer;
     use crate::object::{Environment, EvalResult};
     use crate::parser::Parser;
     use std::cell::RefCell;
     use std::rc::Rc;
+    use std::time::Duration;
 
     #[test]
     fn eval_boolean() {
@@ -691,6 +1126,214 @@ mod evalator_tests {
         )]);
     }
 
+    #[test]
+    fn input_output() {
+        let tests = vec![
+            ("imprima(1); imprima; imprima(\"a\" + \"b\");", "", "1\nab"),
+            ("imprima(2.5 * 2); imprima(VERDADEIRO);", "", "5true"),
+            (
+                "int x; leia x; real y; leia y; string s; leia s; imprima(x + y); imprima(s);",
+                "3\n0.5\r\n  texto \n",
+                "3.5  texto ",
+            ),
+            (
+                "int i; enquanto (i < 3) { imprima(i); i = i + 1; }",
+                "",
+                "012",
+            ),
+            // Assignments change the variable where it was declared.
+            (
+                "int x; let f = funcao() { x = x + 1; }; f(); f(); imprima(x);",
+                "",
+                "2",
+            ),
+        ];
+        for (input, stdin, expected) in tests {
+            let (result, output) = eval_with_input(input, stdin);
+            result.unwrap();
+            assert_eq!(output, expected, "for `{}`", input);
+        }
+
+        let tests = vec![
+            ("int x; leia x;", "", "end of input"),
+            (
+                "int x; leia x;",
+                "tres\n",
+                "invalid input for INTEGER: `tres`",
+            ),
+            ("imprima(1); 1 / 0;", "", "division by zero"),
+        ];
+        for (input, stdin, expected) in tests {
+            let (result, _) = eval_with_input(input, stdin);
+            assert_eq!(result.unwrap_err().to_string(), expected, "for `{}`", input);
+        }
+    }
+
+    #[test]
+    fn print_statement() {
+        // `imprima` writes its value and evaluates to nothing.
+        let (result, output) = eval_with_input("imprima(\"a\"); imprima(1 + 1); imprima;", "");
+        assert_eq!(result.unwrap().to_string(), "null");
+        assert_eq!(output, "a2\n");
+        expect_values(vec![("let f = funcao() { imprima(1); }; f();", "null")]);
+    }
+
+    #[test]
+    fn while_expression() {
+        // The body runs while the condition holds, not just once.
+        let tests = vec![
+            ("int i; enquanto (i < 5) { i = i + 1; } imprima(i);", "5"),
+            ("int i; i = 7; enquanto (i < 5) { i = i + 1; } imprima(i);", "7"),
+            (
+                "let f = funcao() { int i; enquanto (VERDADEIRO) { i = i + 1; se (i > 2) { retorne i; } } }; imprima(f());",
+                "3",
+            ),
+        ];
+        for (input, expected) in tests {
+            let (result, output) = eval_with_input(input, "");
+            result.unwrap();
+            assert_eq!(output, expected, "for `{}`", input);
+        }
+    }
+
+    #[test]
+    fn assignment_scope() {
+        let tests = vec![
+            // Functions change the variables of the program.
+            ("int x; let f = funcao() { x = 5; }; f(); imprima(x);", "5"),
+            // And of enclosing functions.
+            (
+                "let f = funcao() { int x; let g = funcao() { x = x + 1; }; g(); g(); x }; imprima(f());",
+                "2",
+            ),
+            // Parameters and `let` shadow the outer variable instead.
+            ("int x; let f = funcao(x) { x = 5; }; f(1); imprima(x);", "0"),
+            ("int x; let f = funcao() { let x = 1; x = 5; }; f(); imprima(x);", "0"),
+            // `leia` too.
+            ("int x; let f = funcao() { leia x; }; f(); imprima(x);", "9"),
+        ];
+        for (input, expected) in tests {
+            let (result, output) = eval_with_input(input, "9\n");
+            result.unwrap();
+            assert_eq!(output, expected, "for `{}`", input);
+        }
+    }
+
+    #[test]
+    fn division_by_zero() {
+        expect_errors(vec![
+            ("1 / 0", "division by zero"),
+            ("int x; 10 / x", "division by zero"),
+            ("let f = funcao(n) { 1 / n }; f(0)", "division by zero"),
+        ]);
+        expect_values(vec![("7 / 2", "3"), ("-7 / 2", "-3")]);
+    }
+
+    #[test]
+    fn limits() {
+        let program = Parser::new(Lexer::new(
+            "int i; enquanto (i < 3) { i = i + 1; }".to_owned(),
+        ))
+        .parse_program();
+        let (mut stdin, mut output) = (&b""[..], vec![]);
+        let mut evaluator = Evaluator::new(&mut stdin, &mut output);
+        evaluator.set_step_limit(100);
+        evaluator
+            .eval(&program, Rc::new(RefCell::new(Environment::new())))
+            .unwrap();
+        // 2 statements, 3 iterations and 3 statements in the body.
+        assert_eq!(evaluator.steps(), 8);
+
+        let program =
+            Parser::new(Lexer::new("enquanto (VERDADEIRO) {}".to_owned())).parse_program();
+        let mut evaluator = Evaluator::new(&mut stdin, &mut output);
+        evaluator.set_step_limit(1000);
+        let result = evaluator.eval(&program, Rc::new(RefCell::new(Environment::new())));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "step limit of 1000 exceeded"
+        );
+
+        let mut evaluator = Evaluator::new(&mut stdin, &mut output);
+        evaluator.set_time_limit(Duration::from_millis(10));
+        let result = evaluator.eval(&program, Rc::new(RefCell::new(Environment::new())));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "time limit of 10ms exceeded"
+        );
+    }
+
+    #[test]
+    fn traceback() {
+        let input = "let conta = funcao(n) {
+    se (n == 0) {
+        retorne 1 / n;
+    }
+    retorne conta(n - 1);
+};
+let aplica = funcao(f, x) { retorne f(x); };
+imprima(aplica(conta, 5));";
+        let program = Parser::new(Lexer::new(input.to_owned())).parse_program();
+        let (mut stdin, mut output) = (&b""[..], vec![]);
+        let mut evaluator = Evaluator::new(&mut stdin, &mut output);
+        let result = evaluator.eval(&program, Rc::new(RefCell::new(Environment::new())));
+        assert_eq!(result.unwrap_err().to_string(), "division by zero");
+        // `conta` is called as `f` by `aplica`.
+        assert_eq!(
+            evaluator.traceback().unwrap().to_string(),
+            "Rastreamento (chamada mais recente por último):
+  linha 8, coluna 9, em <programa>
+  linha 7, coluna 37, em aplica
+  linha 5, coluna 13, em f
+  linha 5, coluna 13, em conta
+  linha 5, coluna 13, em conta
+  linha 5, coluna 13, em conta
+  [a linha anterior se repete mais 1 vezes]
+  linha 3, em conta
+"
+        );
+
+        // After `f` returns, the program is back at the line of the call.
+        let input = "let f = funcao() { retorne 0; };
+let g = funcao(a) {
+    retorne 1 / a;
+};
+imprima(f() + g(f()));";
+        let program = Parser::new(Lexer::new(input.to_owned())).parse_program();
+        let result = evaluator.eval(&program, Rc::new(RefCell::new(Environment::new())));
+        assert!(result.is_err());
+        assert_eq!(
+            evaluator.traceback().unwrap().frames,
+            vec![
+                Frame {
+                    function: "<programa>".to_string(),
+                    line: Some(5),
+                    column: Some(15),
+                },
+                Frame {
+                    function: "g".to_string(),
+                    line: Some(3),
+                    column: None,
+                },
+            ]
+        );
+
+        let program = Parser::new(Lexer::new("int x;".to_owned())).parse_program();
+        evaluator
+            .eval(&program, Rc::new(RefCell::new(Environment::new())))
+            .unwrap();
+        assert_eq!(evaluator.traceback(), None);
+    }
+
+    fn eval_with_input(input: &str, stdin: &str) -> (EvalResult, String) {
+        let program = Parser::new(Lexer::new(input.to_owned())).parse_program();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let mut stdin = stdin.as_bytes();
+        let mut output = vec![];
+        let result = Evaluator::new(&mut stdin, &mut output).eval(&program, env);
+        (result, String::from_utf8(output).unwrap())
+    }
+
     fn with_def(def: &str, code: &str) -> String {
         def.to_string() + code
     }
@@ -0,0 +1,339 @@
+// Flowcharts of Portugol programs, written as Graphviz DOT. The program goes from an `Início`
+// terminal to a `Fim` one, and each function defined with `let f = funcao(...)` gets its own
+// flowchart in a cluster.
+use crate::ast::{BlockStatement, Expression, Program, Statement};
+use crate::formatter;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Shape {
+    // Start and end.
+    Terminal,
+    // Assignments, declarations and other statements.
+    Process,
+    // `leia` and `imprima`.
+    InputOutput,
+    // The condition of `se` and `enquanto`.
+    Decision,
+}
+
+impl Shape {
+    fn dot(&self) -> &'static str {
+        match self {
+            Shape::Terminal => "ellipse",
+            Shape::Process => "box",
+            Shape::InputOutput => "parallelogram",
+            Shape::Decision => "diamond",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Node {
+    pub shape: Shape,
+    pub label: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    // `Sim` or `Não`, for edges that leave a decision.
+    pub label: Option<&'static str>,
+}
+
+// An edge that still has to be connected to the next node.
+type Exit = (usize, Option<&'static str>);
+
+#[derive(Debug, Default)]
+pub struct Flowchart {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    // The name of each function and the nodes of its flowchart.
+    pub functions: Vec<(String, Vec<usize>)>,
+}
+
+impl Flowchart {
+    pub fn new(program: &Program) -> Self {
+        let mut flowchart = Flowchart::default();
+        let start = flowchart.node(Shape::Terminal, "Início".to_string());
+        let end = flowchart.node(Shape::Terminal, "Fim".to_string());
+        let exits = flowchart.statements(&program.statements, vec![(start, None)], end);
+        flowchart.connect(exits, end);
+        flowchart
+    }
+
+    fn node(&mut self, shape: Shape, label: String) -> usize {
+        self.nodes.push(Node { shape, label });
+        self.nodes.len() - 1
+    }
+
+    fn connect(&mut self, exits: Vec<Exit>, to: usize) {
+        for (from, label) in exits {
+            self.edges.push(Edge { from, to, label });
+        }
+    }
+
+    // Adds the statements after `exits`, and returns the exits after them. `retorne` goes to
+    // `end`.
+    fn statements(
+        &mut self,
+        statements: &[Statement],
+        mut exits: Vec<Exit>,
+        end: usize,
+    ) -> Vec<Exit> {
+        for stmt in statements {
+            exits = self.statement(stmt, exits, end);
+        }
+        exits
+    }
+
+    fn block(&mut self, block: &BlockStatement, exits: Vec<Exit>, end: usize) -> Vec<Exit> {
+        self.statements(&block.statements, exits, end)
+    }
+
+    fn statement(&mut self, stmt: &Statement, exits: Vec<Exit>, end: usize) -> Vec<Exit> {
+        let (shape, label) = match stmt {
+            Statement::Expression(Expression::If(condition, consequence, alternative)) => {
+                let decision = self.node(Shape::Decision, formatter::expression_line(condition));
+                self.connect(exits, decision);
+                let mut exits = self.block(consequence, vec![(decision, Some("Sim"))], end);
+                match alternative {
+                    Some(alternative) => {
+                        exits.extend(self.block(alternative, vec![(decision, Some("Não"))], end))
+                    }
+                    None => exits.push((decision, Some("Não"))),
+                }
+                return exits;
+            }
+            Statement::Expression(Expression::While(condition, body)) => {
+                let decision = self.node(Shape::Decision, formatter::expression_line(condition));
+                self.connect(exits, decision);
+                let body_exits = self.block(body, vec![(decision, Some("Sim"))], end);
+                self.connect(body_exits, decision);
+                return vec![(decision, Some("Não"))];
+            }
+            Statement::Let(name, Expression::FunctionLiteral(parameters, body)) => {
+                self.function(name, parameters, body);
+                (
+                    Shape::Process,
+                    format!("{} = funcao({})", name, parameters.join(", ")),
+                )
+            }
+            Statement::Return(_) => {
+                let node = self.node(Shape::Process, statement_label(stmt));
+                self.connect(exits, node);
+                self.connect(vec![(node, None)], end);
+                return vec![];
+            }
+            Statement::Print(_) | Statement::Read(_) => (Shape::InputOutput, statement_label(stmt)),
+            _ => (Shape::Process, statement_label(stmt)),
+        };
+        let node = self.node(shape, label);
+        self.connect(exits, node);
+        vec![(node, None)]
+    }
+
+    fn function(&mut self, name: &str, parameters: &[String], body: &BlockStatement) {
+        let first = self.nodes.len();
+        let start = self.node(
+            Shape::Terminal,
+            format!("{}({})", name, parameters.join(", ")),
+        );
+        let end = self.node(Shape::Terminal, format!("Fim {}", name));
+        let exits = self.block(body, vec![(start, None)], end);
+        self.connect(exits, end);
+        // Nodes of functions defined inside this one are in their own cluster.
+        let nested = self
+            .functions
+            .iter()
+            .flat_map(|(_, nodes)| nodes.iter().copied())
+            .collect::<Vec<usize>>();
+        let nodes = (first..self.nodes.len())
+            .filter(|node| !nested.contains(node))
+            .collect();
+        self.functions.push((name.to_string(), nodes));
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph fluxograma {\n");
+        let in_function =
+            |node: &usize| self.functions.iter().any(|(_, nodes)| nodes.contains(node));
+        for node in (0..self.nodes.len()).filter(|node| !in_function(node)) {
+            out.push_str(&format!("    {}\n", self.dot_node(node)));
+        }
+        for (i, (name, nodes)) in self.functions.iter().enumerate() {
+            out.push_str(&format!("    subgraph cluster_{} {{\n", i));
+            out.push_str(&format!(
+                "        label={};\n",
+                dot_string(&format!("funcao {}", name))
+            ));
+            for node in nodes {
+                out.push_str(&format!("        {}\n", self.dot_node(*node)));
+            }
+            out.push_str("    }\n");
+        }
+        for edge in &self.edges {
+            match edge.label {
+                Some(label) => out.push_str(&format!(
+                    "    n{} -> n{} [label={}];\n",
+                    edge.from,
+                    edge.to,
+                    dot_string(label)
+                )),
+                None => out.push_str(&format!("    n{} -> n{};\n", edge.from, edge.to)),
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn dot_node(&self, id: usize) -> String {
+        let node = &self.nodes[id];
+        format!(
+            "n{} [shape={}, label={}];",
+            id,
+            node.shape.dot(),
+            dot_string(&node.label)
+        )
+    }
+}
+
+// The statement without the `;`, like `x = x + 1`.
+fn statement_label(stmt: &Statement) -> String {
+    formatter::statement_line(stmt)
+        .trim_end_matches(';')
+        .to_string()
+}
+
+fn dot_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Flowchart, Shape};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn flowchart(input: &str) -> Flowchart {
+        Flowchart::new(&Parser::new(Lexer::new(input.to_string())).parse_program())
+    }
+
+    // Edges as `from -> to (label)`, with node labels.
+    fn edges(flowchart: &Flowchart) -> Vec<String> {
+        flowchart
+            .edges
+            .iter()
+            .map(|edge| {
+                let label = edge
+                    .label
+                    .map_or(String::new(), |label| format!(" ({})", label));
+                format!(
+                    "{} -> {}{}",
+                    flowchart.nodes[edge.from].label, flowchart.nodes[edge.to].label, label
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shapes() {
+        let flowchart = flowchart("int x; leia x; x = x * 2; imprima(x);");
+        let shapes = flowchart
+            .nodes
+            .iter()
+            .map(|node| (node.shape, node.label.as_str()))
+            .collect::<Vec<(Shape, &str)>>();
+        assert_eq!(
+            shapes,
+            vec![
+                (Shape::Terminal, "Início"),
+                (Shape::Terminal, "Fim"),
+                (Shape::Process, "int x"),
+                (Shape::InputOutput, "leia x"),
+                (Shape::Process, "x = x * 2"),
+                (Shape::InputOutput, "imprima(x)"),
+            ]
+        );
+        assert_eq!(
+            edges(&flowchart),
+            vec![
+                "Início -> int x",
+                "int x -> leia x",
+                "leia x -> x = x * 2",
+                "x = x * 2 -> imprima(x)",
+                "imprima(x) -> Fim",
+            ]
+        );
+    }
+
+    #[test]
+    fn decisions() {
+        let flowchart = flowchart(
+            "int i;
+            enquanto (i < 3) {
+                se (i == 1) { imprima(\"um\"); } senao { imprima(i); }
+                i = i + 1;
+            }
+            se (i > 5) { imprima(\"muito\"); }",
+        );
+        assert_eq!(
+            edges(&flowchart),
+            vec![
+                "Início -> int i",
+                "int i -> i < 3",
+                "i < 3 -> i == 1 (Sim)",
+                "i == 1 -> imprima(\"um\") (Sim)",
+                "i == 1 -> imprima(i) (Não)",
+                "imprima(\"um\") -> i = i + 1",
+                "imprima(i) -> i = i + 1",
+                "i = i + 1 -> i < 3",
+                "i < 3 -> i > 5 (Não)",
+                "i > 5 -> imprima(\"muito\") (Sim)",
+                "imprima(\"muito\") -> Fim",
+                "i > 5 -> Fim (Não)",
+            ]
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let flowchart = flowchart(
+            "let f = funcao(n) { se (n < 2) { retorne 1; } retorne n * f(n - 1); };
+            imprima(f(3));",
+        );
+        assert_eq!(
+            edges(&flowchart),
+            vec![
+                "f(n) -> n < 2",
+                "n < 2 -> retorne 1 (Sim)",
+                "retorne 1 -> Fim f",
+                "n < 2 -> retorne n * f(n - 1) (Não)",
+                "retorne n * f(n - 1) -> Fim f",
+                "Início -> f = funcao(n)",
+                "f = funcao(n) -> imprima(f(3))",
+                "imprima(f(3)) -> Fim",
+            ]
+        );
+        let dot = flowchart.to_dot();
+        assert!(
+            dot.starts_with(
+                "digraph fluxograma {
+    n0 [shape=ellipse, label=\"Início\"];
+    n1 [shape=ellipse, label=\"Fim\"];
+    n7 [shape=box, label=\"f = funcao(n)\"];
+    n8 [shape=parallelogram, label=\"imprima(f(3))\"];
+    subgraph cluster_0 {
+        label=\"funcao f\";
+        n2 [shape=ellipse, label=\"f(n)\"];
+        n3 [shape=ellipse, label=\"Fim f\"];
+        n4 [shape=diamond, label=\"n < 2\"];
+"
+            ),
+            "{}",
+            dot
+        );
+        assert!(dot.contains("    n4 -> n5 [label=\"Sim\"];\n"), "{}", dot);
+        assert!(dot.ends_with("    n8 -> n1;\n}\n"), "{}", dot);
+    }
+}
@@ -0,0 +1,376 @@
+use crate::ast::{BlockStatement, Expression, Infix, Prefix, Program, Statement};
+use std::fmt;
+
+// Constant folding and simplification over the AST.
+//
+// The folded program is shared by the evaluator and the transpiler, so anything done here
+// must keep the meaning the evaluator gives to the original program.
+
+type Result<T> = std::result::Result<T, FoldError>;
+
+#[derive(Debug, PartialEq)]
+pub enum FoldError {
+    DivisionByZero(Expression),
+}
+
+impl fmt::Display for FoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FoldError::DivisionByZero(exp) => write!(f, "division by zero: {}", exp),
+        }
+    }
+}
+
+// Fails when the program divides a literal by zero where it always runs: outside of functions
+// and loops, and in the `se` branches literal conditions pick. Anywhere else the division is
+// left for the runtime, which only fails if it gets there.
+pub fn fold(program: &Program) -> Result<Program> {
+    let (statements, lines) = fold_statements(&program.statements, &program.lines);
+    if let Some(division) = reachable_division_by_zero(&statements) {
+        return Err(FoldError::DivisionByZero(division.clone()));
+    }
+    Ok(Program { statements, lines })
+}
+
+fn fold_block_statement(block: &BlockStatement) -> BlockStatement {
+    let (statements, lines) = fold_statements(&block.statements, &block.lines);
+    BlockStatement { statements, lines }
+}
+
+// Folds the statements of a block along with their lines.
+fn fold_statements(statements: &[Statement], lines: &[usize]) -> (Vec<Statement>, Vec<usize>) {
+    let mut folded = vec![];
+    let mut folded_lines = vec![];
+    for (i, statement) in statements.iter().enumerate() {
+        let line = lines.get(i).copied();
+        match fold_statement(statement) {
+            // A `se` with a literal condition is replaced by the branch that runs. Blocks
+            // don't introduce a scope, so the statements can live in the enclosing block.
+            Statement::Expression(Expression::If(condition, consequence, alternative)) => {
+                let branch = match literal_truthiness(&condition) {
+                    Some(true) => Some(consequence),
+                    Some(false) => alternative,
+                    None => {
+                        folded.push(Statement::Expression(Expression::If(
+                            condition,
+                            consequence,
+                            alternative,
+                        )));
+                        folded_lines.extend(line);
+                        continue;
+                    }
+                };
+                if let Some(branch) = branch {
+                    for (j, statement) in branch.statements.into_iter().enumerate() {
+                        folded.push(statement);
+                        folded_lines.extend(branch.lines.get(j).copied().or(line));
+                    }
+                }
+            }
+            statement => {
+                folded.push(statement);
+                folded_lines.extend(line);
+            }
+        }
+    }
+    // Lines are all or nothing.
+    if folded_lines.len() != folded.len() {
+        folded_lines.clear();
+    }
+    (folded, folded_lines)
+}
+
+fn fold_statement(statement: &Statement) -> Statement {
+    match statement {
+        Statement::Let(name, exp) => Statement::Let(name.clone(), fold_expression(exp)),
+        Statement::Return(Some(exp)) => Statement::Return(Some(fold_expression(exp))),
+        Statement::Print(Some(exp)) => Statement::Print(Some(fold_expression(exp))),
+        Statement::Expression(exp) => Statement::Expression(fold_expression(exp)),
+        Statement::Return(None)
+        | Statement::Print(None)
+        | Statement::Read(_)
+        | Statement::Int(_)
+        | Statement::Float(_)
+        | Statement::String(_) => statement.clone(),
+    }
+}
+
+fn fold_expression(expression: &Expression) -> Expression {
+    match expression {
+        Expression::Array(values) => Expression::Array(fold_expressions(values)),
+        Expression::Hash(pairs) => Expression::Hash(
+            pairs
+                .iter()
+                .map(|(k, v)| (fold_expression(k), fold_expression(v)))
+                .collect(),
+        ),
+        Expression::Index(left, index) => Expression::Index(
+            Box::new(fold_expression(left)),
+            Box::new(fold_expression(index)),
+        ),
+        Expression::Prefix(prefix, exp) => fold_prefix_expression(prefix, fold_expression(exp)),
+        Expression::Infix(infix, left, right) => {
+            fold_infix_expression(infix, fold_expression(left), fold_expression(right))
+        }
+        Expression::If(condition, consequence, alternative) => {
+            let condition = fold_expression(condition);
+            let consequence = fold_block_statement(consequence);
+            let alternative = alternative.as_ref().map(fold_block_statement);
+            // Keep the expression form (its value may be used), but drop the branch that
+            // can't run.
+            match (literal_truthiness(&condition), alternative) {
+                (Some(true), _) => Expression::If(Box::new(condition), consequence, None),
+                (Some(false), Some(alt)) => {
+                    Expression::If(Box::new(Expression::Boolean(true)), alt, None)
+                }
+                (_, alternative) => Expression::If(Box::new(condition), consequence, alternative),
+            }
+        }
+        Expression::While(condition, consequence) => Expression::While(
+            Box::new(fold_expression(condition)),
+            fold_block_statement(consequence),
+        ),
+        // The left side is a place, not a value.
+        Expression::Assign(left, right) => {
+            Expression::Assign(left.clone(), Box::new(fold_expression(right)))
+        }
+        Expression::FunctionLiteral(params, body) => {
+            Expression::FunctionLiteral(params.clone(), fold_block_statement(body))
+        }
+        Expression::Call(func, args, position) => Expression::Call(
+            Box::new(fold_expression(func)),
+            fold_expressions(args),
+            *position,
+        ),
+        Expression::Identifier(_)
+        | Expression::IntegerLiteral(_)
+        | Expression::FloatLiteral(_)
+        | Expression::StringLiteral(_)
+        | Expression::Boolean(_)
+        | Expression::Int
+        | Expression::Float
+        | Expression::String => expression.clone(),
+    }
+}
+
+fn fold_expressions(exps: &[Expression]) -> Vec<Expression> {
+    exps.iter().map(fold_expression).collect()
+}
+
+fn fold_prefix_expression(prefix: &Prefix, exp: Expression) -> Expression {
+    match (prefix, &exp) {
+        (Prefix::Bang, _) if literal_truthiness(&exp).is_some() => {
+            Expression::Boolean(!literal_truthiness(&exp).unwrap_or_default())
+        }
+        (Prefix::Minus, Expression::IntegerLiteral(value)) if value.checked_neg().is_some() => {
+            Expression::IntegerLiteral(-value)
+        }
+        (Prefix::Minus, Expression::FloatLiteral(value)) => Expression::FloatLiteral(-value),
+        _ => Expression::Prefix(prefix.clone(), Box::new(exp)),
+    }
+}
+
+fn fold_infix_expression(infix: &Infix, left: Expression, right: Expression) -> Expression {
+    let folded = match (&left, &right) {
+        (Expression::IntegerLiteral(l), Expression::IntegerLiteral(r)) => {
+            fold_integer_infix_expression(infix, *l, *r)
+        }
+        (Expression::IntegerLiteral(l), Expression::FloatLiteral(r)) => {
+            fold_float_infix_expression(infix, *l as f64, *r)
+        }
+        (Expression::FloatLiteral(l), Expression::IntegerLiteral(r)) => {
+            fold_float_infix_expression(infix, *l, *r as f64)
+        }
+        (Expression::FloatLiteral(l), Expression::FloatLiteral(r)) => {
+            fold_float_infix_expression(infix, *l, *r)
+        }
+        (Expression::StringLiteral(l), Expression::StringLiteral(r)) => match infix {
+            Infix::Plus => Some(Expression::StringLiteral([l.as_str(), r.as_str()].concat())),
+            Infix::Eq => Some(Expression::Boolean(l == r)),
+            _ => None,
+        },
+        (Expression::Boolean(l), Expression::Boolean(r)) => match infix {
+            Infix::Eq => Some(Expression::Boolean(l == r)),
+            Infix::NotEq => Some(Expression::Boolean(l != r)),
+            _ => None,
+        },
+        _ => None,
+    };
+    folded.unwrap_or_else(|| Expression::Infix(infix.clone(), Box::new(left), Box::new(right)))
+}
+
+fn fold_integer_infix_expression(infix: &Infix, left: i64, right: i64) -> Option<Expression> {
+    match infix {
+        Infix::Eq => Some(Expression::Boolean(left == right)),
+        Infix::NotEq => Some(Expression::Boolean(left != right)),
+        Infix::Lt => Some(Expression::Boolean(left < right)),
+        Infix::Gt => Some(Expression::Boolean(left > right)),
+        // Overflows and divisions by zero are left for the runtime.
+        Infix::Plus => left.checked_add(right).map(Expression::IntegerLiteral),
+        Infix::Minus => left.checked_sub(right).map(Expression::IntegerLiteral),
+        Infix::Asterisk => left.checked_mul(right).map(Expression::IntegerLiteral),
+        Infix::Slash => left.checked_div(right).map(Expression::IntegerLiteral),
+        Infix::Assign => None,
+    }
+}
+
+#[allow(clippy::float_cmp)]
+fn fold_float_infix_expression(infix: &Infix, left: f64, right: f64) -> Option<Expression> {
+    match infix {
+        Infix::Eq => Some(Expression::Boolean(left == right)),
+        Infix::NotEq => Some(Expression::Boolean(left != right)),
+        Infix::Lt => Some(Expression::Boolean(left < right)),
+        Infix::Gt => Some(Expression::Boolean(left > right)),
+        Infix::Plus => Some(Expression::FloatLiteral(left + right)),
+        Infix::Minus => Some(Expression::FloatLiteral(left - right)),
+        Infix::Asterisk => Some(Expression::FloatLiteral(left * right)),
+        Infix::Slash => Some(Expression::FloatLiteral(left / right)),
+        Infix::Assign => None,
+    }
+}
+
+// The first division of a literal by zero that runs whenever `statements` do.
+fn reachable_division_by_zero(statements: &[Statement]) -> Option<&Expression> {
+    for statement in statements {
+        match statement {
+            Statement::Return(Some(exp)) => return division_by_zero(exp),
+            Statement::Return(None) => return None,
+            Statement::Let(_, exp) | Statement::Print(Some(exp)) | Statement::Expression(exp) => {
+                if let Some(division) = division_by_zero(exp) {
+                    return Some(division);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn division_by_zero(exp: &Expression) -> Option<&Expression> {
+    match exp {
+        Expression::Infix(Infix::Slash, left, right)
+            if matches!(left.as_ref(), Expression::IntegerLiteral(_))
+                && matches!(right.as_ref(), Expression::IntegerLiteral(0)) =>
+        {
+            Some(exp)
+        }
+        Expression::Infix(_, left, right) | Expression::Index(left, right) => {
+            division_by_zero(left).or_else(|| division_by_zero(right))
+        }
+        Expression::Prefix(_, right) | Expression::Assign(_, right) => division_by_zero(right),
+        Expression::Array(values) => values.iter().find_map(division_by_zero),
+        Expression::Hash(pairs) => pairs
+            .iter()
+            .find_map(|(k, v)| division_by_zero(k).or_else(|| division_by_zero(v))),
+        Expression::Call(function, arguments, _) => {
+            division_by_zero(function).or_else(|| arguments.iter().find_map(division_by_zero))
+        }
+        Expression::If(condition, consequence, alternative) => {
+            division_by_zero(condition).or_else(|| match literal_truthiness(condition) {
+                Some(true) => reachable_division_by_zero(&consequence.statements),
+                Some(false) => alternative
+                    .as_ref()
+                    .and_then(|alt| reachable_division_by_zero(&alt.statements)),
+                None => None,
+            })
+        }
+        // The body of a loop may never run, like a function.
+        Expression::While(condition, _) => division_by_zero(condition),
+        _ => None,
+    }
+}
+
+// Whether a literal is truthy, following `Object::is_truthy`. `None` for non-literals.
+fn literal_truthiness(exp: &Expression) -> Option<bool> {
+    match exp {
+        Expression::Boolean(value) => Some(*value),
+        Expression::IntegerLiteral(_)
+        | Expression::FloatLiteral(_)
+        | Expression::StringLiteral(_) => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fold::fold;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn fold_expressions() {
+        expect_folded(vec![
+            ("2 * 3 + 1", "7;"),
+            ("-(2 - 5)", "3;"),
+            ("1 + 2.5", "3.5;"),
+            ("!VERDADEIRO", "False;"),
+            ("!!0", "True;"),
+            ("3 > 2 == VERDADEIRO", "True;"),
+            (r#""ola" + ", " + "mundo""#, r#""ola, mundo";"#),
+            ("let x = 2 * a + 3 * 4;", "let x = ((2 * a) + 12);"),
+            ("let r = f(1 + 1, [2 * 2]);", "let r = f(2, [4]);"),
+        ]);
+    }
+
+    #[test]
+    fn fold_if() {
+        expect_folded(vec![
+            ("se (1 < 2) { imprima 1; } senao { imprima 2; }", "print 1;"),
+            ("se (FALSO) { imprima 1; } senao { imprima 2; }", "print 2;"),
+            ("se (FALSO) { imprima 1; }", ""),
+            ("se (a) { imprima 1 + 1; }", "if a { print 2; };"),
+            (
+                "let v = se (FALSO) { 1 } senao { 2 };",
+                "let v = if True { 2; };",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let tests = vec![
+            ("let x = 1 + 4 / (2 - 2);", "division by zero: (4 / 0)"),
+            ("se (VERDADEIRO) { imprima(1 / 0); }", "division by zero: (1 / 0)"),
+            (
+                "let v = se (FALSO) { 1 } senao { 2 / 0 };",
+                "division by zero: (2 / 0)",
+            ),
+            ("enquanto (1 / 0 > x) { }", "division by zero: (1 / 0)"),
+        ];
+        for (input, expected) in tests {
+            let program = Parser::new(Lexer::new(input.to_owned())).parse_program();
+            match fold(&program) {
+                Ok(folded) => panic!("expected an error, got `{}` for `{}`", folded, input),
+                Err(err) => assert_eq!(err.to_string(), expected, "for `{}`", input),
+            }
+        }
+    }
+
+    #[test]
+    fn guarded_division_by_zero() {
+        // Divisions that may never run are left for the runtime.
+        expect_folded(vec![
+            (
+                r#"se (x == 1) { imprima(1 / 0); } imprima("ok");"#,
+                r#"if (x == 1) { print (1 / 0); };print "ok";"#,
+            ),
+            ("let f = funcao() { 2 / (1 - 1) };", "let f = fn() { (2 / 0); };"),
+            ("enquanto (x) { 1 / 0; }", "while x { (1 / 0); };"),
+            ("retorne 1; 1 / 0;", "return 1;(1 / 0);"),
+        ]);
+    }
+
+    fn expect_folded(tests: Vec<(&str, &str)>) {
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_owned());
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+            assert!(parser.errors().is_empty(), "for `{}`", input);
+
+            match fold(&program) {
+                Ok(folded) => assert_eq!(folded.to_string(), expected, "for `{}`", input),
+                Err(err) => panic!("got error=`{}` for `{}`", err, input),
+            }
+        }
+    }
+}
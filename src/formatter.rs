@@ -0,0 +1,559 @@
+use crate::ast::{BlockStatement, Expression, Infix, Prefix, Program, Statement};
+use crate::lexer::{Comment, Lexer};
+use crate::parser::{Parser, ParserError, Precedence};
+
+// Formats Portugol source in the canonical style: 4 spaces of indentation, `{` at the end of
+// the line that opens the block, spaces around binary operators and one statement per line.
+// Comments and single blank lines between statements are kept.
+
+const SPACES: usize = 4;
+
+pub fn format(input: &str) -> Result<String, ParserError> {
+    let mut parser = Parser::new(Lexer::new(input.to_string()));
+    let program = parser.parse_program();
+    if let Some(err) = parser.errors().first() {
+        return Err(err.clone());
+    }
+    let mut formatter = Formatter {
+        source: input.lines().collect(),
+        comments: parser.comments(),
+        next_comment: 0,
+        braces: 0,
+        trailing: None,
+        block_start: true,
+        out: String::new(),
+    };
+    formatter.program(&program);
+    Ok(formatter.out)
+}
+
+// A statement on one line, for messages. Blocks are left out, like `se (x > 1) { ... }`.
+pub fn statement_line(stmt: &Statement) -> String {
+    let mut formatter = Formatter::detached();
+    formatter.statement(stmt, None, None, None, 0);
+    one_line(&formatter.out)
+}
+
+// An expression on one line, like `statement_line`.
+pub fn expression_line(exp: &Expression) -> String {
+    let mut formatter = Formatter::detached();
+    formatter.expression(exp, &Precedence::Lowest, 0);
+    one_line(&formatter.out)
+}
+
+// Keeps the first and the last line of code with blocks.
+fn one_line(code: &str) -> String {
+    let lines = code.lines().collect::<Vec<&str>>();
+    match lines.as_slice() {
+        [first, .., last] => format!("{} ... {}", first, last.trim_start()),
+        _ => code.trim_end().to_string(),
+    }
+}
+
+struct Formatter<'a> {
+    // The lines of the source, to find blank lines.
+    source: Vec<&'a str>,
+    comments: &'a [Comment],
+    // The first comment not written yet.
+    next_comment: usize,
+    // Number of `{` and `}` written so far. It matches the source, and `Comment::braces`.
+    braces: usize,
+    // A comment to end the current line with.
+    trailing: Option<&'a Comment>,
+    // Whether nothing was written in the current block yet.
+    block_start: bool,
+    out: String,
+}
+
+impl<'a> Formatter<'a> {
+    // A formatter without source, for code that isn't a whole program.
+    fn detached() -> Self {
+        Formatter {
+            source: vec![],
+            comments: &[],
+            next_comment: 0,
+            braces: 0,
+            trailing: None,
+            block_start: true,
+            out: String::new(),
+        }
+    }
+
+    fn program(&mut self, program: &Program) {
+        for (i, stmt) in program.statements.iter().enumerate() {
+            let next = program.statements.get(i + 1);
+            self.statement(stmt, program.line(i), next, program.line(i + 1), 0);
+        }
+        self.comments_before(usize::MAX, 0);
+    }
+
+    fn block(&mut self, block: &BlockStatement, indent_level: usize) {
+        let is_empty = block.statements.is_empty()
+            && !matches!(self.comments.get(self.next_comment), Some(comment) if comment.braces < self.braces + 2);
+        if is_empty {
+            self.out.push_str("{}");
+            self.braces += 2;
+            return;
+        }
+        self.out.push('{');
+        self.braces += 1;
+        // Like `se (x) { // comentario`.
+        if let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.trailing
+                && comment.braces == self.braces
+                && block.line(0) != Some(comment.line)
+            {
+                self.trailing = Some(comment);
+                self.next_comment += 1;
+            }
+        }
+        self.newline();
+        self.block_start = true;
+        for (i, stmt) in block.statements.iter().enumerate() {
+            let next = block.statements.get(i + 1);
+            let next_line = block.line(i + 1);
+            self.statement(stmt, block.line(i), next, next_line, indent_level + 1);
+        }
+        // Comments at the end of the block.
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.braces > self.braces {
+                break;
+            }
+            self.comment(comment, indent_level + 1);
+        }
+        self.out.push_str(&indent(indent_level));
+        self.out.push('}');
+        self.braces += 1;
+        self.block_start = false;
+        // Like `} // fim`.
+        if let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.trailing && comment.braces == self.braces {
+                self.trailing = Some(comment);
+                self.next_comment += 1;
+            }
+        }
+    }
+
+    // `next` is the next statement in the same block, if any.
+    fn statement(
+        &mut self,
+        stmt: &Statement,
+        line: Option<usize>,
+        next: Option<&Statement>,
+        next_line: Option<usize>,
+        indent_level: usize,
+    ) {
+        if let Some(line) = line {
+            self.comments_before(line, indent_level);
+            self.blank_line(line);
+            // Like `x = 1; // um`. With more than one statement on the line, the comment
+            // goes with the last one.
+            if let Some(comment) = self.comments.get(self.next_comment) {
+                if comment.line == line
+                    && comment.trailing
+                    && comment.braces == self.braces
+                    && next_line != Some(line)
+                {
+                    self.trailing = Some(comment);
+                    self.next_comment += 1;
+                }
+            }
+        }
+        self.out.push_str(&indent(indent_level));
+        match stmt {
+            Statement::Let(name, value) => {
+                self.out.push_str(&format!("let {} = ", name));
+                self.expression(value, &Precedence::Lowest, indent_level);
+                self.out.push(';');
+            }
+            Statement::Int(name) => self.out.push_str(&format!("int {};", name)),
+            Statement::Float(name) => self.out.push_str(&format!("real {};", name)),
+            Statement::String(name) => self.out.push_str(&format!("string {};", name)),
+            Statement::Return(None) => self.out.push_str("retorne;"),
+            Statement::Return(Some(exp)) => {
+                self.out.push_str("retorne ");
+                self.expression(exp, &Precedence::Lowest, indent_level);
+                self.out.push(';');
+            }
+            Statement::Print(None) => self.out.push_str("imprima;"),
+            Statement::Print(Some(exp)) => {
+                self.out.push_str("imprima(");
+                self.expression(exp, &Precedence::Lowest, indent_level);
+                self.out.push_str(");");
+            }
+            Statement::Read(name) => self.out.push_str(&format!("leia {};", name)),
+            Statement::Expression(Expression::Assign(name, value)) => {
+                self.expression(name, &Precedence::Lowest, indent_level);
+                self.out.push_str(" = ");
+                self.expression(value, &Precedence::Assign, indent_level);
+                self.out.push(';');
+            }
+            // `se` and `enquanto` don't need a `;`, unless the next statement would be read
+            // as part of them, like `-1;` as a subtraction.
+            Statement::Expression(exp @ (Expression::If(_, _, _) | Expression::While(_, _))) => {
+                self.expression(exp, &Precedence::Lowest, indent_level);
+                if let Some(Statement::Expression(next)) = next {
+                    if starts_with_operator(next) {
+                        self.out.push(';');
+                    }
+                }
+            }
+            Statement::Expression(exp) => {
+                self.expression(exp, &Precedence::Lowest, indent_level);
+                self.out.push(';');
+            }
+        }
+        self.newline();
+        self.block_start = false;
+    }
+
+    // Writes `exp`, in parentheses if it binds looser than `precedence`.
+    fn expression(&mut self, exp: &Expression, precedence: &Precedence, indent_level: usize) {
+        let grouped = &binding(exp) < precedence;
+        if grouped {
+            self.out.push('(');
+        }
+        match exp {
+            Expression::Identifier(name) => self.out.push_str(name),
+            Expression::IntegerLiteral(value) => self.out.push_str(&value.to_string()),
+            Expression::FloatLiteral(value) => self.out.push_str(&float(*value)),
+            Expression::StringLiteral(s) => self.out.push_str(&format!("\"{}\"", s)),
+            Expression::Boolean(true) => self.out.push_str("VERDADEIRO"),
+            Expression::Boolean(false) => self.out.push_str("FALSO"),
+            Expression::Int => self.out.push_str("int"),
+            Expression::Float => self.out.push_str("real"),
+            Expression::String => self.out.push_str("string"),
+            Expression::Array(values) => {
+                self.out.push('[');
+                self.comma_separated(values, indent_level);
+                self.out.push(']');
+            }
+            Expression::Hash(pairs) => {
+                self.out.push('{');
+                self.braces += 1;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(key, &Precedence::Lowest, indent_level);
+                    self.out.push_str(": ");
+                    self.expression(value, &Precedence::Lowest, indent_level);
+                }
+                self.out.push('}');
+                self.braces += 1;
+            }
+            Expression::Index(left, index) => {
+                self.expression(left, &Precedence::Call, indent_level);
+                self.out.push('[');
+                self.expression(index, &Precedence::Lowest, indent_level);
+                self.out.push(']');
+            }
+            Expression::Prefix(operator, right) => {
+                self.out.push_str(&operator.to_string());
+                // `- -1` and `! !x`, not `--1` and `!!x`, which read like other operators.
+                if starts_with_prefix(operator, right) {
+                    self.out.push(' ');
+                }
+                self.expression(right, &Precedence::Prefix, indent_level);
+            }
+            Expression::Infix(operator, left, right) => {
+                let precedence = infix_binding(operator);
+                self.expression(left, &precedence, indent_level);
+                self.out.push_str(&format!(" {} ", operator));
+                // Operators are left associative.
+                self.expression(right, &next(&precedence), indent_level);
+            }
+            Expression::If(condition, consequence, alternative) => {
+                self.out.push_str("se (");
+                self.expression(condition, &Precedence::Lowest, indent_level);
+                self.out.push_str(") ");
+                self.block(consequence, indent_level);
+                if let Some(alternative) = alternative {
+                    self.out.push_str(" senao ");
+                    self.block(alternative, indent_level);
+                }
+            }
+            Expression::While(condition, consequence) => {
+                self.out.push_str("enquanto (");
+                self.expression(condition, &Precedence::Lowest, indent_level);
+                self.out.push_str(") ");
+                self.block(consequence, indent_level);
+            }
+            Expression::Assign(name, value) => {
+                self.expression(name, &Precedence::Lowest, indent_level);
+                self.out.push_str(" = ");
+                self.expression(value, &Precedence::Assign, indent_level);
+            }
+            Expression::FunctionLiteral(parameters, body) => {
+                self.out
+                    .push_str(&format!("funcao({}) ", parameters.join(", ")));
+                self.block(body, indent_level);
+            }
+            Expression::Call(function, arguments, _) => {
+                self.expression(function, &Precedence::Call, indent_level);
+                self.out.push('(');
+                self.comma_separated(arguments, indent_level);
+                self.out.push(')');
+            }
+        }
+        if grouped {
+            self.out.push(')');
+        }
+    }
+
+    fn comma_separated(&mut self, exps: &[Expression], indent_level: usize) {
+        for (i, exp) in exps.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expression(exp, &Precedence::Lowest, indent_level);
+        }
+    }
+
+    // Writes the comments before `line` on lines of their own.
+    fn comments_before(&mut self, line: usize, indent_level: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.line >= line {
+                break;
+            }
+            self.comment(comment, indent_level);
+        }
+    }
+
+    fn comment(&mut self, comment: &Comment, indent_level: usize) {
+        self.blank_line(comment.line);
+        self.out.push_str(&indent(indent_level));
+        self.out.push_str(&format!("//{}", comment.text));
+        self.out.push('\n');
+        self.block_start = false;
+        self.next_comment += 1;
+    }
+
+    // Keeps the blank line before `line` in the source, if there is one.
+    fn blank_line(&mut self, line: usize) {
+        let is_blank = line >= 2
+            && self
+                .source
+                .get(line - 2)
+                .is_some_and(|source| source.trim().is_empty());
+        if is_blank && !self.block_start {
+            self.out.push('\n');
+        }
+    }
+
+    fn newline(&mut self) {
+        if let Some(comment) = self.trailing.take() {
+            self.out.push_str(&format!(" //{}", comment.text));
+        }
+        self.out.push('\n');
+    }
+}
+
+// Whether `exp` starts with a token that can continue an expression, like `-` or `(`.
+fn starts_with_operator(exp: &Expression) -> bool {
+    match exp {
+        Expression::Prefix(Prefix::Minus, _) | Expression::Array(_) => true,
+        Expression::Infix(operator, left, _) => {
+            binding(left) < infix_binding(operator) || starts_with_operator(left)
+        }
+        Expression::Call(left, _, _) | Expression::Index(left, _) => {
+            binding(left) < Precedence::Call || starts_with_operator(left)
+        }
+        Expression::Assign(left, _) => starts_with_operator(left),
+        _ => false,
+    }
+}
+
+// Whether `exp`, written as the operand of a prefix `operator`, starts with the same operator.
+fn starts_with_prefix(operator: &Prefix, exp: &Expression) -> bool {
+    match (operator, exp) {
+        (Prefix::Minus, Expression::Prefix(Prefix::Minus, _))
+        | (Prefix::Bang, Expression::Prefix(Prefix::Bang, _)) => true,
+        (Prefix::Minus, Expression::IntegerLiteral(value)) => *value < 0,
+        (Prefix::Minus, Expression::FloatLiteral(value)) => value.is_sign_negative(),
+        _ => false,
+    }
+}
+
+// How tightly an expression binds, as the parser sees it. Anything that isn't an operator
+// binds like a literal.
+fn binding(exp: &Expression) -> Precedence {
+    match exp {
+        Expression::Infix(operator, _, _) => infix_binding(operator),
+        Expression::Assign(_, _) => Precedence::Assign,
+        Expression::Prefix(_, _) => Precedence::Prefix,
+        Expression::Call(_, _, _) => Precedence::Call,
+        _ => Precedence::Index,
+    }
+}
+
+fn infix_binding(operator: &Infix) -> Precedence {
+    match operator {
+        Infix::Assign => Precedence::Assign,
+        Infix::Eq | Infix::NotEq => Precedence::Equals,
+        Infix::Lt | Infix::Gt => Precedence::LessGreater,
+        Infix::Plus | Infix::Minus => Precedence::Sum,
+        Infix::Asterisk | Infix::Slash => Precedence::Product,
+    }
+}
+
+// The precedence right above `precedence`, for the right side of left associative operators.
+fn next(precedence: &Precedence) -> Precedence {
+    match precedence {
+        Precedence::Assign => Precedence::Lowest,
+        Precedence::Lowest => Precedence::Equals,
+        Precedence::Equals => Precedence::LessGreater,
+        Precedence::LessGreater => Precedence::Sum,
+        Precedence::Sum => Precedence::Product,
+        Precedence::Product => Precedence::Prefix,
+        Precedence::Prefix => Precedence::Call,
+        Precedence::Call | Precedence::Index => Precedence::Index,
+    }
+}
+
+// Floats always have a `.`, otherwise they would be read back as integers.
+pub(crate) fn float(value: f64) -> String {
+    let s = value.to_string();
+    if s.contains('.') {
+        s
+    } else {
+        format!("{}.0", s)
+    }
+}
+
+fn indent(indent_level: usize) -> String {
+    " ".repeat(indent_level * SPACES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn canonical() {
+        let input = r#"int   x;
+real y;string s;
+
+
+x<-1+2*3;
+let f=funcao(a,b){retorne a+b;};
+se(x==7){imprima("sete");}senao{
+  imprima ( "outro" ) ;
+}
+enquanto(x>0){x=x-1;}
+let h = {"a": 1, "b": [1, 2.0, VERDADEIRO]};
+imprima(f(1,2)*(3-x));
+se (x) {}
+leia x;
+imprima;
+"#;
+        let expected = r#"int x;
+real y;
+string s;
+
+x = 1 + 2 * 3;
+let f = funcao(a, b) {
+    retorne a + b;
+};
+se (x == 7) {
+    imprima("sete");
+} senao {
+    imprima("outro");
+}
+enquanto (x > 0) {
+    x = x - 1;
+}
+let h = {"a": 1, "b": [1, 2.0, VERDADEIRO]};
+imprima(f(1, 2) * (3 - x));
+se (x) {}
+leia x;
+imprima;
+"#;
+        assert_eq!(format(input).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn comments() {
+        let input = r#"// cabecalho
+
+int x; // contador
+se (x == 0) { // zero
+        // dentro
+    x = 1;   // um
+
+    // antes do fim
+} // fim se
+let f = funcao() {
+    // vazio
+};
+// fim
+"#;
+        let expected = r#"// cabecalho
+
+int x; // contador
+se (x == 0) { // zero
+    // dentro
+    x = 1; // um
+
+    // antes do fim
+} // fim se
+let f = funcao() {
+    // vazio
+};
+// fim
+"#;
+        assert_eq!(format(input).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn same_program() {
+        let tests = vec![
+            "x = (1 + 2) * 3 - (4 - 5) - 6;",
+            "x = -(1 + 2) + --3 * !VERDADEIRO;",
+            "imprima((f)(1)[0] + [1][0] + (a < b) == (c > d));",
+            "let f = funcao(x) { se (x) { 1 } senao { 2 } }(1);",
+            "x = 1.0 / 3.5 + 10.0;",
+            "se (x) { y; };\n-1;",
+            "enquanto (x) { y; };\n(a + b)(c);",
+            "se (x) { y; }\n!a;",
+            "se (x) { y; }\n-1;",
+        ];
+        for input in tests {
+            let formatted = format(input).unwrap();
+            assert_eq!(parse(&formatted), parse(input), "{}", formatted);
+            assert_eq!(format(&formatted).unwrap(), formatted);
+        }
+    }
+
+    #[test]
+    fn nested_prefixes() {
+        assert_eq!(
+            format("x = -(-1) + -(-y) - !(!z) * -(!w);").unwrap(),
+            "x = - -1 + - -y - ! !z * -!w;\n"
+        );
+    }
+
+    #[test]
+    fn semicolons() {
+        assert_eq!(
+            format("se (x) { y; }; -1;").unwrap(),
+            "se (x) {\n    y;\n};\n-1;\n"
+        );
+        assert_eq!(
+            format("se (x) { y; }; z;").unwrap(),
+            "se (x) {\n    y;\n}\nz;\n"
+        );
+    }
+
+    fn parse(input: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        // `Display` shows the structure, without the lines.
+        program.to_string()
+    }
+}
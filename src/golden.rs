@@ -0,0 +1,305 @@
+// Golden tests for Portugol programs. Each `<name>.por` is checked against the files next to
+// it: `<name>.out` has what the program prints with the input in `<name>.in` (which can be
+// missing), and `<name>.py` has the code transpiled to Python. The Python code is also run with
+// `python3`, when it's installed, and has to print the same as `.out`.
+use crate::ast::Program;
+use crate::evaluator::Evaluator;
+use crate::object::{Environment, EvalError};
+use crate::semantic;
+use crate::transpiler;
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::thread;
+
+// Enough for any example, and stops programs that never end.
+const STEP_LIMIT: u64 = 10_000_000;
+
+const PYTHON: &str = "python3";
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Status {
+    Matches,
+    // The diff between the golden and the actual result.
+    Differs(String),
+    Missing,
+    // The golden was written with `bless`.
+    Updated,
+    // The interpreter to run the transpiled code isn't installed.
+    Skipped,
+}
+
+#[derive(Debug)]
+pub struct GoldenResult {
+    pub golden: PathBuf,
+    // The interpreter that ran the transpiled code, when it's compared with the golden
+    // instead of what yapc gives.
+    pub interpreter: Option<&'static str>,
+    pub status: Status,
+}
+
+impl GoldenResult {
+    pub fn passed(&self) -> bool {
+        matches!(
+            self.status,
+            Status::Matches | Status::Updated | Status::Skipped
+        )
+    }
+}
+
+// What a program printed when evaluated.
+struct Run {
+    printed: String,
+    // The traceback and the error after what it printed when it fails, like `yapc run`
+    // shows them.
+    error: Option<String>,
+    // It didn't end within `STEP_LIMIT`.
+    endless: bool,
+}
+
+impl Run {
+    fn output(&self) -> String {
+        format!("{}{}", self.printed, self.error.as_deref().unwrap_or(""))
+    }
+}
+
+// Every `.por` file under `path`, which can also be a file itself. Sorted.
+pub fn discover(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut programs = vec![];
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            programs.extend(discover(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "por") {
+            programs.push(path);
+        }
+    }
+    programs.sort();
+    Ok(programs)
+}
+
+// Checks the goldens of a program. With `bless`, goldens that are missing or differ are
+// written instead.
+pub fn check(program: &Path, bless: bool) -> io::Result<Vec<GoldenResult>> {
+    let source = fs::read_to_string(program)?;
+    let input_path = program.with_extension("in");
+    let input = if input_path.exists() {
+        fs::read_to_string(input_path)?
+    } else {
+        String::new()
+    };
+
+    // A program that doesn't compile only has its error in `.out`.
+    let mut actual = vec![];
+    let mut python = None;
+    match semantic::analyze(&source) {
+        Ok(program_ast) => {
+            let run = evaluate(&program_ast, &input);
+            actual.push(("out", run.output()));
+            let code = transpiler::backend("python", program_ast)
+                .map(|backend| backend.transpile_to_string())
+                .map_err(|err| io::Error::other(err.to_string()))?;
+            actual.push(("py", code.clone()));
+            // It wouldn't end in Python either.
+            if !run.endless {
+                python = Some((code, run));
+            }
+        }
+        Err(err) => actual.push(("out", format!("{}\n", err))),
+    }
+
+    let mut results = vec![];
+    for (extension, actual) in actual {
+        let golden = program.with_extension(extension);
+        let expected = if golden.exists() {
+            Some(fs::read_to_string(&golden)?)
+        } else {
+            None
+        };
+        let status = match expected {
+            Some(expected) if expected == actual => Status::Matches,
+            _ if bless => {
+                fs::write(&golden, actual)?;
+                Status::Updated
+            }
+            Some(expected) => Status::Differs(diff(&expected, &actual)),
+            None => Status::Missing,
+        };
+        results.push(GoldenResult {
+            golden,
+            interpreter: None,
+            status,
+        });
+    }
+    // After the loop, so that it's compared with a `.out` just blessed.
+    if let Some((code, run)) = python {
+        results.push(run_python(program, &code, &input, &run)?);
+    }
+    Ok(results)
+}
+
+fn evaluate(program: &Program, input: &str) -> Run {
+    let mut input = input.as_bytes();
+    let mut output = vec![];
+    let mut evaluator = Evaluator::new(&mut input, &mut output);
+    evaluator.set_step_limit(STEP_LIMIT);
+    let result = evaluator.eval(program, Rc::new(RefCell::new(Environment::new())));
+    let traceback = evaluator.traceback().cloned();
+    let mut run = Run {
+        printed: String::from_utf8_lossy(&output).into_owned(),
+        error: None,
+        endless: matches!(result, Err(EvalError::StepLimit(_))),
+    };
+    if let Err(err) = result {
+        let mut error = String::from("\n");
+        if let Some(traceback) = traceback {
+            error.push_str(&traceback.to_string());
+        }
+        error.push_str(&format!("ERRO DE EXECUÇÃO: {}\n", err));
+        run.error = Some(error);
+    }
+    run
+}
+
+// Runs the Python code with the same input and compares what it prints with `.out`. When the
+// program fails in yapc, it has to fail in Python too, after printing the same. Its traceback
+// and error are Python's, so they aren't compared.
+fn run_python(program: &Path, code: &str, input: &str, run: &Run) -> io::Result<GoldenResult> {
+    let golden = program.with_extension("out");
+    let result = |status| GoldenResult {
+        golden: golden.clone(),
+        interpreter: Some(PYTHON),
+        status,
+    };
+    if !golden.exists() {
+        return Ok(result(Status::Missing));
+    }
+    let expected = fs::read_to_string(&golden)?;
+    let expected = match &run.error {
+        Some(error) => expected.strip_suffix(error.as_str()).unwrap_or(&expected),
+        None => &expected,
+    };
+
+    let child = Command::new(PYTHON)
+        .arg("-c")
+        .arg(code)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(result(Status::Skipped)),
+        Err(err) => return Err(err),
+    };
+    // From another thread, so that a program that prints a lot before reading can't block
+    // both.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    // The program can end without reading all the input.
+    let _ = writer.join();
+
+    let mut differences = diff(expected, &String::from_utf8_lossy(&output.stdout));
+    match (run.error.is_some(), output.status.success()) {
+        (true, true) => differences.push_str("o programa deveria terminar com erro\n"),
+        (false, false) => differences.push_str(&format!(
+            "o programa terminou com erro:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        )),
+        _ => {}
+    }
+    Ok(result(if differences.is_empty() {
+        Status::Matches
+    } else {
+        Status::Differs(differences)
+    }))
+}
+
+// The lines that differ, compared by position: `-` for the expected line and `+` for the
+// actual one.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected = expected.split('\n').collect::<Vec<&str>>();
+    let actual = actual.split('\n').collect::<Vec<&str>>();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (expected, actual) = (expected.get(i), actual.get(i));
+        if expected == actual {
+            continue;
+        }
+        out.push_str(&format!("linha {}:\n", i + 1));
+        if let Some(line) = expected {
+            out.push_str(&format!("-{}\n", line));
+        }
+        if let Some(line) = actual {
+            out.push_str(&format!("+{}\n", line));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, diff, Status};
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    fn bless() {
+        let dir = std::env::temp_dir().join(format!("yapc-golden-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("dobro.por");
+        fs::write(&program, "int x; leia x; imprima(x * 2);").unwrap();
+        fs::write(dir.join("dobro.in"), "21\n").unwrap();
+
+        let statuses = |bless| {
+            check(&program, bless)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.status)
+                .collect::<Vec<Status>>()
+        };
+        // Without python3, the Python code isn't run.
+        let installed = Command::new("python3").arg("--version").output().is_ok();
+        let python = |status| if installed { status } else { Status::Skipped };
+        assert_eq!(
+            statuses(false),
+            vec![Status::Missing, Status::Missing, Status::Missing]
+        );
+        assert_eq!(
+            statuses(true),
+            vec![Status::Updated, Status::Updated, python(Status::Matches)]
+        );
+        assert_eq!(fs::read_to_string(dir.join("dobro.out")).unwrap(), "42");
+        assert_eq!(
+            statuses(false),
+            vec![Status::Matches, Status::Matches, python(Status::Matches)]
+        );
+
+        fs::write(dir.join("dobro.in"), "1\n").unwrap();
+        let differs = Status::Differs("linha 1:\n-42\n+2\n".to_string());
+        assert_eq!(
+            statuses(false),
+            vec![differs.clone(), Status::Matches, python(differs)]
+        );
+
+        // A program that fails has to fail in Python too.
+        fs::write(&program, "int x; leia x; imprima(x); imprima(1 / (x - 1));").unwrap();
+        assert_eq!(statuses(true)[2], python(Status::Matches));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn diffs() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), "");
+        assert_eq!(diff("a\nb\n", "a\nc\n"), "linha 2:\n-b\n+c\n");
+        assert_eq!(diff("a\n", "a\nb\n"), "linha 2:\n-\n+b\nlinha 3:\n+\n");
+    }
+}
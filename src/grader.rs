@@ -0,0 +1,402 @@
+// Runs a program against test cases, each an input and the output it should print, and
+// reports which ones pass.
+use crate::ast::Program;
+use crate::evaluator::{self, Evaluator};
+use crate::object::{Environment, EvalError};
+use crate::semantic;
+use crate::transpiler::json_string;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub struct TestCase {
+    pub name: String,
+    pub input: String,
+    pub expected: String,
+}
+
+// Loads every `<name>.out` in `dir` with the input in `<name>.in`, which can be missing when
+// the program doesn't read anything. Sorted by name.
+pub fn load_cases(dir: &Path) -> io::Result<Vec<TestCase>> {
+    let mut cases = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "out") {
+            continue;
+        }
+        let input_path = path.with_extension("in");
+        let input = if input_path.exists() {
+            fs::read_to_string(input_path)?
+        } else {
+            String::new()
+        };
+        cases.push(TestCase {
+            name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+            input,
+            expected: fs::read_to_string(&path)?,
+        });
+    }
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+// How whitespace differences between the output and the expected one are treated.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Whitespace {
+    Exact,
+    // Ignores whitespace at the end of lines and empty lines at the end.
+    Trailing,
+    // Ignores any difference in whitespace between words.
+    Collapse,
+}
+
+pub struct Config {
+    pub step_limit: u64,
+    pub time_limit: Duration,
+    pub whitespace: Whitespace,
+    pub ignore_case: bool,
+    // Words that are both numbers match when they differ by at most this. Words are compared
+    // one by one, so whitespace is then ignored like with `Whitespace::Collapse`.
+    pub float_tolerance: Option<f64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            step_limit: 1_000_000,
+            time_limit: Duration::from_secs(1),
+            whitespace: Whitespace::Trailing,
+            ignore_case: false,
+            float_tolerance: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Status {
+    Passed,
+    WrongOutput,
+    RuntimeError,
+    StepLimit,
+    TimeLimit,
+    // The program doesn't parse or is rejected by the semantic analysis.
+    CompileError,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Passed => write!(f, "passed"),
+            Status::WrongOutput => write!(f, "wrong_output"),
+            Status::RuntimeError => write!(f, "runtime_error"),
+            Status::StepLimit => write!(f, "step_limit"),
+            Status::TimeLimit => write!(f, "time_limit"),
+            Status::CompileError => write!(f, "compile_error"),
+        }
+    }
+}
+
+pub struct TestResult {
+    pub name: String,
+    pub status: Status,
+    pub steps: u64,
+    pub time: Duration,
+    // The error, empty when the program ran to the end.
+    pub message: String,
+    // The calls that led to the error, like `yapc run` shows them. Empty when the program
+    // ran to the end or didn't compile.
+    pub traceback: String,
+    pub output: String,
+}
+
+// Runs the program once per test case, each time in a new environment.
+pub fn grade(source: &str, cases: &[TestCase], config: &Config) -> Vec<TestResult> {
+    let program = match semantic::analyze(source) {
+        Ok(program) => program,
+        Err(err) => {
+            return cases
+                .iter()
+                .map(|case| TestResult {
+                    name: case.name.clone(),
+                    status: Status::CompileError,
+                    steps: 0,
+                    time: Duration::ZERO,
+                    message: err.to_string(),
+                    traceback: String::new(),
+                    output: String::new(),
+                })
+                .collect()
+        }
+    };
+    // A runaway recursion is a runtime error of its test case, not a crash of the grader.
+    evaluator::with_stack(|| {
+        cases
+            .iter()
+            .map(|case| run_case(&program, case, config))
+            .collect()
+    })
+}
+
+fn run_case(program: &Program, case: &TestCase, config: &Config) -> TestResult {
+    let mut input = case.input.as_bytes();
+    let mut output = vec![];
+    let mut evaluator = Evaluator::new(&mut input, &mut output);
+    evaluator.set_step_limit(config.step_limit);
+    evaluator.set_time_limit(config.time_limit);
+    let start = Instant::now();
+    let result = evaluator.eval(program, Rc::new(RefCell::new(Environment::new())));
+    let time = start.elapsed();
+    let steps = evaluator.steps();
+    let traceback = match (&result, evaluator.traceback()) {
+        (Err(_), Some(traceback)) => traceback.to_string(),
+        _ => String::new(),
+    };
+    let output = String::from_utf8_lossy(&output).into_owned();
+    let (status, message) = match result {
+        Ok(_) if matches(&output, &case.expected, config) => (Status::Passed, String::new()),
+        Ok(_) => (Status::WrongOutput, String::new()),
+        Err(err @ EvalError::StepLimit(_)) => (Status::StepLimit, err.to_string()),
+        Err(err @ EvalError::TimeLimit(_)) => (Status::TimeLimit, err.to_string()),
+        Err(err) => (Status::RuntimeError, err.to_string()),
+    };
+    TestResult {
+        name: case.name.clone(),
+        status,
+        steps,
+        time,
+        message,
+        traceback,
+        output,
+    }
+}
+
+// Whether the output matches the expected one after normalizing both.
+pub fn matches(output: &str, expected: &str, config: &Config) -> bool {
+    let output = normalize(output, config);
+    let expected = normalize(expected, config);
+    let tolerance = match config.float_tolerance {
+        Some(tolerance) => tolerance,
+        None => return output == expected,
+    };
+    let output = output.split_whitespace().collect::<Vec<&str>>();
+    let expected = expected.split_whitespace().collect::<Vec<&str>>();
+    output.len() == expected.len()
+        && output
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| match (a.parse::<f64>(), b.parse::<f64>()) {
+                (Ok(a), Ok(b)) => (a - b).abs() <= tolerance,
+                _ => a == b,
+            })
+}
+
+fn normalize(text: &str, config: &Config) -> String {
+    let text = match config.whitespace {
+        Whitespace::Exact => text.to_string(),
+        Whitespace::Trailing => text
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<&str>>()
+            .join("\n")
+            .trim_end()
+            .to_string(),
+        Whitespace::Collapse => text.split_whitespace().collect::<Vec<&str>>().join(" "),
+    };
+    if config.ignore_case {
+        text.to_lowercase()
+    } else {
+        text
+    }
+}
+
+pub fn to_json(results: &[TestResult]) -> String {
+    let tests = results
+        .iter()
+        .map(|result| {
+            format!(
+                "  {{\"name\": {}, \"status\": \"{}\", \"steps\": {}, \"time_ms\": {}, \"message\": {}, \"traceback\": {}}}",
+                json_string(&result.name),
+                result.status,
+                result.steps,
+                result.time.as_millis(),
+                json_string(&result.message),
+                json_string(&result.traceback)
+            )
+        })
+        .collect::<Vec<String>>();
+    format!("[\n{}\n]\n", tests.join(",\n"))
+}
+
+pub fn to_csv(results: &[TestResult]) -> String {
+    let mut csv = String::from("name,status,steps,time_ms,message,traceback\n");
+    for result in results {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_field(&result.name),
+            result.status,
+            result.steps,
+            result.time.as_millis(),
+            csv_field(&result.message),
+            csv_field(&result.traceback)
+        ));
+    }
+    csv
+}
+
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{grade, matches, to_csv, to_json, Config, Status, TestCase, Whitespace};
+
+    fn case(name: &str, input: &str, expected: &str) -> TestCase {
+        TestCase {
+            name: name.to_string(),
+            input: input.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+
+    #[test]
+    fn statuses() {
+        let source = "int x; leia x; enquanto (x < 0) {} imprima(100 / x); imprima;";
+        let cases = vec![
+            case("certo", "4\n", "25\n"),
+            case("errado", "5\n", "25\n"),
+            case("zero", "0\n", ""),
+            case("laco", "-1\n", ""),
+            case("vazio", "", ""),
+        ];
+        let config = Config {
+            step_limit: 1000,
+            ..Config::default()
+        };
+        let results = grade(source, &cases, &config);
+        let statuses = results.iter().map(|r| r.status).collect::<Vec<Status>>();
+        assert_eq!(
+            statuses,
+            vec![
+                Status::Passed,
+                Status::WrongOutput,
+                Status::RuntimeError,
+                Status::StepLimit,
+                Status::RuntimeError,
+            ]
+        );
+        assert_eq!(results[1].output, "20\n");
+        assert_eq!(results[2].message, "division by zero");
+        assert_eq!(
+            results[2].traceback,
+            "Rastreamento (chamada mais recente por último):\n  linha 1, em <programa>\n"
+        );
+        assert_eq!(results[0].traceback, "");
+
+        let results = grade("imprima(;", &cases[..1], &config);
+        assert_eq!(results[0].status, Status::CompileError);
+    }
+
+    #[test]
+    fn runaway_programs() {
+        let cases = vec![case("um", "", "")];
+        let config = Config::default();
+        let tests = vec![
+            (
+                "let f = funcao(n) { f(n + 1) }; f(0);",
+                "stack overflow: more than 1000 nested calls",
+            ),
+            (
+                "let f = funcao(n) { se (n == 0) { 0 } senao { 1 + f(n - 1) } }; f(3000);",
+                "stack overflow: more than 1000 nested calls",
+            ),
+            (
+                "int x; x = 9223372036854775807; imprima(x + 1);",
+                "integer overflow: 9223372036854775807 + 1",
+            ),
+            (
+                "int x; x = 0 - 9223372036854775807 - 1; imprima(x / -1);",
+                "integer overflow: -9223372036854775808 / -1",
+            ),
+            (
+                "int x; x = 3037000500; imprima(x * x);",
+                "integer overflow: 3037000500 * 3037000500",
+            ),
+        ];
+        for (source, message) in tests {
+            let results = grade(source, &cases, &config);
+            assert_eq!(results[0].status, Status::RuntimeError, "for `{}`", source);
+            assert_eq!(results[0].message, message, "for `{}`", source);
+        }
+    }
+
+    #[test]
+    fn normalization() {
+        let exact = Config {
+            whitespace: Whitespace::Exact,
+            ..Config::default()
+        };
+        let trailing = Config::default();
+        let collapse = Config {
+            whitespace: Whitespace::Collapse,
+            ..Config::default()
+        };
+        let ignore_case = Config {
+            ignore_case: true,
+            ..Config::default()
+        };
+        let tolerance = Config {
+            float_tolerance: Some(0.01),
+            ..Config::default()
+        };
+        let tests = vec![
+            ("a b\n", "a b\n", &exact, true),
+            ("a b \n", "a b\n", &exact, false),
+            ("a b \r\nc\n\n", "a b\nc", &trailing, true),
+            (" a  b", "a b", &trailing, false),
+            ("a\n  b\n", "a b", &collapse, true),
+            ("Media: 3", "media: 3", &trailing, false),
+            ("Media: 3", "media: 3", &ignore_case, true),
+            ("media 3.333\n", "media 3.33", &tolerance, true),
+            ("media 3.4", "media 3.33", &tolerance, false),
+            ("Media 3.333", "media 3.33", &tolerance, false),
+        ];
+        for (output, expected, config, result) in tests {
+            assert_eq!(
+                matches(output, expected, config),
+                result,
+                "{:?} {:?}",
+                output,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn reports() {
+        let cases = vec![case("um", "", "1"), case("a,\"b\"", "", "2")];
+        let results = grade("imprima(1);", &cases, &Config::default());
+        let csv = to_csv(&results);
+        let lines = csv.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "name,status,steps,time_ms,message,traceback");
+        assert!(lines[1].starts_with("um,passed,1,"), "{}", csv);
+        assert!(
+            lines[2].starts_with("\"a,\"\"b\"\"\",wrong_output,1,"),
+            "{}",
+            csv
+        );
+
+        let json = to_json(&results);
+        assert!(json.starts_with("[\n  {\"name\": \"um\", \"status\": \"passed\", \"steps\": 1,"));
+        assert!(json.contains("{\"name\": \"a,\\\"b\\\"\", \"status\": \"wrong_output\""));
+        assert!(json.ends_with("\"message\": \"\", \"traceback\": \"\"}\n]\n"));
+    }
+}
@@ -12,6 +12,27 @@ pub struct Lexer {
     // Use `Chars` to support UTF-8.
     // https://stackoverflow.com/questions/43952104/how-can-i-store-a-chars-iterator-in-the-same-struct-as-the-string-it-is-iteratin
     chars: Peekable<Chars<'static>>,
+    // Line and column of the current char, starting at 1.
+    line: usize,
+    column: usize,
+    // Line and column where the last token starts.
+    token_line: usize,
+    token_column: usize,
+    // Number of `{` and `}` read so far.
+    braces: usize,
+    comments: Vec<Comment>,
+}
+
+// A `//` comment. The parser skips comments, they are only kept for the formatter.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    pub line: usize,
+    // The text after `//`.
+    pub text: String,
+    // Whether there is code before the comment on its line.
+    pub trailing: bool,
+    // Number of `{` and `}` before the comment, which tells the block it is in.
+    pub braces: usize,
 }
 
 impl Lexer {
@@ -22,6 +43,12 @@ impl Lexer {
             position: 0,
             ch: '\u{0}',
             chars,
+            line: 1,
+            column: 1,
+            token_line: 1,
+            token_column: 1,
+            braces: 0,
+            comments: vec![],
         };
         lexer.read_char();
         lexer
@@ -31,8 +58,25 @@ impl Lexer {
         &self.input
     }
 
+    // Line where the last token returned by `next_token` starts.
+    pub fn token_line(&self) -> usize {
+        self.token_line
+    }
+
+    // Column where the last token returned by `next_token` starts, counting chars.
+    pub fn token_column(&self) -> usize {
+        self.token_column
+    }
+
+    // The comments read so far.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
     pub fn next_token(&mut self) -> Token {
         self.skip_whitespace();
+        self.token_line = self.line;
+        self.token_column = self.column;
 
         let tok: Token;
         match self.ch {
@@ -91,9 +135,11 @@ impl Lexer {
                 tok = Token::Gt;
             }
             '{' => {
+                self.braces += 1;
                 tok = Token::Lbrace;
             }
             '}' => {
+                self.braces += 1;
                 tok = Token::Rbrace;
             }
             '[' => {
@@ -175,15 +221,44 @@ impl Lexer {
         &self.input[position..self.position]
     }
 
+    // Skips comments too.
     fn skip_whitespace(&mut self) {
-        while is_whitespace(self.ch) {
+        loop {
+            while is_whitespace(self.ch) {
+                self.read_char();
+            }
+            if self.ch == '/' && self.peek_char() == '/' {
+                self.read_comment();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn read_comment(&mut self) {
+        let line_start = self.input[..self.position].rfind('\n').map_or(0, |i| i + 1);
+        let trailing = !self.input[line_start..self.position].trim().is_empty();
+        let start = self.position + "//".len();
+        while self.ch != '\n' && self.ch != '\u{0}' {
             self.read_char();
         }
+        self.comments.push(Comment {
+            line: self.line,
+            text: self.input[start..self.position].trim_end().to_string(),
+            trailing,
+            braces: self.braces,
+        });
     }
 
     // -- Low-level methods that touches the `Chars`.
 
     fn read_char(&mut self) {
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else if self.ch != '\u{0}' {
+            self.column += 1;
+        }
         self.position += if self.ch == '\u{0}' {
             0
         } else {
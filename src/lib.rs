@@ -1,8 +1,20 @@
 pub mod ast;
+pub mod cfg;
 pub mod code;
+pub mod compiler;
+pub mod debugger;
 pub mod evaluator;
+pub mod flowchart;
+pub mod fold;
+pub mod formatter;
+pub mod golden;
+pub mod grader;
 pub mod lexer;
 pub mod object;
+pub mod optimizer;
 pub mod parser;
+pub mod repl;
+pub mod semantic;
 pub mod token;
+pub mod trace;
 pub mod transpiler;
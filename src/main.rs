@@ -1,52 +1,677 @@
-use yapc::evaluator;
-use yapc::lexer::Lexer;
-use yapc::object::Environment;
-use yapc::parser::Parser;
-use yapc::transpiler::Transpiler;
-//use yapc::token::Token;
 use std::cell::RefCell;
 use std::env;
 use std::fs;
+use std::io::{self, BufReader, IsTerminal, Read, Write};
+use std::path::Path;
 use std::process;
 use std::rc::Rc;
+use std::time::Duration;
+use yapc::ast::Program;
+use yapc::code;
+use yapc::compiler;
+use yapc::debugger::{Debugger, SharedInput};
+use yapc::evaluator::{self, Evaluator};
+use yapc::flowchart::Flowchart;
+use yapc::formatter;
+use yapc::golden;
+use yapc::grader::{self, Whitespace};
+use yapc::lexer::Lexer;
+use yapc::object::{Environment, EvalError};
+use yapc::optimizer::{self, OptLevel};
+use yapc::repl::Repl;
+use yapc::semantic::{self, AnalysisError};
+use yapc::token::Token;
+use yapc::trace;
+use yapc::transpiler::{self, SourceMap, TARGETS};
+
+// Exit codes, the same for every command.
+// The Portugol program has errors: it doesn't parse, is rejected by the semantic analysis,
+// fails while running, or can't be transpiled.
+const PROGRAM_ERROR: i32 = 1;
+// The command line is wrong.
+const USAGE_ERROR: i32 = 2;
+
+const USAGE: &str = "uso: yapc <comando> [opções] <arquivo>
+
+o arquivo `-`, ou nenhum quando a entrada padrão não é um terminal, lê o programa da entrada
+padrão.
+
+comandos:
+    run        interpreta o programa [--input <arquivo>]
+    repl       abre o interpretador interativo
+    transpile  gera o código na linguagem alvo
+               [--target <linguagem>] [-o <arquivo>] [--dump-names] [--annotate]
+               [--source-map <arquivo>] [--rewrite-traceback <source map>]
+    check      verifica a sintaxe e a semântica, sem executar
+    tokens     lista os tokens e suas posições
+    ast        mostra a árvore sintática
+    disasm     compila o programa para bytecode e mostra a listagem das instruções
+               [-O0|-O1]
+    flowchart  gera o fluxograma do programa no formato DOT do Graphviz [-o <arquivo>]
+    fmt        formata os arquivos [--check]
+    grade      executa o programa com os casos de teste de um diretório
+               <arquivo> <diretório> [--steps <n>] [--timeout <ms>]
+               [--whitespace exact|trailing|collapse] [--ignore-case]
+               [--float-tolerance <x>] [--format json|csv] [-o <arquivo>]
+    trace      executa o programa e mostra o teste de mesa: a linha, o comando e as
+               variáveis depois de cada comando. o que o programa imprime vai para a
+               saída de erro
+               [--format text|markdown|csv] [--input <arquivo>] [--steps <n>]
+    debug      executa o programa passo a passo, lendo os comandos do depurador da entrada
+               padrão (digite ajuda para ver os comandos)
+               [--input <arquivo>] [--break <linha>]...
+    test       compara os programas .por com os arquivos .out e .py ao lado deles
+               [<arquivo ou diretório>...] [--bless]";
 
 fn main() {
-    let filename = env::args().nth(1).expect("Arquivo de input não inserido");
-    let contents = fs::read_to_string(filename).expect("Erro ao ler arquivo");
+    let mut args = env::args().skip(1);
+    let command = args
+        .next()
+        .unwrap_or_else(|| usage_error("comando não inserido"));
+    let args = args.collect::<Vec<String>>();
+    // Deep recursions in the program stop with an error instead of overflowing the stack.
+    evaluator::with_stack(|| match command.as_str() {
+        "run" => run(&args),
+        "repl" => repl(&args),
+        "transpile" => transpile(&args),
+        "check" => check(&args),
+        "tokens" => tokens(&args),
+        "ast" => ast(&args),
+        "disasm" => disasm(&args),
+        "fmt" => fmt(&args),
+        "flowchart" => flowchart(&args),
+        "grade" => grade(&args),
+        "test" => test(&args),
+        "trace" => trace_program(&args),
+        "debug" => debug(&args),
+        "-h" | "--help" => println!("{}", USAGE),
+        _ => usage_error(&format!("comando desconhecido `{}`", command)),
+    })
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("ERRO: {}", message);
+    eprintln!("{}", USAGE);
+    process::exit(USAGE_ERROR);
+}
+
+// The only argument of commands without options.
+fn filename(args: &[String]) -> String {
+    match args {
+        [filename] => filename.clone(),
+        [] => default_filename(),
+        _ => usage_error("argumentos demais"),
+    }
+}
+
+// Without a file, the program comes from stdin when it is piped or redirected.
+fn default_filename() -> String {
+    if io::stdin().is_terminal() {
+        usage_error("arquivo de input não inserido");
+    }
+    STDIN.to_string()
+}
+
+// The file name that means stdin.
+const STDIN: &str = "-";
+
+fn option_value(args: &mut impl Iterator<Item = String>, option: &str) -> String {
+    args.next()
+        .unwrap_or_else(|| usage_error(&format!("valor de {} não inserido", option)))
+}
+
+// Parses the value of an option, like a number.
+fn parse_option<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, option: &str) -> T {
+    let value = option_value(args, option);
+    value
+        .parse()
+        .unwrap_or_else(|_| usage_error(&format!("valor inválido para {}: `{}`", option, value)))
+}
+
+fn read_source(filename: &str) -> String {
+    let result = if filename == STDIN {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        fs::read_to_string(filename)
+    };
+    result.unwrap_or_else(|err| {
+        eprintln!(
+            "ERRO: não foi possível ler {}: {}",
+            display_name(filename),
+            err
+        );
+        process::exit(USAGE_ERROR);
+    })
+}
+
+// How diagnostics name the file.
+fn display_name(filename: &str) -> &str {
+    if filename == STDIN {
+        "<stdin>"
+    } else {
+        filename
+    }
+}
+
+fn parse(filename: &str) -> Program {
+    semantic::parse(&read_source(filename)).unwrap_or_else(|err| analysis_error(err))
+}
+
+// Parses and checks the program.
+fn analyze(filename: &str) -> Program {
+    semantic::analyze(&read_source(filename)).unwrap_or_else(|err| analysis_error(err))
+}
+
+fn analysis_error(err: AnalysisError) -> ! {
+    eprintln!("{}", err);
+    process::exit(PROGRAM_ERROR);
+}
 
-    /* Debug lexer
-    let mut lexer = Lexer::new(contents);
-    while let token = lexer.next_token() {
+fn run(args: &[String]) {
+    let mut filename = None;
+    let mut input_file = None;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input_file = Some(option_value(&mut args, &arg)),
+            _ if arg.starts_with('-') && arg != STDIN => {
+                usage_error(&format!("opção desconhecida `{}`", arg))
+            }
+            _ if filename.is_some() => usage_error("argumentos demais"),
+            _ => filename = Some(arg),
+        }
+    }
+    // Read the program first, stdin can't be locked for `leia` while it is read.
+    let program = analyze(&filename.unwrap_or_else(default_filename));
+    let mut input = open_input(input_file);
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let mut output = io::stdout();
+    let mut evaluator = Evaluator::new(&mut input, &mut output);
+    if let Err(err) = evaluator.eval(&program, env) {
+        runtime_error(&evaluator, &err);
+        process::exit(PROGRAM_ERROR);
+    }
+}
+
+// Prints a runtime error after its traceback, like Python.
+fn runtime_error(evaluator: &Evaluator, err: &EvalError) {
+    // Keep the error after what the program printed.
+    let _ = io::stdout().flush();
+    if let Some(traceback) = evaluator.traceback() {
+        eprint!("{}", traceback);
+    }
+    eprintln!("ERRO DE EXECUÇÃO: {}", err);
+}
+
+fn repl(args: &[String]) {
+    if !args.is_empty() {
+        usage_error("argumentos demais");
+    }
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout();
+    if let Err(err) = Repl::new().start(&mut input, &mut output) {
+        eprintln!("ERRO: {}", err);
+        process::exit(PROGRAM_ERROR);
+    }
+}
+
+// Where `leia` reads from: the `--input` file, or what is left in stdin.
+fn open_input(input_file: Option<String>) -> Box<dyn io::BufRead> {
+    match input_file {
+        Some(path) => match fs::File::open(&path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(err) => {
+                eprintln!("ERRO: não foi possível ler {}: {}", path, err);
+                process::exit(USAGE_ERROR);
+            }
+        },
+        None => Box::new(io::stdin().lock()),
+    }
+}
+
+// Reports every semantic error, not only the first one.
+fn check(args: &[String]) {
+    let errors = semantic::check(&analyze(&filename(args)));
+    for err in &errors {
+        eprintln!("ERRO SEMÂNTICO: {}", err);
+    }
+    if !errors.is_empty() {
+        process::exit(PROGRAM_ERROR);
+    }
+}
+
+fn tokens(args: &[String]) {
+    let mut lexer = Lexer::new(read_source(&filename(args)));
+    loop {
+        let token = lexer.next_token();
+        println!(
+            "{}:{}\t{:?}",
+            lexer.token_line(),
+            lexer.token_column(),
+            token
+        );
         if token == Token::Eof {
             break;
         }
-        println!("{:?}", token);
     }
-    */
+}
 
-    let mut parser = Parser::new(Lexer::new(contents));
+fn ast(args: &[String]) {
+    println!("{:#?}", parse(&filename(args)));
+}
 
-    let program = parser.parse_program();
-    if !parser.errors().is_empty() {
-        println!("ERROR: foi encontrado o seguinte erro de parse:");
-        let error = parser.errors().first();
-        println!("\t{:?}", error);
-        process::exit(1);
+// Lists the bytecode of the program, optimized with `-O1`. `-O0` is the default.
+fn disasm(args: &[String]) {
+    let mut filename = None;
+    let mut level = OptLevel::O0;
+    for arg in args {
+        match OptLevel::from_flag(arg) {
+            Some(flag) => level = flag,
+            None if arg.starts_with('-') && arg != STDIN => {
+                usage_error(&format!("opção desconhecida `{}`", arg))
+            }
+            None if filename.is_some() => usage_error("argumentos demais"),
+            None => filename = Some(arg.clone()),
+        }
     }
-    // for debug
-    //println!("{}", program);
+    let program = analyze(&filename.unwrap_or_else(default_filename));
+    match compiler::compile(&program) {
+        Ok(bytecode) => print!(
+            "{}",
+            code::disassemble(&optimizer::optimize(bytecode, level))
+        ),
+        Err(err) => {
+            eprintln!("ERRO DE COMPILAÇÃO: {}", err);
+            process::exit(PROGRAM_ERROR);
+        }
+    }
+}
 
-    // For evaluator
-    let env = Rc::new(RefCell::new(Environment::new()));
+fn transpile(args: &[String]) {
+    let mut filename = None;
+    let mut output = None;
+    let mut dump_names = false;
+    let mut annotate = false;
+    let mut source_map = None;
+    let mut traceback_map = None;
+    let mut target = TARGETS[0].to_string();
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dump-names" => dump_names = true,
+            "--annotate" => annotate = true,
+            "--source-map" => source_map = Some(option_value(&mut args, &arg)),
+            "--rewrite-traceback" => traceback_map = Some(option_value(&mut args, &arg)),
+            "--target" => target = option_value(&mut args, &arg),
+            "-o" => output = Some(option_value(&mut args, &arg)),
+            _ if arg.starts_with('-') && arg != STDIN => {
+                usage_error(&format!("opção desconhecida `{}`", arg))
+            }
+            _ if filename.is_some() => usage_error("argumentos demais"),
+            _ => filename = Some(arg),
+        }
+    }
+    if let Some(path) = traceback_map {
+        rewrite_traceback(&path);
+        return;
+    }
+    let filename = filename.unwrap_or_else(default_filename);
+    let program = analyze(&filename);
 
-    match evaluator::eval(&program, Rc::clone(&env)) {
-        Ok(_obj) => {}
+    let mut transpiler = match transpiler::backend(&target, program) {
+        Ok(transpiler) => transpiler,
         Err(err) => {
-            println!("ERRO SEMÂNTICO: {}", err);
-            process::exit(1);
+            eprintln!("ERRO: {}", err);
+            process::exit(PROGRAM_ERROR);
         }
     };
+    if dump_names {
+        eprint!("{}", transpiler.names());
+    }
+    transpiler.set_annotate(annotate || source_map.is_some());
+    let mut code = transpiler.transpile_to_string();
+    if let Some(map_path) = source_map {
+        // The name Python shows in tracebacks.
+        let file = match &output {
+            Some(path) => Path::new(path)
+                .file_name()
+                .map_or(path.clone(), |name| name.to_string_lossy().into_owned()),
+            None => "<stdin>".to_string(),
+        };
+        let (clean, map) =
+            SourceMap::from_annotated(&code, transpiler.comment(), &file, display_name(&filename));
+        if !annotate {
+            code = clean;
+        }
+        if let Err(err) = fs::write(map_path, map.to_json()) {
+            eprintln!("ERRO: não foi possível escrever o source map: {}", err);
+            process::exit(PROGRAM_ERROR);
+        }
+    }
+    let result = match output {
+        Some(path) => fs::write(path, code),
+        None => io::stdout().lock().write_all(code.as_bytes()),
+    };
+    if let Err(err) = result {
+        eprintln!("ERRO: não foi possível escrever o código gerado: {}", err);
+        process::exit(PROGRAM_ERROR);
+    }
+}
+
+// Reads a Python traceback from stdin and prints it with the Portugol file and lines.
+fn rewrite_traceback(map_path: &str) {
+    let map = read_source(map_path);
+    let map = match SourceMap::from_json(&map) {
+        Ok(map) => map,
+        Err(err) => {
+            eprintln!("ERRO: {}", err);
+            process::exit(PROGRAM_ERROR);
+        }
+    };
+    let mut traceback = String::new();
+    io::stdin()
+        .read_to_string(&mut traceback)
+        .expect("Erro ao ler traceback");
+    print!("{}", map.rewrite_traceback(&traceback));
+}
+
+fn flowchart(args: &[String]) {
+    let mut filename = None;
+    let mut output = None;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(option_value(&mut args, &arg)),
+            _ if arg.starts_with('-') && arg != STDIN => {
+                usage_error(&format!("opção desconhecida `{}`", arg))
+            }
+            _ if filename.is_some() => usage_error("argumentos demais"),
+            _ => filename = Some(arg),
+        }
+    }
+    let program = parse(&filename.unwrap_or_else(default_filename));
+    let dot = Flowchart::new(&program).to_dot();
+    let result = match output {
+        Some(path) => fs::write(path, dot),
+        None => io::stdout().lock().write_all(dot.as_bytes()),
+    };
+    if let Err(err) = result {
+        eprintln!("ERRO: não foi possível escrever o fluxograma: {}", err);
+        process::exit(PROGRAM_ERROR);
+    }
+}
+
+// Formats the files in place, or prints the formatted stdin. With `--check`, lists the files
+// that aren't formatted instead, and fails if there is any.
+fn fmt(args: &[String]) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let mut filenames = args
+        .iter()
+        .filter(|arg| *arg != "--check")
+        .cloned()
+        .collect::<Vec<String>>();
+    if filenames.is_empty() {
+        filenames.push(default_filename());
+    }
+    let mut unformatted = false;
+    for filename in &filenames {
+        let contents = read_source(filename);
+        let formatted = match formatter::format(&contents) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprint!("{}: ", display_name(filename));
+                analysis_error(AnalysisError::Syntax(err));
+            }
+        };
+        if filename == STDIN && !check {
+            print!("{}", formatted);
+            continue;
+        }
+        if formatted == contents {
+            continue;
+        }
+        if check {
+            println!("{} não está formatado", display_name(filename));
+            unformatted = true;
+        } else if let Err(err) = fs::write(filename, formatted) {
+            eprintln!("ERRO: não foi possível escrever {}: {}", filename, err);
+            process::exit(PROGRAM_ERROR);
+        }
+    }
+    if unformatted {
+        process::exit(PROGRAM_ERROR);
+    }
+}
+
+// Runs the program with every test case in the directory and writes a report with a line per
+// test. Fails if any test doesn't pass.
+fn grade(args: &[String]) {
+    let mut paths = vec![];
+    let mut config = grader::Config::default();
+    let mut format = "json".to_string();
+    let mut output = None;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => config.step_limit = parse_option(&mut args, &arg),
+            "--timeout" => config.time_limit = Duration::from_millis(parse_option(&mut args, &arg)),
+            "--whitespace" => {
+                config.whitespace = match option_value(&mut args, &arg).as_str() {
+                    "exact" => Whitespace::Exact,
+                    "trailing" => Whitespace::Trailing,
+                    "collapse" => Whitespace::Collapse,
+                    value => usage_error(&format!("valor inválido para --whitespace: `{}`", value)),
+                }
+            }
+            "--ignore-case" => config.ignore_case = true,
+            "--float-tolerance" => config.float_tolerance = Some(parse_option(&mut args, &arg)),
+            "--format" => format = option_value(&mut args, &arg),
+            "-o" => output = Some(option_value(&mut args, &arg)),
+            _ if arg.starts_with('-') && arg != STDIN => {
+                usage_error(&format!("opção desconhecida `{}`", arg))
+            }
+            _ => paths.push(arg),
+        }
+    }
+    let (filename, dir) = match paths.as_slice() {
+        [filename, dir] => (filename, dir),
+        [] | [_] => usage_error("arquivo e diretório de testes não inseridos"),
+        _ => usage_error("argumentos demais"),
+    };
+    if format != "json" && format != "csv" {
+        usage_error(&format!("valor inválido para --format: `{}`", format));
+    }
+    let source = read_source(filename);
+    let cases = grader::load_cases(Path::new(dir)).unwrap_or_else(|err| {
+        eprintln!("ERRO: não foi possível ler os testes de {}: {}", dir, err);
+        process::exit(USAGE_ERROR);
+    });
+    if cases.is_empty() {
+        eprintln!("ERRO: nenhum arquivo .out em {}", dir);
+        process::exit(USAGE_ERROR);
+    }
+
+    let results = grader::grade(&source, &cases, &config);
+    let report = if format == "csv" {
+        grader::to_csv(&results)
+    } else {
+        grader::to_json(&results)
+    };
+    let result = match output {
+        Some(path) => fs::write(path, report),
+        None => io::stdout().lock().write_all(report.as_bytes()),
+    };
+    if let Err(err) = result {
+        eprintln!("ERRO: não foi possível escrever o relatório: {}", err);
+        process::exit(PROGRAM_ERROR);
+    }
+    let passed = results
+        .iter()
+        .filter(|result| result.status == grader::Status::Passed)
+        .count();
+    eprintln!("{}/{} testes passaram", passed, results.len());
+    if passed < results.len() {
+        process::exit(PROGRAM_ERROR);
+    }
+}
+
+// Checks the goldens of every program in the given files and directories, `examples` by
+// default. With `--bless`, writes the goldens instead.
+fn test(args: &[String]) {
+    let bless = args.iter().any(|arg| arg == "--bless");
+    let mut paths = args
+        .iter()
+        .filter(|arg| *arg != "--bless")
+        .cloned()
+        .collect::<Vec<String>>();
+    if let Some(arg) = paths.iter().find(|arg| arg.starts_with('-')) {
+        usage_error(&format!("opção desconhecida `{}`", arg));
+    }
+    if paths.is_empty() {
+        paths.push("examples".to_string());
+    }
+    let mut programs = vec![];
+    for path in &paths {
+        match golden::discover(Path::new(path)) {
+            Ok(found) => programs.extend(found),
+            Err(err) => {
+                eprintln!("ERRO: não foi possível ler {}: {}", path, err);
+                process::exit(USAGE_ERROR);
+            }
+        }
+    }
 
-    let transpiler = Transpiler::new(program);
-    transpiler.transpile();
+    let mut failed = 0;
+    for program in &programs {
+        let results = golden::check(program, bless).unwrap_or_else(|err| {
+            eprintln!("ERRO: {}: {}", program.display(), err);
+            process::exit(USAGE_ERROR);
+        });
+        if results.iter().all(|result| result.passed()) {
+            println!("ok     {}", program.display());
+        } else {
+            println!("FALHOU {}", program.display());
+            failed += 1;
+        }
+        for result in results {
+            match result.status {
+                golden::Status::Matches => {}
+                golden::Status::Updated => println!("    {} atualizado", result.golden.display()),
+                golden::Status::Missing => println!(
+                    "    {} não existe, use --bless para criá-lo",
+                    result.golden.display()
+                ),
+                golden::Status::Skipped => println!(
+                    "    {} não encontrado, o código transpilado não foi executado",
+                    result.interpreter.unwrap_or_default()
+                ),
+                golden::Status::Differs(diff) => {
+                    match result.interpreter {
+                        Some(interpreter) => println!(
+                            "    {} é diferente do que o código imprime com {}:",
+                            result.golden.display(),
+                            interpreter
+                        ),
+                        None => println!("    {} é diferente:", result.golden.display()),
+                    }
+                    for line in diff.lines() {
+                        println!("    {}", line);
+                    }
+                }
+            }
+        }
+    }
+    println!("{} programas, {} falharam", programs.len(), failed);
+    if failed > 0 {
+        process::exit(PROGRAM_ERROR);
+    }
+}
+
+// Runs the program and prints a desk-check table. What the program prints goes to stderr, to
+// keep the table apart.
+fn trace_program(args: &[String]) {
+    let mut filename = None;
+    let mut input_file = None;
+    let mut format = trace::Format::Text;
+    let mut step_limit = 10_000;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input_file = Some(option_value(&mut args, &arg)),
+            "--steps" => step_limit = parse_option(&mut args, &arg),
+            "--format" => {
+                format = match option_value(&mut args, &arg).as_str() {
+                    "text" => trace::Format::Text,
+                    "markdown" => trace::Format::Markdown,
+                    "csv" => trace::Format::Csv,
+                    value => usage_error(&format!("valor inválido para --format: `{}`", value)),
+                }
+            }
+            _ if arg.starts_with('-') && arg != STDIN => {
+                usage_error(&format!("opção desconhecida `{}`", arg))
+            }
+            _ if filename.is_some() => usage_error("argumentos demais"),
+            _ => filename = Some(arg),
+        }
+    }
+    let program = analyze(&filename.unwrap_or_else(default_filename));
+    let mut input = open_input(input_file);
+    let mut output = io::stderr();
+    let mut evaluator = Evaluator::new(&mut input, &mut output);
+    evaluator.set_tracing(true);
+    evaluator.set_step_limit(step_limit);
+    let result = evaluator.eval(&program, Rc::new(RefCell::new(Environment::new())));
+    eprintln!();
+    // The steps until the error are shown too.
+    print!("{}", trace::render(evaluator.trace(), format));
+    if let Err(err) = result {
+        runtime_error(&evaluator, &err);
+        process::exit(PROGRAM_ERROR);
+    }
+}
+
+// Runs the program in the debugger, with its commands read from stdin. `leia` reads from stdin
+// too, between the commands, unless there is an `--input` file.
+fn debug(args: &[String]) {
+    let mut filename = None;
+    let mut input_file = None;
+    let mut breakpoints = vec![];
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input_file = Some(option_value(&mut args, &arg)),
+            "--break" => breakpoints.push(parse_option(&mut args, &arg)),
+            _ if arg.starts_with('-') => usage_error(&format!("opção desconhecida `{}`", arg)),
+            _ if filename.is_some() => usage_error("argumentos demais"),
+            _ => filename = Some(arg),
+        }
+    }
+    let filename = filename.unwrap_or_else(|| {
+        usage_error("arquivo não inserido, a entrada padrão tem os comandos do depurador")
+    });
+    let program = analyze(&filename);
+    let source = read_source(&filename);
+
+    let commands: Rc<RefCell<dyn io::BufRead>> = Rc::new(RefCell::new(io::stdin().lock()));
+    let mut input: Box<dyn io::BufRead> = match input_file {
+        Some(_) => open_input(input_file),
+        None => Box::new(BufReader::new(SharedInput(commands.clone()))),
+    };
+    let mut debugger_output = io::stdout();
+    let mut debugger = Debugger::new(&source, commands, &mut debugger_output);
+    for line in breakpoints {
+        debugger.add_breakpoint(line);
+    }
+    let mut output = io::stdout();
+    let mut evaluator = Evaluator::new(&mut input, &mut output);
+    evaluator.set_hook(&mut debugger);
+    match evaluator.eval(&program, Rc::new(RefCell::new(Environment::new()))) {
+        Ok(_) | Err(EvalError::Stopped) => {}
+        Err(err) => {
+            runtime_error(&evaluator, &err);
+            process::exit(PROGRAM_ERROR);
+        }
+    }
 }
@@ -21,6 +21,11 @@ impl Environment {
         }
     }
 
+    // The environment of the enclosing function or program, if any.
+    pub fn outer(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.outer.clone()
+    }
+
     pub fn get(&self, name: &str) -> Option<Object> {
         match self.store.get(name) {
             Some(value) => Some(value.clone()),
@@ -31,4 +36,40 @@ impl Environment {
     pub fn set(&mut self, name: &str, val: Object) {
         self.store.insert(name.to_string(), val);
     }
+
+    // The variables defined in this environment, without the outer ones, sorted by name.
+    pub fn variables(&self) -> Vec<(&String, &Object)> {
+        let mut variables = self.store.iter().collect::<Vec<(&String, &Object)>>();
+        variables.sort_by_key(|(name, _)| *name);
+        variables
+    }
+
+    // The variables visible from this environment, including the outer ones that aren't
+    // shadowed, sorted by name.
+    pub fn variables_in_scope(&self) -> Vec<(String, Object)> {
+        let mut variables = match &self.outer {
+            Some(outer) => outer.borrow().variables_in_scope(),
+            None => vec![],
+        };
+        variables.retain(|(name, _)| !self.store.contains_key(name));
+        variables.extend(
+            self.variables()
+                .into_iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        variables.sort_by(|(a, _), (b, _)| a.cmp(b));
+        variables
+    }
+
+    // Changes a variable in the environment where it is defined, or defines it here.
+    pub fn assign(&mut self, name: &str, val: Object) {
+        if !self.store.contains_key(name) {
+            if let Some(outer) = &self.outer {
+                if outer.borrow().get(name).is_some() {
+                    return outer.borrow_mut().assign(name, val);
+                }
+            }
+        }
+        self.set(name, val);
+    }
 }
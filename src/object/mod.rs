@@ -8,7 +8,9 @@ pub use crate::object::environment::Environment;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
+use std::io;
 use std::rc::Rc;
+use std::time::Duration;
 
 pub type EvalResult = Result<Object, EvalError>;
 pub type BuiltinFunction = fn(Vec<Object>) -> EvalResult;
@@ -23,7 +25,6 @@ pub enum Object {
     Hash(HashMap<HashKey, Object>),
     Null,
     Return(Box<Object>),
-    Print(Box<Object>),
     Function(Vec<String>, BlockStatement, Rc<RefCell<Environment>>),
     Builtin(BuiltinFunction),
     CompiledFunction(CompiledFunction),
@@ -56,7 +57,6 @@ impl fmt::Display for Object {
             }
             Object::Null => write!(f, "null"),
             Object::Return(value) => write!(f, "{}", *value),
-            Object::Print(value) => write!(f, "{}", *value),
             Object::Function(params, body, _) => {
                 write!(f, "fn({}) {{\n{}\n}}", params.join(", "), body)
             }
@@ -74,7 +74,10 @@ impl fmt::Display for Object {
                     "closure ({}) ({}): {}",
                     closure.func.num_locals,
                     free_list,
-                    code::print_instructions(&closure.func.instructions),
+                    code::print_instructions_with_lines(
+                        &closure.func.instructions,
+                        &closure.func.lines
+                    ),
                 )
             }
         }
@@ -102,7 +105,6 @@ impl Object {
             Object::Hash(_) => "HASH",
             Object::Null => "NULL",
             Object::Return(_) => "RETURN",
-            Object::Print(_) => "PRINT",
             Object::Function(_, _, _) => "FUNCTION",
             Object::Builtin(_) => "BUILTIN",
             Object::CompiledFunction(_) => "COMPILED_FUNCTION",
@@ -144,7 +146,7 @@ impl HashKey {
             Object::Integer(value) => Ok(HashKey::Integer(*value)),
             Object::String(value) => Ok(HashKey::String(value.to_string())),
             Object::Boolean(value) => Ok(HashKey::Boolean(*value)),
-            _ => Err(EvalError::UnsupportedHashKey(obj.clone())),
+            _ => Err(EvalError::UnsupportedHashKey(Box::new(obj.clone()))),
         }
     }
 }
@@ -155,17 +157,35 @@ pub struct Closure {
     pub free: Vec<Rc<Object>>,
 }
 
+// Values are boxed to keep `EvalResult` small, it's returned all over the evaluator.
 #[derive(Debug)]
 pub enum EvalError {
-    TypeMismatch(Infix, Object, Object),
-    UnknownPrefixOperator(Prefix, Object),
-    UnknownInfixOperator(Infix, Object, Object),
+    TypeMismatch(Infix, Box<Object>, Box<Object>),
+    UnknownPrefixOperator(Prefix, Box<Object>),
+    UnknownInfixOperator(Infix, Box<Object>, Box<Object>),
     IdentifierNotFound(String),
-    NotCallable(Object),
+    NotCallable(Box<Object>),
     WrongArgumentCount { expected: usize, given: usize },
     UnsupportedArguments(String, Vec<Object>),
-    UnknownIndexOperator(Object, Object),
-    UnsupportedHashKey(Object),
+    UnknownIndexOperator(Box<Object>, Box<Object>),
+    UnsupportedHashKey(Box<Object>),
+    DivisionByZero,
+    // An integer operation, like `a + b`, whose result doesn't fit in 64 bits.
+    IntegerOverflow(String),
+    // Functions called inside each other more than the evaluator's depth limit, like a
+    // recursion without a base case.
+    StackOverflow(usize),
+    // `leia` found no more input.
+    EndOfInput,
+    // `leia` read something that isn't a value of the variable's type.
+    InvalidInput(String, String),
+    Io(io::Error),
+    // The program ran more statements than the evaluator's step limit.
+    StepLimit(u64),
+    // The program ran longer than the evaluator's time limit.
+    TimeLimit(Duration),
+    // The debugger ended the program.
+    Stopped,
 }
 
 impl fmt::Display for EvalError {
@@ -223,6 +243,19 @@ impl fmt::Display for EvalError {
             EvalError::UnsupportedHashKey(key) => {
                 write!(f, "unusable as hash key: {}", key.type_name())
             }
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::IntegerOverflow(operation) => write!(f, "integer overflow: {}", operation),
+            EvalError::StackOverflow(depth) => {
+                write!(f, "stack overflow: more than {} nested calls", depth)
+            }
+            EvalError::EndOfInput => write!(f, "end of input"),
+            EvalError::InvalidInput(input, type_name) => {
+                write!(f, "invalid input for {}: `{}`", type_name, input)
+            }
+            EvalError::Io(err) => write!(f, "input/output error: {}", err),
+            EvalError::StepLimit(limit) => write!(f, "step limit of {} exceeded", limit),
+            EvalError::TimeLimit(limit) => write!(f, "time limit of {:?} exceeded", limit),
+            EvalError::Stopped => write!(f, "stopped by the debugger"),
         }
     }
 }
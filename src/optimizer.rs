@@ -0,0 +1,584 @@
+use crate::code::{self, Bytecode, Constant, Instructions, LineTable, OpCode};
+
+// Peephole optimizations over compiled bytecode.
+//
+// Instructions are decoded into a list where jump operands point to instruction indices
+// instead of byte offsets, so that passes can remove instructions freely. Jump targets are
+// relocated, and the line table rebuilt, when the list is encoded back into bytes.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptLevel {
+    // No optimization.
+    O0,
+    // Constant folding, jump threading and dead code elimination.
+    O1,
+}
+
+impl OptLevel {
+    pub fn from_flag(flag: &str) -> Option<OptLevel> {
+        match flag {
+            "-O0" => Some(OptLevel::O0),
+            "-O1" => Some(OptLevel::O1),
+            _ => None,
+        }
+    }
+}
+
+pub fn optimize(bytecode: Bytecode, level: OptLevel) -> Bytecode {
+    if level == OptLevel::O0 {
+        return bytecode;
+    }
+    let mut constants = bytecode.constants;
+    let (instructions, lines) =
+        optimize_instructions(&bytecode.instructions, &bytecode.lines, &mut constants);
+
+    // Folding inside a function may add constants to the pool, so functions are optimized
+    // one at a time against the growing pool.
+    for i in 0..constants.len() {
+        if let Constant::CompiledFunction(cf) = &constants[i] {
+            let (insts, lines) = (cf.instructions.clone(), cf.lines.clone());
+            let optimized = optimize_instructions(&insts, &lines, &mut constants);
+            if let Constant::CompiledFunction(cf) = &mut constants[i] {
+                cf.instructions = optimized.0;
+                cf.lines = optimized.1;
+            }
+        }
+    }
+    let mut bytecode = Bytecode::new(instructions, constants);
+    bytecode.lines = lines;
+    bytecode
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Instruction {
+    op_code: OpCode,
+    // Jump operands hold the index of the target instruction.
+    operands: Vec<usize>,
+    line: Option<usize>,
+}
+
+impl Instruction {
+    fn is_jump(&self) -> bool {
+        self.op_code == OpCode::Jump || self.op_code == OpCode::JumpIfNotTruthy
+    }
+
+    fn is_terminator(&self) -> bool {
+        matches!(
+            self.op_code,
+            OpCode::Jump | OpCode::ReturnValue | OpCode::Return
+        )
+    }
+}
+
+fn optimize_instructions(
+    insts: &[u8],
+    lines: &LineTable,
+    constants: &mut Vec<Constant>,
+) -> (Instructions, LineTable) {
+    let mut decoded = match decode(insts, lines) {
+        Some(decoded) => decoded,
+        // Leave anything we can't fully understand untouched.
+        None => return (insts.to_vec(), lines.clone()),
+    };
+    loop {
+        let mut changed = fold_constants(&mut decoded, constants);
+        changed |= thread_jumps(&mut decoded);
+        changed |= remove_jumps_to_next(&mut decoded);
+        changed |= remove_dead_code(&mut decoded);
+        if !changed {
+            break;
+        }
+    }
+    encode(&decoded)
+}
+
+fn decode(insts: &[u8], lines: &LineTable) -> Option<Vec<Instruction>> {
+    let mut decoded = vec![];
+    let mut offsets = vec![];
+    let mut i = 0;
+    while i < insts.len() {
+        let op_code = OpCode::from_byte(insts[i])?;
+        let def = code::lookup_definition(insts[i])?;
+        let mut operands = vec![];
+        let mut offset = i + 1;
+        for width in &def.widths {
+            match width {
+                2 if offset + 1 < insts.len() => {
+                    operands.push(code::read_uint16(insts, offset) as usize)
+                }
+                1 if offset < insts.len() => operands.push(insts[offset] as usize),
+                _ => return None,
+            }
+            offset += width;
+        }
+        offsets.push(i);
+        decoded.push(Instruction {
+            op_code,
+            operands,
+            line: lines.line_at(i),
+        });
+        i = offset;
+    }
+    offsets.push(insts.len());
+
+    // Byte offsets -> instruction indices. A jump into the middle of an instruction can't be
+    // represented.
+    for inst in decoded.iter_mut().filter(|inst| inst.is_jump()) {
+        inst.operands[0] = offsets.iter().position(|&o| o == inst.operands[0])?;
+    }
+    Some(decoded)
+}
+
+fn encode(decoded: &[Instruction]) -> (Instructions, LineTable) {
+    let mut offsets = Vec::with_capacity(decoded.len() + 1);
+    let mut offset = 0;
+    for inst in decoded {
+        offsets.push(offset);
+        offset += 1 + code::lookup_definition(inst.op_code as u8)
+            .map(|def| def.widths.iter().sum::<usize>())
+            .unwrap_or_default();
+    }
+    offsets.push(offset);
+
+    let mut insts = Vec::with_capacity(offset);
+    let mut lines = LineTable::new();
+    for inst in decoded {
+        if let Some(line) = inst.line {
+            lines.add(insts.len(), line);
+        }
+        if inst.is_jump() {
+            insts.extend(code::make_u16(
+                inst.op_code,
+                offsets[inst.operands[0]] as u16,
+            ));
+            continue;
+        }
+        let widths = code::lookup_definition(inst.op_code as u8)
+            .map(|def| def.widths)
+            .unwrap_or_default();
+        insts.extend(match (widths.as_slice(), inst.operands.as_slice()) {
+            ([2], [operand]) => code::make_u16(inst.op_code, *operand as u16),
+            ([1], [operand]) => code::make_u8(inst.op_code, *operand as u8),
+            ([2, 1], [first, second]) => {
+                code::make_u16_u8(inst.op_code, *first as u16, *second as u8)
+            }
+            _ => code::make(inst.op_code),
+        });
+    }
+    (insts, lines)
+}
+
+fn jump_targets(decoded: &[Instruction]) -> Vec<bool> {
+    let mut targets = vec![false; decoded.len() + 1];
+    for inst in decoded.iter().filter(|inst| inst.is_jump()) {
+        targets[inst.operands[0]] = true;
+    }
+    targets
+}
+
+// Drops the instructions whose `keep` flag is false. Jumps to a removed instruction land on
+// the next instruction that is kept.
+fn retain(decoded: &mut Vec<Instruction>, keep: &[bool]) -> bool {
+    let mut new_indices = Vec::with_capacity(decoded.len() + 1);
+    let mut kept = 0;
+    for &k in keep {
+        new_indices.push(kept);
+        if k {
+            kept += 1;
+        }
+    }
+    new_indices.push(kept);
+    if kept == decoded.len() {
+        return false;
+    }
+
+    let mut i = 0;
+    decoded.retain(|_| {
+        i += 1;
+        keep[i - 1]
+    });
+    for inst in decoded.iter_mut().filter(|inst| inst.is_jump()) {
+        inst.operands[0] = new_indices[inst.operands[0]];
+    }
+    true
+}
+
+// `OpConstant a OpConstant b OpAdd` -> `OpConstant (a + b)`
+fn fold_constants(decoded: &mut Vec<Instruction>, constants: &mut Vec<Constant>) -> bool {
+    let targets = jump_targets(decoded);
+    let mut keep = vec![true; decoded.len()];
+    let mut i = 0;
+    while i + 2 < decoded.len() {
+        let window = &decoded[i..i + 3];
+        let foldable = window[0].op_code == OpCode::Constant
+            && window[1].op_code == OpCode::Constant
+            && !targets[i + 1]
+            && !targets[i + 2];
+        let folded = if foldable {
+            match (
+                constants.get(window[0].operands[0]),
+                constants.get(window[1].operands[0]),
+            ) {
+                (Some(left), Some(right)) => fold_binary(window[2].op_code, left, right),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        match folded.and_then(|constant| add_constant(constants, constant)) {
+            Some(index) => {
+                decoded[i].operands[0] = index;
+                keep[i + 1] = false;
+                keep[i + 2] = false;
+                i += 3;
+            }
+            None => i += 1,
+        }
+    }
+    retain(decoded, &keep)
+}
+
+fn fold_binary(op_code: OpCode, left: &Constant, right: &Constant) -> Option<Constant> {
+    match (left, right) {
+        (Constant::Integer(l), Constant::Integer(r)) => match op_code {
+            OpCode::Add => l.checked_add(*r),
+            OpCode::Sub => l.checked_sub(*r),
+            OpCode::Mul => l.checked_mul(*r),
+            // `checked_div` also refuses to fold a division by zero.
+            OpCode::Div => l.checked_div(*r),
+            OpCode::Modulo => l.checked_rem(*r),
+            OpCode::Power if *r >= 0 && *r <= u32::MAX as i64 => l.checked_pow(*r as u32),
+            _ => None,
+        }
+        .map(Constant::Integer),
+        (Constant::Integer(l), Constant::Float(r)) => fold_float(op_code, *l as f64, *r),
+        (Constant::Float(l), Constant::Integer(r)) => fold_float(op_code, *l, *r as f64),
+        (Constant::Float(l), Constant::Float(r)) => fold_float(op_code, *l, *r),
+        (Constant::String(l), Constant::String(r)) if op_code == OpCode::Add => {
+            Some(Constant::String([l.as_str(), r.as_str()].concat()))
+        }
+        _ => None,
+    }
+}
+
+fn fold_float(op_code: OpCode, left: f64, right: f64) -> Option<Constant> {
+    match op_code {
+        OpCode::Add => Some(Constant::Float(left + right)),
+        OpCode::Sub => Some(Constant::Float(left - right)),
+        OpCode::Mul => Some(Constant::Float(left * right)),
+        // Leave divisions by zero to the runtime.
+        OpCode::Div if right != 0.0 => Some(Constant::Float(left / right)),
+        OpCode::Power => Some(Constant::Float(left.powf(right))),
+        _ => None,
+    }
+}
+
+fn add_constant(constants: &mut Vec<Constant>, constant: Constant) -> Option<usize> {
+    if let Some(index) = constants.iter().position(|c| same_constant(c, &constant)) {
+        return Some(index);
+    }
+    if constants.len() > u16::MAX as usize {
+        return None;
+    }
+    constants.push(constant);
+    Some(constants.len() - 1)
+}
+
+// Floats are compared by their bits: `-0.0 == 0.0`, but they give different results, like
+// `1 / -0.0`.
+fn same_constant(left: &Constant, right: &Constant) -> bool {
+    match (left, right) {
+        (Constant::Float(l), Constant::Float(r)) => l.to_bits() == r.to_bits(),
+        _ => left == right,
+    }
+}
+
+// A jump to an `OpJump` goes directly to the final target instead.
+fn thread_jumps(decoded: &mut [Instruction]) -> bool {
+    let mut changed = false;
+    for i in 0..decoded.len() {
+        if !decoded[i].is_jump() {
+            continue;
+        }
+        let mut target = decoded[i].operands[0];
+        // Bound the steps to not loop forever on a cycle of jumps.
+        let mut steps = 0;
+        while target < decoded.len()
+            && decoded[target].op_code == OpCode::Jump
+            && decoded[target].operands[0] != target
+            && steps < decoded.len()
+        {
+            target = decoded[target].operands[0];
+            steps += 1;
+        }
+        if target != decoded[i].operands[0] {
+            decoded[i].operands[0] = target;
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn remove_jumps_to_next(decoded: &mut Vec<Instruction>) -> bool {
+    let keep = decoded
+        .iter()
+        .enumerate()
+        .map(|(i, inst)| !(inst.op_code == OpCode::Jump && inst.operands[0] == i + 1))
+        .collect::<Vec<bool>>();
+    retain(decoded, &keep)
+}
+
+// Nothing after `OpReturnValue`, `OpReturn` or `OpJump` runs until the next jump target.
+fn remove_dead_code(decoded: &mut Vec<Instruction>) -> bool {
+    let targets = jump_targets(decoded);
+    let mut keep = vec![true; decoded.len()];
+    let mut reachable = true;
+    for (i, inst) in decoded.iter().enumerate() {
+        if targets[i] {
+            reachable = true;
+        }
+        keep[i] = reachable;
+        if reachable && inst.is_terminator() {
+            reachable = false;
+        }
+    }
+    retain(decoded, &keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code::{disassemble, make, make_u16, make_u8, Bytecode, CompiledFunction, Constant};
+    use crate::code::{emit, print_instructions_with_lines, Instructions, LineTable, OpCode};
+    use crate::optimizer::{optimize, OptLevel};
+
+    #[test]
+    fn constant_folding() {
+        expect_optimized(
+            [
+                make_u16(OpCode::Constant, 0),
+                make_u16(OpCode::Constant, 1),
+                make(OpCode::Add),
+                make_u16(OpCode::Constant, 2),
+                make(OpCode::Mul),
+                make(OpCode::Pop),
+            ]
+            .concat(),
+            vec![
+                Constant::Integer(1),
+                Constant::Integer(2),
+                Constant::Integer(3),
+            ],
+            "constants:
+0000 INTEGER 1
+0001 INTEGER 2
+0002 INTEGER 3
+0003 INTEGER 9
+
+main:
+0000 OpConstant 3 ; 9
+0003 OpPop
+",
+        );
+        // Division by zero is left for the runtime.
+        expect_optimized(
+            [
+                make_u16(OpCode::Constant, 0),
+                make_u16(OpCode::Constant, 1),
+                make(OpCode::Div),
+                make(OpCode::Pop),
+            ]
+            .concat(),
+            vec![Constant::Integer(1), Constant::Integer(0)],
+            "constants:
+0000 INTEGER 1
+0001 INTEGER 0
+
+main:
+0000 OpConstant 0 ; 1
+0003 OpConstant 1 ; 0
+0006 OpDiv
+0007 OpPop
+",
+        );
+        expect_optimized(
+            [
+                make_u16(OpCode::Constant, 0),
+                make_u16(OpCode::Constant, 1),
+                make(OpCode::Add),
+                make(OpCode::Pop),
+            ]
+            .concat(),
+            vec![
+                Constant::String("foo".to_string()),
+                Constant::String("bar".to_string()),
+            ],
+            "constants:
+0000 STRING \"foo\"
+0001 STRING \"bar\"
+0002 STRING \"foobar\"
+
+main:
+0000 OpConstant 2 ; \"foobar\"
+0003 OpPop
+",
+        );
+    }
+
+    #[test]
+    fn negative_zero_is_a_new_constant() {
+        expect_optimized(
+            [
+                make_u16(OpCode::Constant, 0),
+                make_u16(OpCode::Constant, 1),
+                make(OpCode::Mul),
+                make(OpCode::Pop),
+            ]
+            .concat(),
+            vec![Constant::Float(0.0), Constant::Float(-1.0)],
+            "constants:
+0000 FLOAT 0
+0001 FLOAT -1
+0002 FLOAT -0
+
+main:
+0000 OpConstant 2 ; -0
+0003 OpPop
+",
+        );
+    }
+
+    #[test]
+    fn no_folding_across_jump_targets() {
+        // The second constant is a jump target, so the sequence isn't a single expression.
+        let insts = [
+            make(OpCode::True),
+            make_u16(OpCode::JumpIfNotTruthy, 7),
+            make_u16(OpCode::Constant, 0),
+            make_u16(OpCode::Constant, 1),
+            make(OpCode::Add),
+            make(OpCode::Pop),
+        ]
+        .concat();
+        expect_optimized(
+            insts,
+            vec![Constant::Integer(1), Constant::Integer(2)],
+            "constants:
+0000 INTEGER 1
+0001 INTEGER 2
+
+main:
+0000 OpTrue
+0001 OpJumpIfNotTruthy L0
+0004 OpConstant 0 ; 1
+L0:
+0007 OpConstant 1 ; 2
+0010 OpAdd
+0011 OpPop
+",
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        // Jump to the next instruction, and a conditional jump to a jump.
+        let insts = [
+            make(OpCode::True),
+            make_u16(OpCode::JumpIfNotTruthy, 9),
+            make(OpCode::Null),
+            make(OpCode::Pop),
+            make_u16(OpCode::Jump, 9),
+            make_u16(OpCode::Jump, 13),
+            make(OpCode::False),
+            make(OpCode::Pop),
+        ]
+        .concat();
+        expect_optimized(
+            insts,
+            vec![],
+            "constants:
+
+main:
+0000 OpTrue
+0001 OpJumpIfNotTruthy L0
+0004 OpNull
+0005 OpPop
+L0:
+0006 OpPop
+",
+        );
+    }
+
+    #[test]
+    fn dead_code_after_return() {
+        let func = CompiledFunction {
+            instructions: [
+                make_u8(OpCode::GetLocal, 0),
+                make(OpCode::ReturnValue),
+                make_u8(OpCode::GetLocal, 0),
+                make(OpCode::Pop),
+                make(OpCode::Return),
+            ]
+            .concat(),
+            num_locals: 1,
+            num_parameters: 1,
+            lines: LineTable::new(),
+        };
+        expect_optimized(
+            make(OpCode::Null),
+            vec![Constant::CompiledFunction(func)],
+            "constants:
+0000 COMPILED_FUNCTION <fn 0>
+
+main:
+0000 OpNull
+
+<fn 0> (1 locals, 1 parameters):
+0000 OpGetLocal 0
+0002 OpReturnValue
+",
+        );
+    }
+
+    #[test]
+    fn lines_are_relocated() {
+        let mut insts = vec![];
+        let mut lines = LineTable::new();
+        emit(&mut insts, &mut lines, make_u16(OpCode::Constant, 0), 1);
+        emit(&mut insts, &mut lines, make_u16(OpCode::Constant, 0), 1);
+        emit(&mut insts, &mut lines, make(OpCode::Add), 1);
+        emit(&mut insts, &mut lines, make(OpCode::Pop), 1);
+        emit(&mut insts, &mut lines, make(OpCode::Null), 2);
+        emit(&mut insts, &mut lines, make(OpCode::Pop), 2);
+        let mut bytecode = Bytecode::new(insts, vec![Constant::Integer(1)]);
+        bytecode.lines = lines;
+
+        let optimized = optimize(bytecode, OptLevel::O1);
+        let expected = "0000 OpConstant 1 [line 1]
+0003 OpPop [line 1]
+0004 OpNull [line 2]
+0005 OpPop [line 2]";
+        assert_eq!(
+            print_instructions_with_lines(&optimized.instructions, &optimized.lines),
+            expected
+        );
+    }
+
+    #[test]
+    fn level_zero_is_untouched() {
+        let insts = [
+            make_u16(OpCode::Jump, 3),
+            make(OpCode::Null),
+            make(OpCode::Pop),
+        ]
+        .concat();
+        let bytecode = Bytecode::new(insts.clone(), vec![]);
+        assert_eq!(optimize(bytecode, OptLevel::O0).instructions, insts);
+    }
+
+    fn expect_optimized(insts: Instructions, constants: Vec<Constant>, expected: &str) {
+        let bytecode = Bytecode::new(insts, constants);
+        let before = disassemble(&bytecode);
+        let after = disassemble(&optimize(bytecode, OptLevel::O1));
+        assert_eq!(after, expected, "before:\n{}", before);
+    }
+}
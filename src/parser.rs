@@ -1,5 +1,5 @@
-use crate::ast::{BlockStatement, Expression, Infix, Prefix, Program, Statement};
-use crate::lexer::Lexer;
+use crate::ast::{BlockStatement, Expression, Infix, Position, Prefix, Program, Statement};
+use crate::lexer::{Comment, Lexer};
 use crate::token::Token;
 use std::mem;
 
@@ -18,7 +18,7 @@ pub enum Precedence {
 
 type Result<T> = std::result::Result<T, ParserError>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ParserError {
     ExpectedValue(Token),
     ExpectedPrefixToken(Token),
@@ -50,6 +50,14 @@ pub struct Parser {
 
     cur_token: Token,
     peek_token: Token,
+    // Lines where `cur_token` and `peek_token` start.
+    cur_line: usize,
+    peek_line: usize,
+    // And their columns.
+    cur_column: usize,
+    peek_column: usize,
+    // Where the left expression of the infix being parsed starts.
+    left_start: Position,
 }
 
 impl Parser {
@@ -59,6 +67,11 @@ impl Parser {
             errors: vec![],
             cur_token: Token::Illegal,
             peek_token: Token::Illegal,
+            cur_line: 1,
+            peek_line: 1,
+            cur_column: 1,
+            peek_column: 1,
+            left_start: Position { line: 1, column: 1 },
         };
         p.next_token();
         p.next_token();
@@ -69,21 +82,30 @@ impl Parser {
         self.lexer.input()
     }
 
+    pub fn comments(&self) -> &[Comment] {
+        self.lexer.comments()
+    }
+
     pub fn errors(&self) -> &[ParserError] {
         &self.errors
     }
 
     fn next_token(&mut self) {
         self.cur_token = mem::replace(&mut self.peek_token, self.lexer.next_token());
+        self.cur_line = mem::replace(&mut self.peek_line, self.lexer.token_line());
+        self.cur_column = mem::replace(&mut self.peek_column, self.lexer.token_column());
     }
 
     pub fn parse_program(&mut self) -> Program {
         let mut statements = vec![];
+        let mut lines = vec![];
 
         while self.cur_token != Token::Eof {
+            let line = self.cur_line;
             match self.parse_statement() {
                 Ok(stmt) => {
                     statements.push(stmt);
+                    lines.push(line);
                 }
                 Err(err) => {
                     self.errors.push(err);
@@ -92,7 +114,7 @@ impl Parser {
             self.next_token();
         }
 
-        Program { statements }
+        Program { statements, lines }
     }
 
     fn parse_statement(&mut self) -> Result<Statement> {
@@ -104,7 +126,11 @@ impl Parser {
             Token::Return => self.parse_return_statement(),
             Token::Print => self.parse_print_statement(),
             Token::Read => self.parse_read_statement(),
-            Token::Ident(_) => {
+            // `x <- 1;` is an assignment, anything else starting with a name (like a call) is
+            // an expression statement.
+            Token::Ident(_)
+                if self.peek_token == Token::Arrow || self.peek_token == Token::Assign =>
+            {
                 self.parse_assign_statement(Expression::Identifier(self.cur_token.to_string()))
             }
             _ => self.parse_expression_statement(),
@@ -281,18 +307,24 @@ impl Parser {
 
     fn parse_block_statement(&mut self) -> Result<BlockStatement> {
         let mut statements = vec![];
+        let mut lines = vec![];
 
         self.next_token();
         while self.cur_token != Token::Rbrace && self.cur_token != Token::Eof {
+            lines.push(self.cur_line);
             statements.push(self.parse_statement()?);
             self.next_token();
         }
 
-        Ok(BlockStatement { statements })
+        Ok(BlockStatement { statements, lines })
     }
 
     fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression> {
         // cur_token: the first token of the expression
+        let start = Position {
+            line: self.cur_line,
+            column: self.cur_column,
+        };
         let prefix = self
             .prefix_parse_fn()
             .ok_or_else(|| ParserError::ExpectedPrefixToken(self.cur_token.clone()))?;
@@ -304,6 +336,7 @@ impl Parser {
             if let Some(infix) = self.infix_parse_fn() {
                 self.next_token();
                 // cur_token: the infix token
+                self.left_start = start;
                 left_exp = infix(self, left_exp)?;
             // cur_token: the last token of the right expression
             } else {
@@ -550,9 +583,10 @@ impl Parser {
 
     fn parse_call_expression(&mut self, function: Expression) -> Result<Expression> {
         // cur_token: (
+        let start = self.left_start;
         let arguments = self.parse_expressions(Token::Rparen, ParserError::ExpectedRparen)?;
         // cur_token: )
-        Ok(Expression::Call(Box::new(function), arguments))
+        Ok(Expression::Call(Box::new(function), arguments, Some(start)))
     }
 
     fn parse_index_expression(&mut self, array: Expression) -> Result<Expression> {
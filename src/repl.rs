@@ -0,0 +1,298 @@
+// Interactive interpreter. Every input runs in the same environment, so variables and
+// functions stay defined until `:reset`.
+use crate::ast::{Expression, Program, Statement};
+use crate::evaluator::Evaluator;
+use crate::formatter;
+use crate::lexer::Lexer;
+use crate::object::{Environment, HashKey, Object};
+use crate::semantic;
+use crate::token::Token;
+use crate::transpiler;
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+const PROMPT: &str = ">> ";
+// Shown while a block is still open.
+const CONTINUATION_PROMPT: &str = "... ";
+
+const HELP: &str = "comandos:
+    :vars            lista as variáveis
+    :reset           apaga todas as variáveis
+    :load <arquivo>  executa um arquivo
+    :python          mostra o código Python da última entrada
+    :ajuda           mostra esta mensagem
+    :sair            encerra o interpretador";
+
+pub struct Repl {
+    env: Rc<RefCell<Environment>>,
+    // The source of the last input that was run, for `:python`.
+    last_input: Option<String>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl {
+            env: Rc::new(RefCell::new(Environment::new())),
+            last_input: None,
+        }
+    }
+
+    // Reads inputs until the end of `input` or `:sair`. `leia` reads from `input` too.
+    pub fn start(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+        let mut source = String::new();
+        loop {
+            let prompt = if source.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            write!(output, "{}", prompt)?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(());
+            }
+            source.push_str(&line);
+            if is_incomplete(&source) {
+                continue;
+            }
+            let done = !self.run(&source, input, output)?;
+            source.clear();
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    // Runs a complete input, a meta-command or Portugol code. Returns false after `:sair`.
+    pub fn run(
+        &mut self,
+        source: &str,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> io::Result<bool> {
+        let trimmed = source.trim();
+        if let Some(command) = trimmed.strip_prefix(':') {
+            let mut words = command.split_whitespace();
+            let name = words.next().unwrap_or("");
+            let args = words.collect::<Vec<&str>>();
+            match (name, args.as_slice()) {
+                ("sair", []) => return Ok(false),
+                ("ajuda", []) => writeln!(output, "{}", HELP)?,
+                ("vars", []) => self.vars(output)?,
+                ("reset", []) => {
+                    self.env = Rc::new(RefCell::new(Environment::new()));
+                    self.last_input = None;
+                }
+                ("load", [filename]) => match fs::read_to_string(filename) {
+                    Ok(contents) => self.eval(&contents, input, output)?,
+                    Err(err) => {
+                        writeln!(output, "ERRO: não foi possível ler {}: {}", filename, err)?
+                    }
+                },
+                ("load", _) => writeln!(output, "ERRO: uso: :load <arquivo>")?,
+                ("python", []) => self.python(output)?,
+                _ => writeln!(
+                    output,
+                    "ERRO: comando desconhecido `:{}`, veja :ajuda",
+                    command
+                )?,
+            }
+            return Ok(true);
+        }
+        if !trimmed.is_empty() {
+            self.eval(source, input, output)?;
+        }
+        Ok(true)
+    }
+
+    fn eval(
+        &mut self,
+        source: &str,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        let program = match semantic::analyze(source) {
+            Ok(program) => program,
+            Err(err) => return writeln!(output, "{}", err),
+        };
+        self.last_input = Some(source.to_string());
+        let mut evaluator = Evaluator::new(input, output);
+        let result = evaluator.eval(&program, Rc::clone(&self.env));
+        let traceback = evaluator.traceback().cloned();
+        match result {
+            Ok(value) if shows_value(&program) && value != Object::Null => {
+                writeln!(output, "{}", portugol_value(&value))
+            }
+            Ok(_) => Ok(()),
+            Err(err) => {
+                if let Some(traceback) = traceback {
+                    write!(output, "{}", traceback)?;
+                }
+                writeln!(output, "ERRO DE EXECUÇÃO: {}", err)
+            }
+        }
+    }
+
+    fn vars(&self, output: &mut dyn Write) -> io::Result<()> {
+        for (name, value) in self.env.borrow().variables() {
+            writeln!(output, "{} = {}", name, portugol_value(value))?;
+        }
+        Ok(())
+    }
+
+    // Transpiles the last input alone, so names defined by earlier inputs are not declared.
+    fn python(&self, output: &mut dyn Write) -> io::Result<()> {
+        let program = match self.last_input.as_deref().map(semantic::analyze) {
+            Some(Ok(program)) => program,
+            Some(Err(err)) => return writeln!(output, "{}", err),
+            None => return writeln!(output, "ERRO: nenhuma entrada executada"),
+        };
+        match transpiler::backend("python", program) {
+            Ok(backend) => write!(output, "{}", backend.transpile_to_string()),
+            Err(err) => writeln!(output, "ERRO: {}", err),
+        }
+    }
+}
+
+// Whether the input has a `{` that isn't closed yet, so it continues on the next line.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut lexer = Lexer::new(source.to_string());
+    let mut open = 0;
+    loop {
+        match lexer.next_token() {
+            Token::Lbrace => open += 1,
+            Token::Rbrace => open -= 1,
+            Token::Eof => return open > 0,
+            _ => {}
+        }
+    }
+}
+
+// Only inputs that end with an expression show its value. Assignments, `se` and `enquanto`
+// are statements in Portugol.
+fn shows_value(program: &Program) -> bool {
+    match program.statements.last() {
+        Some(Statement::Expression(exp)) => !matches!(
+            exp,
+            Expression::Assign(..) | Expression::If(..) | Expression::While(..)
+        ),
+        _ => false,
+    }
+}
+
+// A value written the way it would be in Portugol code.
+pub fn portugol_value(value: &Object) -> String {
+    match value {
+        Object::Boolean(true) => "VERDADEIRO".to_string(),
+        Object::Boolean(false) => "FALSO".to_string(),
+        Object::Float(value) => formatter::float(*value),
+        Object::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(portugol_value)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        Object::Hash(pairs) => {
+            // Sorted like `Object`'s `Display`, for a stable output.
+            let mut items = pairs
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{}: {}",
+                        portugol_value(&key_value(key)),
+                        portugol_value(value)
+                    )
+                })
+                .collect::<Vec<String>>();
+            items.sort();
+            format!("{{{}}}", items.join(", "))
+        }
+        Object::Return(value) => portugol_value(value),
+        Object::Function(params, _, _) => format!("funcao({})", params.join(", ")),
+        Object::Builtin(_) => "funcao embutida".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+fn key_value(key: &HashKey) -> Object {
+    match key {
+        HashKey::Integer(value) => Object::Integer(*value),
+        HashKey::String(value) => Object::String(value.clone()),
+        HashKey::Boolean(value) => Object::Boolean(*value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_incomplete, Repl};
+
+    fn session(lines: &str) -> String {
+        let mut input = lines.as_bytes();
+        let mut output = vec![];
+        Repl::new().start(&mut input, &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .replace(super::CONTINUATION_PROMPT, "")
+            .replace(super::PROMPT, "")
+    }
+
+    #[test]
+    fn incomplete() {
+        assert!(is_incomplete("se (x > 1) {"));
+        assert!(is_incomplete("funcao(x) {\n se (x) {\n }"));
+        assert!(!is_incomplete("se (x > 1) { imprima(x); }"));
+        assert!(!is_incomplete("imprima(\"{\"); // {"));
+    }
+
+    #[test]
+    fn values() {
+        let tests = vec![
+            ("1 + 2;\n", "3\n\n"),
+            ("1.5 * 2;\n", "3.0\n\n"),
+            ("1 < 2;\n", "VERDADEIRO\n\n"),
+            ("\"a\";\n", "\"a\"\n\n"),
+            ("[1, 2.5, FALSO];\n", "[1, 2.5, FALSO]\n\n"),
+            ("int x;\nx = 2;\nx;\n", "2\n\n"),
+            ("imprima(1);\n", "1\n"),
+            ("1 +;\n", "ERRO DE SINTAXE"),
+            (
+                "int z;\n1 / z;\n",
+                "Rastreamento (chamada mais recente por último):\n  linha 1, em <programa>\nERRO DE EXECUÇÃO: division by zero\n\n",
+            ),
+        ];
+        for (input, expected) in tests {
+            let output = session(input);
+            assert!(output.starts_with(expected), "{:?} -> {:?}", input, output);
+        }
+    }
+
+    #[test]
+    fn multi_line() {
+        let output = session("int x;\nx = 0;\nenquanto (x < 3) {\n x = x + 1;\n}\nx;\n");
+        assert_eq!(output, "3\n\n");
+    }
+
+    #[test]
+    fn meta_commands() {
+        let output = session("int x;\nx = 1;\nlet y = 2.0;\n:vars\n:reset\n:vars\n:sair\nx;\n");
+        assert_eq!(output, "x = 1\ny = 2.0\n");
+
+        let output = session("let f = funcao(a) { retorne a; };\n:python\n");
+        assert!(output.contains("def f(a):"), "{}", output);
+
+        let output = session(":python\n:desconhecido\n");
+        assert!(output.starts_with("ERRO: nenhuma entrada executada\nERRO: comando desconhecido"));
+    }
+}
@@ -0,0 +1,644 @@
+use crate::ast::{BlockStatement, Expression, Infix, Prefix, Program, Statement};
+use crate::fold::{self, FoldError};
+use crate::lexer::Lexer;
+use crate::object::builtin;
+use crate::parser::{Parser, ParserError};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Why a program can't run at all, shown the same way by every command.
+#[derive(Debug)]
+pub enum AnalysisError {
+    Syntax(ParserError),
+    Semantic(FoldError),
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnalysisError::Syntax(err) => write!(f, "ERRO DE SINTAXE: {:?}", err),
+            AnalysisError::Semantic(err) => write!(f, "ERRO SEMÂNTICO: {}", err),
+        }
+    }
+}
+
+// Parses a program, failing on the first syntax error.
+pub fn parse(source: &str) -> Result<Program, AnalysisError> {
+    let mut parser = Parser::new(Lexer::new(source.to_string()));
+    let program = parser.parse_program();
+    match parser.errors().first() {
+        Some(err) => Err(AnalysisError::Syntax(err.clone())),
+        None => Ok(program),
+    }
+}
+
+// Parses and folds a program, the way it is run, translated or graded.
+pub fn analyze(source: &str) -> Result<Program, AnalysisError> {
+    fold::fold(&parse(source)?).map_err(AnalysisError::Semantic)
+}
+
+// Semantic analysis run by `check`: names that are never defined, operators used with values of
+// the wrong type, and calls with the wrong number of arguments.
+//
+// Portugol is dynamically typed, so only what is certain is reported. Every error found here is
+// one the evaluator stops with whenever the code runs. The types of variables come from all the
+// places they are bound, anywhere in the program: a name bound with two types, or with a value
+// of unknown type, isn't checked.
+
+// The types of values known before running the program, named like `Object::type_name`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Type {
+    Boolean,
+    Integer,
+    Float,
+    String,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Boolean => write!(f, "BOOLEAN"),
+            Type::Integer => write!(f, "INTEGER"),
+            Type::Float => write!(f, "FLOAT"),
+            Type::String => write!(f, "STRING"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ErrorKind {
+    IdentifierNotFound(String),
+    TypeMismatch(Infix, Type, Type),
+    UnknownInfixOperator(Infix, Type, Type),
+    UnknownPrefixOperator(Prefix, Type),
+    WrongArgumentCount {
+        function: String,
+        expected: usize,
+        given: usize,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SemanticError {
+    // The line of the statement the error is in.
+    pub line: Option<usize>,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        match &self.kind {
+            ErrorKind::IdentifierNotFound(name) => write!(f, "identifier not found: {}", name),
+            ErrorKind::TypeMismatch(infix, left, right) => {
+                write!(f, "type mismatch: {} {} {}", left, infix, right)
+            }
+            ErrorKind::UnknownInfixOperator(infix, left, right) => {
+                write!(f, "unknown infix operator: {} {} {}", left, infix, right)
+            }
+            ErrorKind::UnknownPrefixOperator(prefix, right) => {
+                write!(f, "unknown prefix operator: {}{}", prefix, right)
+            }
+            ErrorKind::WrongArgumentCount {
+                function,
+                expected,
+                given,
+            } => write!(
+                f,
+                "wrong number of arguments to `{}`: expected {}, given {}",
+                function, expected, given
+            ),
+        }
+    }
+}
+
+// The errors of the program, in the order of its statements.
+pub fn check(program: &Program) -> Vec<SemanticError> {
+    let mut bindings = vec![];
+    collect_bindings(&program.statements, &mut bindings);
+    let mut checker = Checker {
+        types: infer_types(&bindings),
+        arities: arities(&bindings),
+        scopes: vec![local_names(&program.statements)],
+        line: None,
+        errors: vec![],
+    };
+    checker.statements(&program.statements, &program.lines);
+    checker.errors
+}
+
+struct Checker {
+    // `None` when the type of a variable isn't certain.
+    types: HashMap<String, Option<Type>>,
+    // The number of parameters of the functions bound once, and never to anything else.
+    arities: HashMap<String, usize>,
+    // The names bound in each enclosing function, the innermost last. Blocks don't introduce
+    // a scope, so a name bound anywhere in a function is visible in all of it.
+    scopes: Vec<HashSet<String>>,
+    line: Option<usize>,
+    errors: Vec<SemanticError>,
+}
+
+impl Checker {
+    fn statements(&mut self, statements: &[Statement], lines: &[usize]) {
+        for (i, stmt) in statements.iter().enumerate() {
+            self.line = lines.get(i).copied();
+            match stmt {
+                Statement::Let(_, exp)
+                | Statement::Return(Some(exp))
+                | Statement::Print(Some(exp))
+                | Statement::Expression(exp) => {
+                    self.expression(exp);
+                }
+                Statement::Read(name) => self.identifier(name),
+                _ => {}
+            }
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatement) {
+        let line = self.line;
+        self.statements(&block.statements, &block.lines);
+        self.line = line;
+    }
+
+    // Checks `exp` and returns its type, if it is certain.
+    fn expression(&mut self, exp: &Expression) -> Option<Type> {
+        match exp {
+            Expression::Identifier(name) => {
+                self.identifier(name);
+                self.types.get(name).cloned().flatten()
+            }
+            Expression::Prefix(prefix, right) => {
+                let right = self.expression(right)?;
+                match prefix_type(prefix, right) {
+                    Ok(t) => Some(t),
+                    Err(kind) => self.error(kind),
+                }
+            }
+            Expression::Infix(infix, left, right) => self.infix(infix, left, right),
+            Expression::Assign(left, right) => self.infix(&Infix::Assign, left, right),
+            Expression::If(condition, consequence, alternative) => {
+                self.expression(condition);
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+                None
+            }
+            Expression::While(condition, consequence) => {
+                self.expression(condition);
+                self.block(consequence);
+                None
+            }
+            Expression::FunctionLiteral(parameters, body) => {
+                let mut scope = local_names(&body.statements);
+                scope.extend(parameters.iter().cloned());
+                self.scopes.push(scope);
+                self.block(body);
+                self.scopes.pop();
+                None
+            }
+            Expression::Call(function, arguments, _) => {
+                self.expression(function);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                if let Expression::Identifier(name) = function.as_ref() {
+                    match self.arities.get(name) {
+                        Some(&expected) if expected != arguments.len() => {
+                            self.error(ErrorKind::WrongArgumentCount {
+                                function: name.clone(),
+                                expected,
+                                given: arguments.len(),
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+                None
+            }
+            Expression::Array(values) => {
+                for value in values {
+                    self.expression(value);
+                }
+                None
+            }
+            Expression::Hash(pairs) => {
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+                None
+            }
+            Expression::Index(left, index) => {
+                self.expression(left);
+                self.expression(index);
+                None
+            }
+            _ => literal_type(exp),
+        }
+    }
+
+    fn infix(&mut self, infix: &Infix, left: &Expression, right: &Expression) -> Option<Type> {
+        let left = self.expression(left);
+        let right = self.expression(right);
+        match infix_type(infix, left, right) {
+            Ok(t) => t,
+            Err(kind) => self.error(kind),
+        }
+    }
+
+    fn identifier(&mut self, name: &str) {
+        let defined = self.scopes.iter().any(|scope| scope.contains(name));
+        if !defined && builtin::lookup(name).is_none() {
+            self.error(ErrorKind::IdentifierNotFound(name.to_string()));
+        }
+    }
+
+    fn error(&mut self, kind: ErrorKind) -> Option<Type> {
+        self.errors.push(SemanticError {
+            line: self.line,
+            kind,
+        });
+        None
+    }
+}
+
+// A place where a name gets a value, anywhere in the program.
+enum Binding<'a> {
+    // `int x;`, `real x;` and `string x;`.
+    Declaration(&'a str, Type),
+    Let(&'a str, &'a Expression),
+    Assign(&'a str, &'a Expression),
+    Read(&'a str),
+    Parameter(&'a str),
+}
+
+impl Binding<'_> {
+    fn name(&self) -> &str {
+        match self {
+            Binding::Declaration(name, _)
+            | Binding::Let(name, _)
+            | Binding::Assign(name, _)
+            | Binding::Read(name)
+            | Binding::Parameter(name) => name,
+        }
+    }
+}
+
+fn collect_bindings<'a>(statements: &'a [Statement], bindings: &mut Vec<Binding<'a>>) {
+    for stmt in statements {
+        match stmt {
+            Statement::Int(name) => bindings.push(Binding::Declaration(name, Type::Integer)),
+            Statement::Float(name) => bindings.push(Binding::Declaration(name, Type::Float)),
+            Statement::String(name) => bindings.push(Binding::Declaration(name, Type::String)),
+            Statement::Read(name) => bindings.push(Binding::Read(name)),
+            Statement::Let(name, exp) => {
+                bindings.push(Binding::Let(name, exp));
+                collect_expression_bindings(exp, bindings);
+            }
+            Statement::Return(Some(exp))
+            | Statement::Print(Some(exp))
+            | Statement::Expression(exp) => collect_expression_bindings(exp, bindings),
+            _ => {}
+        }
+    }
+}
+
+fn collect_expression_bindings<'a>(exp: &'a Expression, bindings: &mut Vec<Binding<'a>>) {
+    match exp {
+        Expression::Assign(left, right) | Expression::Infix(Infix::Assign, left, right) => {
+            if let Expression::Identifier(name) = left.as_ref() {
+                bindings.push(Binding::Assign(name, right));
+            }
+        }
+        Expression::FunctionLiteral(parameters, _) => {
+            bindings.extend(parameters.iter().map(|p| Binding::Parameter(p)));
+        }
+        _ => {}
+    }
+    let (expressions, blocks) = children(exp);
+    for exp in expressions {
+        collect_expression_bindings(exp, bindings);
+    }
+    for block in blocks {
+        collect_bindings(&block.statements, bindings);
+    }
+}
+
+// The types of the variables, found again until nothing changes, as a binding can take the
+// type of another variable bound later.
+fn infer_types(bindings: &[Binding]) -> HashMap<String, Option<Type>> {
+    let mut types = HashMap::new();
+    loop {
+        let mut changed = false;
+        for binding in bindings {
+            // Assigning keeps the type of the variable, or it's an error, except that an
+            // integer assigned a float becomes a float.
+            let t = match binding {
+                Binding::Declaration(_, t) => Some(*t),
+                Binding::Let(_, exp) => expression_type(exp, &types),
+                Binding::Assign(_, exp) => match expression_type(exp, &types) {
+                    Some(Type::Float) => Some(Type::Float),
+                    Some(_) => continue,
+                    None => None,
+                },
+                // `leia` reads numbers into numbers, and anything else as a string.
+                Binding::Read(name) => match types.get(*name).cloned().flatten() {
+                    Some(Type::Integer) | Some(Type::Float) | Some(Type::String) => continue,
+                    _ => None,
+                },
+                Binding::Parameter(_) => None,
+            };
+            let entry = types.entry(binding.name().to_string()).or_insert(t);
+            if *entry != t && entry.is_some() {
+                *entry = None;
+                changed = true;
+            }
+        }
+        if !changed {
+            return types;
+        }
+    }
+}
+
+fn arities(bindings: &[Binding]) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for binding in bindings {
+        *counts.entry(binding.name()).or_insert(0) += 1;
+    }
+    let mut arities = HashMap::new();
+    for binding in bindings {
+        if let Binding::Let(name, Expression::FunctionLiteral(parameters, _)) = binding {
+            if counts[name] == 1 {
+                arities.insert(name.to_string(), parameters.len());
+            }
+        }
+    }
+    for b in builtin::BUILTINS {
+        if !counts.contains_key(b.name) {
+            match b.name {
+                "len" | "first" | "last" | "rest" => arities.insert(b.name.to_string(), 1),
+                "push" => arities.insert(b.name.to_string(), 2),
+                _ => None,
+            };
+        }
+    }
+    arities
+}
+
+// The names bound in a function body, or in the program, without looking into nested
+// functions.
+fn local_names(statements: &[Statement]) -> HashSet<String> {
+    let mut names = HashSet::new();
+    collect_local_names(statements, &mut names);
+    names
+}
+
+fn collect_local_names(statements: &[Statement], names: &mut HashSet<String>) {
+    for stmt in statements {
+        match stmt {
+            Statement::Int(name)
+            | Statement::Float(name)
+            | Statement::String(name)
+            | Statement::Let(name, _) => {
+                names.insert(name.clone());
+            }
+            _ => {}
+        }
+        match stmt {
+            Statement::Let(_, exp)
+            | Statement::Return(Some(exp))
+            | Statement::Print(Some(exp))
+            | Statement::Expression(exp) => collect_expression_local_names(exp, names),
+            _ => {}
+        }
+    }
+}
+
+fn collect_expression_local_names(exp: &Expression, names: &mut HashSet<String>) {
+    let (expressions, blocks) = children(exp);
+    for exp in expressions {
+        collect_expression_local_names(exp, names);
+    }
+    for block in blocks {
+        collect_local_names(&block.statements, names);
+    }
+}
+
+// The expressions and blocks right inside `exp`, leaving out function bodies.
+fn children(exp: &Expression) -> (Vec<&Expression>, Vec<&BlockStatement>) {
+    match exp {
+        Expression::Prefix(_, right) => (vec![right.as_ref()], vec![]),
+        Expression::Infix(_, left, right)
+        | Expression::Assign(left, right)
+        | Expression::Index(left, right) => (vec![left.as_ref(), right.as_ref()], vec![]),
+        Expression::If(condition, consequence, alternative) => (
+            vec![condition.as_ref()],
+            std::iter::once(consequence).chain(alternative).collect(),
+        ),
+        Expression::While(condition, consequence) => (vec![condition.as_ref()], vec![consequence]),
+        Expression::Call(function, arguments, _) => (
+            std::iter::once(function.as_ref())
+                .chain(arguments)
+                .collect(),
+            vec![],
+        ),
+        Expression::Array(values) => (values.iter().collect(), vec![]),
+        Expression::Hash(pairs) => (pairs.iter().flat_map(|(k, v)| vec![k, v]).collect(), vec![]),
+        _ => (vec![], vec![]),
+    }
+}
+
+fn expression_type(exp: &Expression, types: &HashMap<String, Option<Type>>) -> Option<Type> {
+    match exp {
+        Expression::Identifier(name) => types.get(name).cloned().flatten(),
+        Expression::Prefix(prefix, right) => {
+            prefix_type(prefix, expression_type(right, types)?).ok()
+        }
+        Expression::Infix(infix, left, right) => infix_type(
+            infix,
+            expression_type(left, types),
+            expression_type(right, types),
+        )
+        .ok()
+        .flatten(),
+        Expression::Assign(left, right) => infix_type(
+            &Infix::Assign,
+            expression_type(left, types),
+            expression_type(right, types),
+        )
+        .ok()
+        .flatten(),
+        _ => literal_type(exp),
+    }
+}
+
+fn literal_type(exp: &Expression) -> Option<Type> {
+    match exp {
+        Expression::Int | Expression::IntegerLiteral(_) => Some(Type::Integer),
+        Expression::Float | Expression::FloatLiteral(_) => Some(Type::Float),
+        Expression::String | Expression::StringLiteral(_) => Some(Type::String),
+        Expression::Boolean(_) => Some(Type::Boolean),
+        _ => None,
+    }
+}
+
+fn prefix_type(prefix: &Prefix, right: Type) -> Result<Type, ErrorKind> {
+    match (prefix, right) {
+        // `!` works with any value.
+        (Prefix::Bang, _) => Ok(Type::Boolean),
+        (Prefix::Minus, Type::Integer) | (Prefix::Minus, Type::Float) => Ok(right),
+        (Prefix::Minus, _) => Err(ErrorKind::UnknownPrefixOperator(prefix.clone(), right)),
+    }
+}
+
+// The type of an infix expression, following the evaluator. Comparisons that don't fail give
+// booleans even when the type of an operand isn't known.
+fn infix_type(
+    infix: &Infix,
+    left: Option<Type>,
+    right: Option<Type>,
+) -> Result<Option<Type>, ErrorKind> {
+    let comparison = matches!(infix, Infix::Eq | Infix::NotEq | Infix::Lt | Infix::Gt);
+    let (left, right) = match (left, right) {
+        (Some(left), Some(right)) => (left, right),
+        _ if comparison => return Ok(Some(Type::Boolean)),
+        _ => return Ok(None),
+    };
+    let unknown = || ErrorKind::UnknownInfixOperator(infix.clone(), left, right);
+    let t = match (left, right) {
+        (Type::Boolean, Type::Boolean) => match infix {
+            Infix::Eq | Infix::NotEq | Infix::Assign => Type::Boolean,
+            _ => return Err(unknown()),
+        },
+        (Type::Integer, Type::Integer) if !comparison => Type::Integer,
+        (Type::Integer, Type::Float)
+        | (Type::Float, Type::Integer)
+        | (Type::Float, Type::Float)
+            if !comparison =>
+        {
+            Type::Float
+        }
+        (Type::Integer, _) | (Type::Float, _)
+            if comparison && matches!(right, Type::Integer | Type::Float) =>
+        {
+            Type::Boolean
+        }
+        (Type::String, Type::String) => match infix {
+            Infix::Plus | Infix::Assign => Type::String,
+            Infix::Eq => Type::Boolean,
+            _ => return Err(unknown()),
+        },
+        _ => return Err(ErrorKind::TypeMismatch(infix.clone(), left, right)),
+    };
+    Ok(Some(t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn errors(input: &str) -> Vec<String> {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        check(&program).iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn undefined_names() {
+        assert_eq!(
+            errors("imprima(naodefinida);\nleia x;\nf(1, 2);"),
+            vec![
+                "line 1: identifier not found: naodefinida",
+                "line 2: identifier not found: x",
+                "line 3: identifier not found: f",
+            ]
+        );
+        // Local names aren't seen outside their function.
+        assert_eq!(
+            errors("let f = funcao(a) { let b = a; b };\nimprima(a + b);"),
+            vec![
+                "line 2: identifier not found: a",
+                "line 2: identifier not found: b",
+            ]
+        );
+    }
+
+    #[test]
+    fn operand_types() {
+        assert_eq!(
+            errors(
+                "imprima(\"a\" * 3);
+int x;
+x = \"a\";
+string s;
+leia s;
+imprima(-s);
+se (VERDADEIRO > FALSO) { imprima(s != \"b\"); }"
+            ),
+            vec![
+                "line 1: type mismatch: STRING * INTEGER",
+                "line 3: type mismatch: INTEGER = STRING",
+                "line 6: unknown prefix operator: -STRING",
+                "line 7: unknown infix operator: BOOLEAN > BOOLEAN",
+                "line 7: unknown infix operator: STRING != STRING",
+            ]
+        );
+    }
+
+    #[test]
+    fn argument_counts() {
+        assert_eq!(
+            errors(
+                "let soma = funcao(a, b) { a + b };
+imprima(soma(1));
+imprima(len(\"a\", \"b\"));"
+            ),
+            vec![
+                "line 2: wrong number of arguments to `soma`: expected 2, given 1",
+                "line 3: wrong number of arguments to `len`: expected 1, given 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn valid_programs() {
+        let tests = vec![
+            // Recursion, and functions that use names bound after them.
+            "let f = funcao(n) { se (n > 0) { g(n - 1) } senao { 0 } };
+let g = funcao(n) { f(n) };
+imprima(f(3));",
+            // Closures, and names bound in blocks.
+            "let soma = funcao(a) { funcao(b) { a + b } };
+se (VERDADEIRO) { let y = soma(1)(2); }
+imprima(y + 1);",
+            // Integers become floats when assigned one, and bindings with other types.
+            "int x;
+x = 2.5;
+imprima(x * 2);
+let y = 1;
+let y = \"a\";
+imprima(y + \"b\");",
+            // A function bound again isn't checked.
+            "let f = funcao(a) { a };
+let f = funcao(a, b) { a };
+imprima(f(1, 2));
+imprima(len([1]) + first([null]));",
+            "real r;
+leia r;
+imprima(r / 2 + 1);
+imprima(1 == 1.0);
+imprima(!\"a\");",
+        ];
+        for input in tests {
+            assert_eq!(errors(input), Vec::<String>::new(), "for `{}`", input);
+        }
+    }
+}
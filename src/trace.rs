@@ -0,0 +1,186 @@
+// Renders the steps recorded by `Evaluator::set_tracing` as a desk-check table, with a row per
+// step and a column per variable.
+use crate::evaluator::TraceStep;
+use crate::grader::csv_field;
+use crate::repl::portugol_value;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Text,
+    Markdown,
+    Csv,
+}
+
+pub fn render(steps: &[TraceStep], format: Format) -> String {
+    let table = table(steps);
+    match format {
+        Format::Text => text(&table),
+        Format::Markdown => markdown(&table),
+        Format::Csv => table
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| csv_field(cell))
+                    .collect::<Vec<String>>()
+                    .join(",")
+                    + "\n"
+            })
+            .collect(),
+    }
+}
+
+// The header and the rows. Variables are in the order they first show up, and their cell is
+// empty where they aren't in scope.
+fn table(steps: &[TraceStep]) -> Vec<Vec<String>> {
+    let mut names: Vec<&str> = vec![];
+    for step in steps {
+        for (name, _) in &step.variables {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+    }
+    let mut header = vec!["linha".to_string(), "comando".to_string()];
+    header.extend(names.iter().map(|name| name.to_string()));
+    let mut table = vec![header];
+    for step in steps {
+        let mut row = vec![
+            step.line.map_or(String::new(), |line| line.to_string()),
+            step.statement.clone(),
+        ];
+        for name in &names {
+            let value = step.variables.iter().find(|(n, _)| n == name);
+            row.push(value.map_or(String::new(), |(_, value)| portugol_value(value)));
+        }
+        table.push(row);
+    }
+    table
+}
+
+fn text(table: &[Vec<String>]) -> String {
+    let mut widths = vec![0; table[0].len()];
+    for row in table {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    // Empty cells at the end are left out.
+    let line = |row: &[String]| {
+        let len = row
+            .iter()
+            .rposition(|cell| !cell.is_empty())
+            .map_or(0, |i| i + 1);
+        row[..len]
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+            + "\n"
+    };
+    let mut out = line(&table[0]);
+    out.push_str(
+        &widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<String>>()
+            .join("-+-"),
+    );
+    out.push('\n');
+    for row in &table[1..] {
+        out.push_str(&line(row));
+    }
+    out
+}
+
+fn markdown(table: &[Vec<String>]) -> String {
+    let line = |row: &[String]| {
+        let cells = row
+            .iter()
+            .map(|cell| cell.replace('|', "\\|"))
+            .collect::<Vec<String>>();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let mut out = line(&table[0]);
+    out.push_str(&format!("|{}\n", " --- |".repeat(table[0].len())));
+    for row in &table[1..] {
+        out.push_str(&line(row));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, Format};
+    use crate::evaluator::Evaluator;
+    use crate::lexer::Lexer;
+    use crate::object::Environment;
+    use crate::parser::Parser;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn trace(input: &str, format: Format) -> String {
+        let program = Parser::new(Lexer::new(input.to_string())).parse_program();
+        let (mut stdin, mut output) = (&b""[..], vec![]);
+        let mut evaluator = Evaluator::new(&mut stdin, &mut output);
+        evaluator.set_tracing(true);
+        evaluator
+            .eval(&program, Rc::new(RefCell::new(Environment::new())))
+            .unwrap();
+        render(evaluator.trace(), format)
+    }
+
+    #[test]
+    fn text() {
+        let input = "int i;
+enquanto (i < 2) {
+    i = i + 1;
+}
+let dobro = funcao(n) { retorne n * 2; };
+string s;
+s = \"a|b\";
+dobro(i);";
+        // Variables show up when they are declared, and `n` only inside `dobro`.
+        let expected = "linha | comando                        | i | s     | n
+------+--------------------------------+---+-------+--
+1     | int i;                         | 0
+2     | enquanto (i < 2): VERDADEIRO   | 0
+3     | i = i + 1;                     | 1
+2     | enquanto (i < 2): VERDADEIRO   | 1
+3     | i = i + 1;                     | 2
+2     | enquanto (i < 2): FALSO        | 2
+5     | let dobro = funcao(n) { ... }; | 2
+6     | string s;                      | 2 | \"\"
+7     | s = \"a|b\";                     | 2 | \"a|b\"
+5     | retorne n * 2;                 | 2 | \"a|b\" | 2
+8     | dobro(i);                      | 2 | \"a|b\"
+";
+        assert_eq!(trace(input, Format::Text), expected);
+    }
+
+    #[test]
+    fn markdown_and_csv() {
+        let input = "int x; x = 2; string s; s = \"a|b, c\";";
+        assert_eq!(
+            trace(input, Format::Markdown),
+            "| linha | comando | x | s |
+| --- | --- | --- | --- |
+| 1 | int x; | 0 |  |
+| 1 | x = 2; | 2 |  |
+| 1 | string s; | 2 | \"\" |
+| 1 | s = \"a\\|b, c\"; | 2 | \"a\\|b, c\" |
+"
+        );
+        assert_eq!(
+            trace(input, Format::Csv),
+            "linha,comando,x,s
+1,int x;,0,
+1,x = 2;,2,
+1,string s;,2,\"\"\"\"\"\"
+1,\"s = \"\"a|b, c\"\";\",2,\"\"\"a|b, c\"\"\"
+"
+        );
+    }
+}
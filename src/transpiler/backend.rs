@@ -0,0 +1,125 @@
+use super::mangle::NameTable;
+use crate::ast::{Expression, Program, Statement};
+use std::fmt;
+use std::io::{self, Write};
+
+const SPACES: usize = 4;
+
+// Marks the Portugol line in annotations.
+pub const ANNOTATION: &str = "portugol:";
+
+// A target language of the transpiler.
+//
+// The program a backend holds has already been renamed (see `NameTable`) and rewritten as the
+// target needs. `transpile` writes the prologue, every top level statement, then the epilogue.
+pub trait Backend {
+    fn program(&self) -> &Program;
+
+    // Portugol names that had to be renamed in the generated code.
+    fn names(&self) -> &NameTable;
+
+    // How line comments start in the target language.
+    fn comment(&self) -> &'static str;
+
+    // Whether the code generated for each statement is annotated with the Portugol line it
+    // comes from, like `x = 1  # portugol:3`. See `SourceMap`.
+    fn set_annotate(&mut self, annotate: bool);
+
+    // Code before the first statement, like imports, runtime helpers and declarations.
+    fn prologue(&self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn epilogue(&self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    // The indentation level of top level statements.
+    fn top_level(&self) -> usize {
+        0
+    }
+
+    // `line` is the Portugol line of the statement, when known.
+    fn statement(
+        &self,
+        out: &mut dyn Write,
+        stmt: &Statement,
+        line: Option<usize>,
+        indent_level: usize,
+    ) -> io::Result<()>;
+
+    fn expression(&self, exp: &Expression) -> String;
+
+    fn indent(&self, indent_level: usize) -> String {
+        indent(indent_level)
+    }
+
+    fn transpile(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.prologue(out)?;
+        let program = self.program();
+        for (i, stmt) in program.statements.iter().enumerate() {
+            self.statement(out, stmt, program.line(i), self.top_level())?;
+        }
+        self.epilogue(out)
+    }
+
+    fn transpile_to_string(&self) -> String {
+        let mut out = vec![];
+        // Writing to a `Vec` never fails.
+        self.transpile(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+}
+
+pub fn indent(indent_level: usize) -> String {
+    " ".repeat(indent_level * SPACES)
+}
+
+// Runs `emit`, annotating the first line it writes with the Portugol `line`, if any.
+pub fn annotated<F>(
+    out: &mut dyn Write,
+    comment: &str,
+    line: Option<usize>,
+    emit: F,
+) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let line = match line {
+        Some(line) => line,
+        None => return emit(out),
+    };
+    let mut code = vec![];
+    emit(&mut code)?;
+    let mut code = String::from_utf8(code).expect("generated code is UTF-8");
+    annotate(&mut code, comment, line);
+    out.write_all(code.as_bytes())
+}
+
+// Appends the annotation of the Portugol `line` to the first line of `code`.
+pub fn annotate(code: &mut String, comment: &str, line: usize) {
+    if let Some(end) = code.find('\n') {
+        code.insert_str(end, &format!("  {} {}{}", comment, ANNOTATION, line));
+    }
+}
+
+#[derive(Debug)]
+pub enum BackendError {
+    UnknownTarget(String),
+    // The backend can't translate this program, for the reason given by its own error.
+    Rejected(String),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackendError::UnknownTarget(target) => write!(
+                f,
+                "unknown target `{}`, expected one of: {}",
+                target,
+                super::TARGETS.join(", ")
+            ),
+            BackendError::Rejected(reason) => write!(f, "{}", reason),
+        }
+    }
+}
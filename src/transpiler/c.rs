@@ -0,0 +1,1709 @@
+use super::backend::{annotate, indent, Backend};
+use super::mangle::{NameTable, Reserved};
+use super::{block_value, collect_locals, Type};
+use crate::ast::{BlockStatement, Expression, Infix, Prefix, Program, Statement};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+
+// C99 backend. Portugol is dynamically typed, so the types of variables, parameters and
+// return values are inferred from how the program uses them. Programs whose types can't be
+// pinned down, or that use arrays, hashes or closures, are rejected with a `CError`.
+
+// Included at the top of every generated file, so it compiles on its own with
+// `gcc -std=c99 programa.c`. The helpers are `static inline` to keep unused ones quiet.
+const RUNTIME: &str = r#"#include <errno.h>
+#include <limits.h>
+#include <math.h>
+#include <stdbool.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+// Stops the program with a runtime error, like the interpreter.
+static inline void pt_fail(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "ERRO DE EXECUÇÃO: %s\n", message);
+    exit(1);
+}
+
+static inline void pt_overflow(long long a, const char *operator, long long b) {
+    char message[96];
+    snprintf(message, sizeof message, "integer overflow: %lld %s %lld", a, operator, b);
+    pt_fail(message);
+}
+
+// Integer arithmetic stops on overflow instead of being undefined.
+static inline long long pt_add(long long a, long long b) {
+    long long result;
+    if (__builtin_add_overflow(a, b, &result)) {
+        pt_overflow(a, "+", b);
+    }
+    return result;
+}
+
+static inline long long pt_sub(long long a, long long b) {
+    long long result;
+    if (__builtin_sub_overflow(a, b, &result)) {
+        pt_overflow(a, "-", b);
+    }
+    return result;
+}
+
+static inline long long pt_mul(long long a, long long b) {
+    long long result;
+    if (__builtin_mul_overflow(a, b, &result)) {
+        pt_overflow(a, "*", b);
+    }
+    return result;
+}
+
+// C truncates like the interpreter, but a zero divisor or `LLONG_MIN / -1` would crash the
+// program.
+static inline long long pt_div(long long a, long long b) {
+    if (b == 0) {
+        pt_fail("division by zero");
+    }
+    if (a == LLONG_MIN && b == -1) {
+        pt_overflow(a, "/", b);
+    }
+    return a / b;
+}
+
+static inline long long pt_neg(long long a) {
+    if (a == LLONG_MIN) {
+        char message[64];
+        snprintf(message, sizeof message, "integer overflow: -(%lld)", a);
+        pt_fail(message);
+    }
+    return -a;
+}
+
+// Strings are immutable and never freed.
+typedef struct {
+    size_t length;
+    const char *chars;
+} pt_string;
+
+#define pt_str(literal) ((pt_string){sizeof(literal) - 1, literal})
+
+static inline pt_string pt_concat(pt_string a, pt_string b) {
+    char *chars = malloc(a.length + b.length + 1);
+    memcpy(chars, a.chars, a.length);
+    memcpy(chars + a.length, b.chars, b.length);
+    chars[a.length + b.length] = '\0';
+    return (pt_string){a.length + b.length, chars};
+}
+
+static inline bool pt_equals(pt_string a, pt_string b) {
+    return a.length == b.length && memcmp(a.chars, b.chars, a.length) == 0;
+}
+
+// Reads a line into `buffer`, without the line break, like `leia` in the interpreter. The end
+// of a line that doesn't fit is dropped. Stops the program at the end of the input.
+static inline void pt_read_line(char *buffer, int size) {
+    if (fgets(buffer, size, stdin) == NULL) {
+        pt_fail("end of input");
+    }
+    size_t length = strcspn(buffer, "\r\n");
+    if (buffer[length] == '\0') {
+        int c;
+        while ((c = getchar()) != '\n' && c != EOF) {
+        }
+    }
+    buffer[length] = '\0';
+}
+
+static inline void pt_invalid_input(const char *input, const char *type) {
+    char message[128];
+    snprintf(message, sizeof message, "invalid input for %s: `%s`", type, input);
+    pt_fail(message);
+}
+
+// Whether only blanks follow a number that ends at `end`.
+static inline bool pt_blank(const char *end) {
+    return end[strspn(end, " \t\v\f")] == '\0';
+}
+
+static inline long long pt_read_int(void) {
+    char buffer[64];
+    pt_read_line(buffer, sizeof buffer);
+    char *end;
+    errno = 0;
+    long long value = strtoll(buffer, &end, 10);
+    if (end == buffer || errno == ERANGE || !pt_blank(end)) {
+        pt_invalid_input(buffer, "INTEGER");
+    }
+    return value;
+}
+
+// Like in the interpreter, numbers out of range read as infinities or zero.
+static inline double pt_read_float(void) {
+    char buffer[64];
+    pt_read_line(buffer, sizeof buffer);
+    char *end;
+    double value = strtod(buffer, &end);
+    if (end == buffer || !pt_blank(end)) {
+        pt_invalid_input(buffer, "FLOAT");
+    }
+    return value;
+}
+
+static inline pt_string pt_read_string(void) {
+    char buffer[1024];
+    pt_read_line(buffer, sizeof buffer);
+    size_t length = strlen(buffer);
+    char *chars = malloc(length + 1);
+    memcpy(chars, buffer, length + 1);
+    return (pt_string){length, chars};
+}
+
+static inline void pt_print_string(pt_string s) {
+    fwrite(s.chars, 1, s.length, stdout);
+}
+
+// The shortest form that reads back as the same value, like the interpreter prints floats.
+static inline void pt_print_float(double value) {
+    char buffer[32];
+    for (int precision = 1; precision <= 17; precision++) {
+        snprintf(buffer, sizeof buffer, "%.*g", precision, value);
+        if (strtod(buffer, NULL) == value) {
+            break;
+        }
+    }
+    printf("%s", buffer);
+}
+"#;
+
+const C: Reserved = Reserved {
+    keywords: &[
+        "_Bool",
+        "_Complex",
+        "_Imaginary",
+        "auto",
+        "bool",
+        "break",
+        "case",
+        "char",
+        "const",
+        "continue",
+        "default",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extern",
+        "false",
+        "float",
+        "for",
+        "goto",
+        "if",
+        "inline",
+        "int",
+        "long",
+        "register",
+        "restrict",
+        "return",
+        "short",
+        "signed",
+        "sizeof",
+        "static",
+        "struct",
+        "switch",
+        "true",
+        "typedef",
+        "union",
+        "unsigned",
+        "void",
+        "volatile",
+        "while",
+    ],
+    // The runtime, and every name the C99 headers it includes declare. Most of them are
+    // functions or macros, a variable with the same name wouldn't compile.
+    builtins: &[
+        "BUFSIZ",
+        "CHAR_BIT",
+        "CHAR_MAX",
+        "CHAR_MIN",
+        "EDOM",
+        "EILSEQ",
+        "EOF",
+        "ERANGE",
+        "EXIT_FAILURE",
+        "EXIT_SUCCESS",
+        "FILE",
+        "FILENAME_MAX",
+        "FOPEN_MAX",
+        "FP_FAST_FMA",
+        "FP_FAST_FMAF",
+        "FP_FAST_FMAL",
+        "FP_ILOGB0",
+        "FP_ILOGBNAN",
+        "FP_INFINITE",
+        "FP_NAN",
+        "FP_NORMAL",
+        "FP_SUBNORMAL",
+        "FP_ZERO",
+        "HUGE_VAL",
+        "HUGE_VALF",
+        "HUGE_VALL",
+        "INFINITY",
+        "INT_MAX",
+        "INT_MIN",
+        "LLONG_MAX",
+        "LLONG_MIN",
+        "LONG_MAX",
+        "LONG_MIN",
+        "L_tmpnam",
+        "MATH_ERREXCEPT",
+        "MATH_ERRNO",
+        "MB_CUR_MAX",
+        "MB_LEN_MAX",
+        "NAN",
+        "NULL",
+        "RAND_MAX",
+        "SCHAR_MAX",
+        "SCHAR_MIN",
+        "SEEK_CUR",
+        "SEEK_END",
+        "SEEK_SET",
+        "SHRT_MAX",
+        "SHRT_MIN",
+        "TMP_MAX",
+        "UCHAR_MAX",
+        "UINT_MAX",
+        "ULLONG_MAX",
+        "ULONG_MAX",
+        "USHRT_MAX",
+        "_Exit",
+        "_IOFBF",
+        "_IOLBF",
+        "_IONBF",
+        "__bool_true_false_are_defined",
+        "abort",
+        "abs",
+        "acos",
+        "acosf",
+        "acosh",
+        "acoshf",
+        "acoshl",
+        "acosl",
+        "asin",
+        "asinf",
+        "asinh",
+        "asinhf",
+        "asinhl",
+        "asinl",
+        "atan",
+        "atan2",
+        "atan2f",
+        "atan2l",
+        "atanf",
+        "atanh",
+        "atanhf",
+        "atanhl",
+        "atanl",
+        "atexit",
+        "atof",
+        "atoi",
+        "atol",
+        "atoll",
+        "bsearch",
+        "calloc",
+        "cbrt",
+        "cbrtf",
+        "cbrtl",
+        "ceil",
+        "ceilf",
+        "ceill",
+        "clearerr",
+        "copysign",
+        "copysignf",
+        "copysignl",
+        "cos",
+        "cosf",
+        "cosh",
+        "coshf",
+        "coshl",
+        "cosl",
+        "div",
+        "div_t",
+        "double_t",
+        "erf",
+        "erfc",
+        "erfcf",
+        "erfcl",
+        "erff",
+        "erfl",
+        "errno",
+        "exit",
+        "exp",
+        "exp2",
+        "exp2f",
+        "exp2l",
+        "expf",
+        "expl",
+        "expm1",
+        "expm1f",
+        "expm1l",
+        "fabs",
+        "fabsf",
+        "fabsl",
+        "fclose",
+        "fdim",
+        "fdimf",
+        "fdiml",
+        "feof",
+        "ferror",
+        "fflush",
+        "fgetc",
+        "fgetpos",
+        "fgets",
+        "float_t",
+        "floor",
+        "floorf",
+        "floorl",
+        "fma",
+        "fmaf",
+        "fmal",
+        "fmax",
+        "fmaxf",
+        "fmaxl",
+        "fmin",
+        "fminf",
+        "fminl",
+        "fmod",
+        "fmodf",
+        "fmodl",
+        "fopen",
+        "fpclassify",
+        "fpos_t",
+        "fprintf",
+        "fputc",
+        "fputs",
+        "fread",
+        "free",
+        "freopen",
+        "frexp",
+        "frexpf",
+        "frexpl",
+        "fscanf",
+        "fseek",
+        "fsetpos",
+        "ftell",
+        "fwrite",
+        "getc",
+        "getchar",
+        "getenv",
+        "gets",
+        "hypot",
+        "hypotf",
+        "hypotl",
+        "ilogb",
+        "ilogbf",
+        "ilogbl",
+        "isfinite",
+        "isgreater",
+        "isgreaterequal",
+        "isinf",
+        "isless",
+        "islessequal",
+        "islessgreater",
+        "isnan",
+        "isnormal",
+        "isunordered",
+        "labs",
+        "ldexp",
+        "ldexpf",
+        "ldexpl",
+        "ldiv",
+        "ldiv_t",
+        "lgamma",
+        "lgammaf",
+        "lgammal",
+        "llabs",
+        "lldiv",
+        "lldiv_t",
+        "llrint",
+        "llrintf",
+        "llrintl",
+        "llround",
+        "llroundf",
+        "llroundl",
+        "log",
+        "log10",
+        "log10f",
+        "log10l",
+        "log1p",
+        "log1pf",
+        "log1pl",
+        "log2",
+        "log2f",
+        "log2l",
+        "logb",
+        "logbf",
+        "logbl",
+        "logf",
+        "logl",
+        "lrint",
+        "lrintf",
+        "lrintl",
+        "lround",
+        "lroundf",
+        "lroundl",
+        "main",
+        "malloc",
+        "math_errhandling",
+        "mblen",
+        "mbstowcs",
+        "mbtowc",
+        "memchr",
+        "memcmp",
+        "memcpy",
+        "memmove",
+        "memset",
+        "modf",
+        "modff",
+        "modfl",
+        "nan",
+        "nanf",
+        "nanl",
+        "nearbyint",
+        "nearbyintf",
+        "nearbyintl",
+        "nextafter",
+        "nextafterf",
+        "nextafterl",
+        "nexttoward",
+        "nexttowardf",
+        "nexttowardl",
+        "perror",
+        "pow",
+        "powf",
+        "powl",
+        "printf",
+        "pt_add",
+        "pt_blank",
+        "pt_concat",
+        "pt_div",
+        "pt_equals",
+        "pt_fail",
+        "pt_invalid_input",
+        "pt_mul",
+        "pt_neg",
+        "pt_overflow",
+        "pt_print_float",
+        "pt_print_string",
+        "pt_read_float",
+        "pt_read_int",
+        "pt_read_line",
+        "pt_read_string",
+        "pt_str",
+        "pt_string",
+        "pt_sub",
+        "putc",
+        "putchar",
+        "puts",
+        "qsort",
+        "rand",
+        "realloc",
+        "remainder",
+        "remainderf",
+        "remainderl",
+        "remove",
+        "remquo",
+        "remquof",
+        "remquol",
+        "rename",
+        "rewind",
+        "rint",
+        "rintf",
+        "rintl",
+        "round",
+        "roundf",
+        "roundl",
+        "scalbln",
+        "scalblnf",
+        "scalblnl",
+        "scalbn",
+        "scalbnf",
+        "scalbnl",
+        "scanf",
+        "setbuf",
+        "setvbuf",
+        "signbit",
+        "sin",
+        "sinf",
+        "sinh",
+        "sinhf",
+        "sinhl",
+        "sinl",
+        "size_t",
+        "snprintf",
+        "sprintf",
+        "sqrt",
+        "sqrtf",
+        "sqrtl",
+        "srand",
+        "sscanf",
+        "stderr",
+        "stdin",
+        "stdout",
+        "strcat",
+        "strchr",
+        "strcmp",
+        "strcoll",
+        "strcpy",
+        "strcspn",
+        "strerror",
+        "strlen",
+        "strncat",
+        "strncmp",
+        "strncpy",
+        "strpbrk",
+        "strrchr",
+        "strspn",
+        "strstr",
+        "strtod",
+        "strtof",
+        "strtok",
+        "strtol",
+        "strtold",
+        "strtoll",
+        "strtoul",
+        "strtoull",
+        "strxfrm",
+        "system",
+        "tan",
+        "tanf",
+        "tanh",
+        "tanhf",
+        "tanhl",
+        "tanl",
+        "tgamma",
+        "tgammaf",
+        "tgammal",
+        "tmpfile",
+        "tmpnam",
+        "trunc",
+        "truncf",
+        "truncl",
+        "ungetc",
+        "vfprintf",
+        "vfscanf",
+        "vprintf",
+        "vscanf",
+        "vsnprintf",
+        "vsprintf",
+        "vsscanf",
+        "wchar_t",
+        "wcstombs",
+        "wctomb",
+    ],
+    ascii: true,
+};
+
+type Result<T> = std::result::Result<T, CError>;
+
+#[derive(Debug, PartialEq)]
+pub enum CError {
+    Unsupported(String),
+    UnknownType(String),
+    TypeMismatch(String, &'static str, &'static str),
+    ExpectedBoolean(Expression),
+}
+
+impl fmt::Display for CError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CError::Unsupported(what) => write!(f, "not supported in C: {}", what),
+            CError::UnknownType(what) => write!(f, "can't infer the type of {}", what),
+            CError::TypeMismatch(name, t1, t2) => {
+                write!(f, "`{}` is used both as {} and as {}", name, t1, t2)
+            }
+            CError::ExpectedBoolean(exp) => write!(f, "condition is not a boolean: {}", exp),
+        }
+    }
+}
+
+// Variables of a scope, in the order they were found.
+#[derive(Default)]
+struct Variables {
+    names: Vec<String>,
+    types: HashMap<String, Option<Type>>,
+}
+
+impl Variables {
+    fn declare(&mut self, name: &str) {
+        if !self.types.contains_key(name) {
+            self.names.push(name.to_string());
+            self.types.insert(name.to_string(), None);
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.types.contains_key(name)
+    }
+
+    fn get(&self, name: &str) -> Option<Type> {
+        self.types.get(name).cloned().flatten()
+    }
+}
+
+struct Function {
+    parameters: Vec<String>,
+    body: BlockStatement,
+    // Parameters and names bound in the body.
+    locals: Variables,
+    // `None` for functions that return nothing.
+    return_type: Option<Type>,
+}
+
+pub struct CTranspiler {
+    program: Program,
+    names: NameTable,
+    context: Context,
+    // Everything before the statements of `main`.
+    prologue: String,
+}
+
+impl CTranspiler {
+    pub fn new(program: Program) -> Result<Self> {
+        let names = NameTable::new(&program, &C);
+        let program = names.rename(&program);
+
+        let mut context = Context::new(&program)?;
+        // Every pass can only fill in types, so this ends.
+        loop {
+            context.changed = false;
+            context.infer_statements(&program.statements, None, false)?;
+            if !context.changed {
+                break;
+            }
+        }
+        // The body of `main` is checked first, so that unsupported constructs are reported
+        // before the variables they leave untyped.
+        for stmt in &program.statements {
+            context.statement(&mut String::new(), stmt, None, 1, false)?;
+        }
+        let prologue = context.prologue()?;
+        Ok(CTranspiler {
+            program,
+            names,
+            context,
+            prologue,
+        })
+    }
+}
+
+impl Backend for CTranspiler {
+    fn program(&self) -> &Program {
+        &self.program
+    }
+
+    fn names(&self) -> &NameTable {
+        &self.names
+    }
+
+    fn comment(&self) -> &'static str {
+        "//"
+    }
+
+    // Functions are part of the prologue, so it's generated again.
+    fn set_annotate(&mut self, annotate: bool) {
+        self.context.annotate = annotate;
+        self.prologue = self
+            .context
+            .prologue()
+            .expect("the program is checked in `CTranspiler::new`");
+    }
+
+    fn prologue(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(self.prologue.as_bytes())
+    }
+
+    fn epilogue(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{}return 0;", self.indent(1))?;
+        writeln!(out, "}}")
+    }
+
+    // Inside `main`.
+    fn top_level(&self) -> usize {
+        1
+    }
+
+    fn statement(
+        &self,
+        out: &mut dyn Write,
+        stmt: &Statement,
+        line: Option<usize>,
+        indent_level: usize,
+    ) -> io::Result<()> {
+        let mut code = String::new();
+        self.context
+            .statement(&mut code, stmt, None, indent_level, false)
+            .expect("the program is checked in `CTranspiler::new`");
+        if let Some(line) = line.filter(|_| self.context.annotate) {
+            annotate(&mut code, self.comment(), line);
+        }
+        out.write_all(code.as_bytes())
+    }
+
+    // Renders an expression as C source, or as a comment explaining why it can't be.
+    fn expression(&self, exp: &Expression) -> String {
+        self.context
+            .expression(exp, None)
+            .unwrap_or_else(|err| format!("/* {} */", err))
+    }
+}
+
+// C operator precedence, from the loosest to the tightest binding.
+#[derive(PartialEq, PartialOrd, Clone, Copy)]
+enum Precedence {
+    Assignment,  // x = y
+    Conditional, // c ? x : y
+    Equality,    // ==, !=
+    Relational,  // <, >
+    Sum,         // +, -
+    Product,     // *, /
+    Unary,       // -x, !x
+    Postfix,     // f(x)
+    Atom,        // literals and names
+}
+
+struct Context {
+    globals: Variables,
+    // Top level functions, in the order they are defined.
+    functions: Vec<(String, Function)>,
+    // Whether the last inference pass found a new type.
+    changed: bool,
+    // See `Backend::set_annotate`.
+    annotate: bool,
+}
+
+impl Context {
+    fn new(program: &Program) -> Result<Self> {
+        let mut context = Context {
+            globals: Variables::default(),
+            functions: vec![],
+            changed: false,
+            annotate: false,
+        };
+        let mut globals = HashSet::new();
+        collect_locals(&program.statements, &mut globals);
+        for stmt in &program.statements {
+            if let Statement::Let(name, Expression::FunctionLiteral(parameters, body)) = stmt {
+                if context.function(name).is_some() {
+                    return Err(CError::Unsupported(format!("redefining function {}", name)));
+                }
+                let mut locals = Variables::default();
+                for parameter in parameters {
+                    locals.declare(parameter);
+                }
+                let mut names = HashSet::new();
+                collect_locals(&body.statements, &mut names);
+                let mut names = names.into_iter().collect::<Vec<String>>();
+                names.sort();
+                for name in names {
+                    locals.declare(&name);
+                }
+                context.functions.push((
+                    name.clone(),
+                    Function {
+                        parameters: parameters.clone(),
+                        body: body.clone(),
+                        locals,
+                        return_type: None,
+                    },
+                ));
+            }
+        }
+        let mut globals = globals
+            .into_iter()
+            .filter(|name| context.function(name).is_none())
+            .collect::<Vec<String>>();
+        globals.sort();
+        for name in globals {
+            context.globals.declare(&name);
+        }
+        Ok(context)
+    }
+
+    fn function(&self, name: &str) -> Option<&Function> {
+        self.functions
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, function)| function)
+    }
+
+    fn function_mut(&mut self, name: &str) -> Option<&mut Function> {
+        self.functions
+            .iter_mut()
+            .find(|(n, _)| n == name)
+            .map(|(_, function)| function)
+    }
+
+    // The variables `name` refers to from inside `function` (or the top level).
+    fn scope_mut(&mut self, function: Option<&str>, name: &str) -> &mut Variables {
+        if let Some(f) = function {
+            let is_local = self.function(f).is_some_and(|f| f.locals.contains(name));
+            if is_local {
+                return &mut self.function_mut(f).unwrap().locals;
+            }
+        }
+        &mut self.globals
+    }
+
+    fn variable_type(&self, function: Option<&str>, name: &str) -> Option<Type> {
+        match function.and_then(|f| self.function(f)) {
+            Some(f) if f.locals.contains(name) => f.locals.get(name),
+            _ => self.globals.get(name),
+        }
+    }
+
+    fn set_type(&mut self, function: Option<&str>, name: &str, t: Option<Type>) -> Result<()> {
+        let t = match t {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        let variables = self.scope_mut(function, name);
+        variables.declare(name);
+        match variables.get(name) {
+            None => {
+                variables.types.insert(name.to_string(), Some(t));
+                self.changed = true;
+                Ok(())
+            }
+            Some(previous) if previous == t => Ok(()),
+            Some(previous) => Err(CError::TypeMismatch(
+                name.to_string(),
+                type_name(previous),
+                type_name(t),
+            )),
+        }
+    }
+
+    fn set_return_type(&mut self, function: &str, t: Option<Type>) -> Result<()> {
+        let (f, t) = match (self.function_mut(function), t) {
+            (Some(f), Some(t)) => (f, t),
+            _ => return Ok(()),
+        };
+        match f.return_type {
+            None => {
+                f.return_type = Some(t);
+                self.changed = true;
+                Ok(())
+            }
+            Some(previous) if previous == t => Ok(()),
+            Some(previous) => Err(CError::TypeMismatch(
+                format!("the value returned by {}", function),
+                type_name(previous),
+                type_name(t),
+            )),
+        }
+    }
+
+    // With `implicit_return`, the last expression of the block is the value of `function`.
+    fn infer_statements(
+        &mut self,
+        statements: &[Statement],
+        function: Option<&str>,
+        implicit_return: bool,
+    ) -> Result<()> {
+        for (i, stmt) in statements.iter().enumerate() {
+            let is_last = implicit_return && i == statements.len() - 1;
+            match stmt {
+                Statement::Let(name, Expression::FunctionLiteral(_, body))
+                    if function.is_none() && self.function(name).is_some() =>
+                {
+                    self.infer_statements(&body.statements, Some(name), true)?
+                }
+                Statement::Let(name, value) => {
+                    self.infer_expression(value, function)?;
+                    let t = self.expression_type(value, function);
+                    self.set_type(function, name, t)?
+                }
+                Statement::Int(name) => self.set_type(function, name, Some(Type::Int))?,
+                Statement::Float(name) => self.set_type(function, name, Some(Type::Float))?,
+                Statement::String(name) => self.set_type(function, name, Some(Type::Str))?,
+                Statement::Return(Some(value)) => {
+                    self.infer_expression(value, function)?;
+                    if let Some(f) = function {
+                        let t = self.expression_type(value, function);
+                        self.set_return_type(f, t)?;
+                    }
+                }
+                Statement::Print(Some(value)) => self.infer_expression(value, function)?,
+                Statement::Expression(Expression::If(condition, consequence, alternative)) => {
+                    self.infer_expression(condition, function)?;
+                    self.infer_statements(&consequence.statements, function, is_last)?;
+                    if let Some(alt) = alternative {
+                        self.infer_statements(&alt.statements, function, is_last)?;
+                    }
+                }
+                Statement::Expression(exp) => {
+                    self.infer_expression(exp, function)?;
+                    let returns_value =
+                        !matches!(exp, Expression::While(_, _) | Expression::Assign(_, _));
+                    if let (true, true, Some(f)) = (is_last, returns_value, function) {
+                        let t = self.expression_type(exp, function);
+                        self.set_return_type(f, t)?;
+                    }
+                }
+                Statement::Return(None) | Statement::Print(None) | Statement::Read(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Looks for assignments and calls, which give types to variables and parameters.
+    fn infer_expression(&mut self, exp: &Expression, function: Option<&str>) -> Result<()> {
+        match exp {
+            Expression::Assign(left, right) | Expression::Infix(Infix::Assign, left, right) => {
+                self.infer_expression(right, function)?;
+                if let Expression::Identifier(name) = left.as_ref() {
+                    let t = self.expression_type(right, function);
+                    self.set_type(function, name, t)?;
+                }
+            }
+            Expression::Call(callee, arguments, _) => {
+                for argument in arguments {
+                    self.infer_expression(argument, function)?;
+                }
+                if let Expression::Identifier(name) = callee.as_ref() {
+                    let parameters = match self.function(name) {
+                        Some(f) => f.parameters.clone(),
+                        None => return Ok(()),
+                    };
+                    for (parameter, argument) in parameters.iter().zip(arguments) {
+                        let t = self.expression_type(argument, function);
+                        self.set_type(Some(name), parameter, t)?;
+                    }
+                }
+            }
+            Expression::If(condition, consequence, alternative) => {
+                self.infer_expression(condition, function)?;
+                self.infer_statements(&consequence.statements, function, false)?;
+                if let Some(alt) = alternative {
+                    self.infer_statements(&alt.statements, function, false)?;
+                }
+            }
+            Expression::While(condition, consequence) => {
+                self.infer_expression(condition, function)?;
+                self.infer_statements(&consequence.statements, function, false)?;
+            }
+            Expression::Prefix(_, right) => self.infer_expression(right, function)?,
+            Expression::Infix(_, left, right) | Expression::Index(left, right) => {
+                self.infer_expression(left, function)?;
+                self.infer_expression(right, function)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // What is known so far about the type of `exp`.
+    fn expression_type(&self, exp: &Expression, function: Option<&str>) -> Option<Type> {
+        match exp {
+            Expression::IntegerLiteral(_) | Expression::Int => Some(Type::Int),
+            Expression::FloatLiteral(_) | Expression::Float => Some(Type::Float),
+            Expression::StringLiteral(_) | Expression::String => Some(Type::Str),
+            Expression::Boolean(_) | Expression::Prefix(Prefix::Bang, _) => Some(Type::Bool),
+            Expression::Identifier(name) => self.variable_type(function, name),
+            Expression::Prefix(Prefix::Minus, right) => self.expression_type(right, function),
+            Expression::Assign(_, right) => self.expression_type(right, function),
+            Expression::Infix(infix, left, right) => {
+                let left = self.expression_type(left, function);
+                let right = self.expression_type(right, function);
+                match (infix, left, right) {
+                    (Infix::Eq, _, _) | (Infix::NotEq, _, _) => Some(Type::Bool),
+                    (Infix::Lt, _, _) | (Infix::Gt, _, _) => Some(Type::Bool),
+                    (Infix::Assign, _, right) => right,
+                    (_, Some(Type::Int), Some(Type::Int)) => Some(Type::Int),
+                    (_, Some(Type::Int), Some(Type::Float))
+                    | (_, Some(Type::Float), Some(Type::Int))
+                    | (_, Some(Type::Float), Some(Type::Float)) => Some(Type::Float),
+                    (Infix::Plus, Some(Type::Str), Some(Type::Str)) => Some(Type::Str),
+                    _ => None,
+                }
+            }
+            Expression::If(_, consequence, _) => {
+                block_value(consequence).and_then(|value| self.expression_type(value, function))
+            }
+            Expression::Call(callee, arguments, _) => match callee.as_ref() {
+                Expression::Identifier(name) if name == "len" && arguments.len() == 1 => {
+                    Some(Type::Int)
+                }
+                Expression::Identifier(name) => self.function(name).and_then(|f| f.return_type),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // The runtime, the variables and the functions of the program, up to the start of `main`.
+    fn prologue(&self) -> Result<String> {
+        let mut functions = vec![];
+        for (name, function) in &self.functions {
+            let mut body = String::new();
+            for local in &function.locals.names {
+                if function.parameters.contains(local) {
+                    continue;
+                }
+                let t = function
+                    .locals
+                    .get(local)
+                    .ok_or_else(|| CError::UnknownType(local.to_string()))?;
+                line(
+                    &mut body,
+                    1,
+                    &format!("{} {} = {};", c_type(t), local, default_value(t)),
+                );
+            }
+            self.block(&mut body, &function.body, Some(name), 1, true)?;
+            functions.push((self.signature(name, function)?, body));
+        }
+
+        let mut out = RUNTIME.to_string();
+        if !self.globals.names.is_empty() {
+            out.push('\n');
+        }
+        for name in &self.globals.names {
+            let t = self
+                .globals
+                .get(name)
+                .ok_or_else(|| CError::UnknownType(name.to_string()))?;
+            line(&mut out, 0, &format!("{} {};", c_type(t), name));
+        }
+        if !functions.is_empty() {
+            out.push('\n');
+        }
+        // Prototypes, so that functions can call each other in any order.
+        for (signature, _) in &functions {
+            line(&mut out, 0, &format!("{};", signature));
+        }
+        for (signature, body) in &functions {
+            out.push('\n');
+            line(&mut out, 0, &format!("{} {{", signature));
+            out.push_str(body);
+            line(&mut out, 0, "}");
+        }
+        out.push('\n');
+        line(&mut out, 0, "int main(void) {");
+        Ok(out)
+    }
+
+    fn signature(&self, name: &str, function: &Function) -> Result<String> {
+        let return_type = function.return_type.map(c_type).unwrap_or("void");
+        let mut parameters = vec![];
+        for parameter in &function.parameters {
+            let t = function.locals.get(parameter).ok_or_else(|| {
+                CError::UnknownType(format!("parameter {} of {}", parameter, name))
+            })?;
+            parameters.push(format!("{} {}", c_type(t), parameter));
+        }
+        if parameters.is_empty() {
+            parameters.push("void".to_string());
+        }
+        Ok(format!(
+            "{} {}({})",
+            return_type,
+            name,
+            parameters.join(", ")
+        ))
+    }
+
+    fn block(
+        &self,
+        out: &mut String,
+        block: &BlockStatement,
+        function: Option<&str>,
+        indent_level: usize,
+        implicit_return: bool,
+    ) -> Result<()> {
+        for (i, stmt) in block.statements.iter().enumerate() {
+            let is_last = implicit_return && i == block.statements.len() - 1;
+            let mut code = String::new();
+            self.statement(&mut code, stmt, function, indent_level, is_last)?;
+            if let Some(line) = block.line(i).filter(|_| self.annotate) {
+                annotate(&mut code, "//", line);
+            }
+            out.push_str(&code);
+        }
+        Ok(())
+    }
+
+    fn statement(
+        &self,
+        out: &mut String,
+        stmt: &Statement,
+        function: Option<&str>,
+        indent_level: usize,
+        implicit_return: bool,
+    ) -> Result<()> {
+        match stmt {
+            // Emitted before `main`.
+            Statement::Let(name, Expression::FunctionLiteral(_, _))
+                if function.is_none() && indent_level == 1 && self.function(name).is_some() => {}
+            Statement::Let(name, value) => {
+                let code = format!("{} = {};", name, self.expression(value, function)?);
+                line(out, indent_level, &code)
+            }
+            // Declarations reset the variable, like in the evaluator.
+            Statement::Int(name) | Statement::Float(name) | Statement::String(name) => {
+                let t = self.variable_type(function, name);
+                let t = t.ok_or_else(|| CError::UnknownType(name.to_string()))?;
+                line(
+                    out,
+                    indent_level,
+                    &format!("{} = {};", name, default_value(t)),
+                )
+            }
+            Statement::Return(None) if function.is_none() => line(out, indent_level, "return 0;"),
+            Statement::Return(None) => line(out, indent_level, "return;"),
+            Statement::Return(Some(_)) if function.is_none() => {
+                line(out, indent_level, "return 0;")
+            }
+            Statement::Return(Some(value)) => {
+                let code = self.expression(value, function)?;
+                line(out, indent_level, &format!("return {};", code))
+            }
+            Statement::Print(None) => line(out, indent_level, "printf(\"\\n\");"),
+            Statement::Print(Some(value)) => {
+                let code = self.operand(value, function, Precedence::Assignment)?;
+                let code = match self.expression_type(value, function) {
+                    Some(Type::Int) => format!("printf(\"%lld\", {});", code),
+                    Some(Type::Float) => format!("pt_print_float({});", code),
+                    Some(Type::Str) => format!("pt_print_string({});", code),
+                    Some(Type::Bool) => format!(
+                        "printf(\"%s\", {} ? \"true\" : \"false\");",
+                        self.operand(value, function, Precedence::Equality)?
+                    ),
+                    None => return Err(CError::UnknownType(value.to_string())),
+                };
+                line(out, indent_level, &code)
+            }
+            Statement::Read(name) => {
+                let code = match self.variable_type(function, name) {
+                    Some(Type::Int) => format!("{} = pt_read_int();", name),
+                    Some(Type::Float) => format!("{} = pt_read_float();", name),
+                    Some(Type::Str) => format!("{} = pt_read_string();", name),
+                    Some(Type::Bool) => {
+                        return Err(CError::Unsupported(format!("reading a boolean ({})", name)))
+                    }
+                    None => return Err(CError::UnknownType(name.to_string())),
+                };
+                line(out, indent_level, &code)
+            }
+            Statement::Expression(Expression::If(condition, consequence, alternative)) => {
+                let condition = self.condition(condition, function)?;
+                line(out, indent_level, &format!("if ({}) {{", condition));
+                self.block(
+                    out,
+                    consequence,
+                    function,
+                    indent_level + 1,
+                    implicit_return,
+                )?;
+                if let Some(alt) = alternative {
+                    line(out, indent_level, "} else {");
+                    self.block(out, alt, function, indent_level + 1, implicit_return)?;
+                }
+                line(out, indent_level, "}")
+            }
+            Statement::Expression(Expression::While(condition, consequence)) => {
+                let condition = self.condition(condition, function)?;
+                line(out, indent_level, &format!("while ({}) {{", condition));
+                self.block(out, consequence, function, indent_level + 1, false)?;
+                line(out, indent_level, "}")
+            }
+            Statement::Expression(exp) => {
+                let returns_value = implicit_return
+                    && !matches!(exp, Expression::Assign(_, _))
+                    && function
+                        .and_then(|f| self.function(f))
+                        .is_some_and(|f| f.return_type.is_some());
+                let code = self.expression(exp, function)?;
+                if returns_value {
+                    line(out, indent_level, &format!("return {};", code))
+                } else {
+                    line(out, indent_level, &format!("{};", code))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&self, exp: &Expression, function: Option<&str>) -> Result<String> {
+        match self.expression_type(exp, function) {
+            Some(Type::Bool) => self.expression(exp, function),
+            _ => Err(CError::ExpectedBoolean(exp.clone())),
+        }
+    }
+
+    // Renders an expression as C source.
+    fn expression(&self, exp: &Expression, function: Option<&str>) -> Result<String> {
+        Ok(self.expression_with_precedence(exp, function)?.0)
+    }
+
+    // Renders `exp`, parenthesised if it binds looser than `precedence`.
+    fn operand(
+        &self,
+        exp: &Expression,
+        function: Option<&str>,
+        precedence: Precedence,
+    ) -> Result<String> {
+        let (code, p) = self.expression_with_precedence(exp, function)?;
+        if p < precedence {
+            Ok(format!("({})", code))
+        } else {
+            Ok(code)
+        }
+    }
+
+    fn expression_with_precedence(
+        &self,
+        exp: &Expression,
+        function: Option<&str>,
+    ) -> Result<(String, Precedence)> {
+        Ok(match exp {
+            Expression::Identifier(name) if self.function(name).is_some() => {
+                return Err(CError::Unsupported(format!(
+                    "functions as values ({})",
+                    name
+                )))
+            }
+            Expression::Identifier(name) => (name.to_string(), Precedence::Atom),
+            // Literals too big for an `int` need the suffix.
+            Expression::IntegerLiteral(value) if *value > i64::from(i32::MAX) => {
+                (format!("{}LL", value), Precedence::Atom)
+            }
+            Expression::IntegerLiteral(value) => (value.to_string(), Precedence::Atom),
+            Expression::FloatLiteral(value) => c_float(*value),
+            Expression::StringLiteral(s) => {
+                (format!("pt_str({})", c_string(s)), Precedence::Postfix)
+            }
+            Expression::Boolean(value) => (value.to_string(), Precedence::Atom),
+            Expression::Int => (default_value(Type::Int).to_string(), Precedence::Atom),
+            Expression::Float => (default_value(Type::Float).to_string(), Precedence::Atom),
+            Expression::String => (default_value(Type::Str).to_string(), Precedence::Postfix),
+            Expression::Prefix(Prefix::Bang, right) => {
+                if self.expression_type(right, function) != Some(Type::Bool) {
+                    return Err(CError::ExpectedBoolean(right.as_ref().clone()));
+                }
+                let right = self.operand(right, function, Precedence::Unary)?;
+                (format!("!{}", right), Precedence::Unary)
+            }
+            Expression::Prefix(Prefix::Minus, right)
+                if self.expression_type(right, function) == Some(Type::Int)
+                    && !matches!(right.as_ref(), Expression::IntegerLiteral(_)) =>
+            {
+                let right = self.operand(right, function, Precedence::Conditional)?;
+                (format!("pt_neg({})", right), Precedence::Postfix)
+            }
+            Expression::Prefix(Prefix::Minus, right) => {
+                let right = self.operand(right, function, Precedence::Unary)?;
+                // `--x` would be a decrement.
+                if right.starts_with('-') {
+                    (format!("-({})", right), Precedence::Unary)
+                } else {
+                    (format!("-{}", right), Precedence::Unary)
+                }
+            }
+            Expression::Assign(left, right) | Expression::Infix(Infix::Assign, left, right) => {
+                if !matches!(left.as_ref(), Expression::Identifier(_)) {
+                    return Err(CError::Unsupported(format!("assigning to {}", left)));
+                }
+                (
+                    format!(
+                        "{} = {}",
+                        self.expression(left, function)?,
+                        self.operand(right, function, Precedence::Assignment)?
+                    ),
+                    Precedence::Assignment,
+                )
+            }
+            Expression::Infix(infix, left, right) => {
+                self.infix_expression(exp, infix, left, right, function)?
+            }
+            Expression::If(condition, consequence, Some(alternative)) => {
+                match (block_value(consequence), block_value(alternative)) {
+                    (Some(cons), Some(alt)) => (
+                        format!(
+                            "{} ? {} : {}",
+                            self.operand(condition, function, Precedence::Equality)?,
+                            self.operand(cons, function, Precedence::Equality)?,
+                            self.operand(alt, function, Precedence::Conditional)?
+                        ),
+                        Precedence::Conditional,
+                    ),
+                    _ => return Err(CError::Unsupported(format!("`se` as a value ({})", exp))),
+                }
+            }
+            Expression::Call(callee, arguments, _) => {
+                let name = match callee.as_ref() {
+                    Expression::Identifier(name) => name,
+                    _ => return Err(CError::Unsupported(format!("calling {}", callee))),
+                };
+                if self.function(name).is_some() {
+                    let mut codes = vec![];
+                    for argument in arguments {
+                        codes.push(self.operand(argument, function, Precedence::Conditional)?);
+                    }
+                    (
+                        format!("{}({})", name, codes.join(", ")),
+                        Precedence::Postfix,
+                    )
+                } else if name == "len"
+                    && arguments.len() == 1
+                    && self.expression_type(&arguments[0], function) == Some(Type::Str)
+                {
+                    let s = self.operand(&arguments[0], function, Precedence::Postfix)?;
+                    (format!("(long long){}.length", s), Precedence::Unary)
+                } else {
+                    return Err(CError::Unsupported(format!("calling {}", name)));
+                }
+            }
+            Expression::If(_, _, None)
+            | Expression::While(_, _)
+            | Expression::Array(_)
+            | Expression::Hash(_)
+            | Expression::Index(_, _)
+            | Expression::FunctionLiteral(_, _) => {
+                return Err(CError::Unsupported(exp.to_string()))
+            }
+        })
+    }
+
+    fn infix_expression(
+        &self,
+        exp: &Expression,
+        infix: &Infix,
+        left: &Expression,
+        right: &Expression,
+        function: Option<&str>,
+    ) -> Result<(String, Precedence)> {
+        let left_type = self.expression_type(left, function);
+        let right_type = self.expression_type(right, function);
+        if left_type.is_none() || right_type.is_none() {
+            return Err(CError::UnknownType(exp.to_string()));
+        }
+        let strings = left_type == Some(Type::Str) && right_type == Some(Type::Str);
+        let numbers = matches!(left_type, Some(Type::Int) | Some(Type::Float))
+            && matches!(right_type, Some(Type::Int) | Some(Type::Float));
+        if strings && matches!(infix, Infix::Eq | Infix::NotEq | Infix::Plus) {
+            let call = match infix {
+                Infix::Plus => "pt_concat",
+                _ => "pt_equals",
+            };
+            let code = format!(
+                "{}({}, {})",
+                call,
+                self.operand(left, function, Precedence::Conditional)?,
+                self.operand(right, function, Precedence::Conditional)?
+            );
+            return Ok(match infix {
+                Infix::NotEq => (format!("!{}", code), Precedence::Unary),
+                _ => (code, Precedence::Postfix),
+            });
+        }
+        if left_type == Some(Type::Int) && right_type == Some(Type::Int) {
+            let call = match infix {
+                Infix::Plus => Some("pt_add"),
+                Infix::Minus => Some("pt_sub"),
+                Infix::Asterisk => Some("pt_mul"),
+                Infix::Slash => Some("pt_div"),
+                _ => None,
+            };
+            if let Some(call) = call {
+                let code = format!(
+                    "{}({}, {})",
+                    call,
+                    self.operand(left, function, Precedence::Conditional)?,
+                    self.operand(right, function, Precedence::Conditional)?
+                );
+                return Ok((code, Precedence::Postfix));
+            }
+        }
+        let (operator, precedence) = match infix {
+            Infix::Eq if left_type == right_type || numbers => ("==", Precedence::Equality),
+            Infix::NotEq if left_type == right_type || numbers => ("!=", Precedence::Equality),
+            Infix::Lt if numbers => ("<", Precedence::Relational),
+            Infix::Gt if numbers => (">", Precedence::Relational),
+            Infix::Plus if numbers => ("+", Precedence::Sum),
+            Infix::Minus if numbers => ("-", Precedence::Sum),
+            Infix::Asterisk if numbers => ("*", Precedence::Product),
+            // Only for floats, integer arithmetic goes through the checked helpers above.
+            Infix::Slash if numbers => ("/", Precedence::Product),
+            _ => {
+                return Err(CError::TypeMismatch(
+                    exp.to_string(),
+                    left_type.map(type_name).unwrap_or_default(),
+                    right_type.map(type_name).unwrap_or_default(),
+                ))
+            }
+        };
+        Ok((
+            format!(
+                "{} {} {}",
+                self.operand(left, function, precedence)?,
+                operator,
+                self.operand(right, function, next(precedence))?
+            ),
+            precedence,
+        ))
+    }
+}
+
+fn line(out: &mut String, indent_level: usize, code: &str) {
+    out.push_str(&indent(indent_level));
+    out.push_str(code);
+    out.push('\n');
+}
+
+// The precedence right above `precedence`, used for the right operand of left-associative
+// operators.
+fn next(precedence: Precedence) -> Precedence {
+    match precedence {
+        Precedence::Assignment => Precedence::Conditional,
+        Precedence::Conditional => Precedence::Equality,
+        Precedence::Equality => Precedence::Relational,
+        Precedence::Relational => Precedence::Sum,
+        Precedence::Sum => Precedence::Product,
+        Precedence::Product => Precedence::Unary,
+        Precedence::Unary => Precedence::Postfix,
+        Precedence::Postfix | Precedence::Atom => Precedence::Atom,
+    }
+}
+
+fn c_type(t: Type) -> &'static str {
+    match t {
+        Type::Int => "long long",
+        Type::Float => "double",
+        Type::Str => "pt_string",
+        Type::Bool => "bool",
+    }
+}
+
+// Portugol names of the types, for errors.
+fn type_name(t: Type) -> &'static str {
+    match t {
+        Type::Int => "int",
+        Type::Float => "real",
+        Type::Str => "string",
+        Type::Bool => "bool",
+    }
+}
+
+// The same default values the evaluator uses.
+fn default_value(t: Type) -> &'static str {
+    match t {
+        Type::Int => "0",
+        Type::Float => "0.0",
+        Type::Str => "pt_str(\"\")",
+        Type::Bool => "false",
+    }
+}
+
+fn c_float(value: f64) -> (String, Precedence) {
+    if value.is_nan() {
+        ("NAN".to_string(), Precedence::Atom)
+    } else if value.is_infinite() && value > 0.0 {
+        ("INFINITY".to_string(), Precedence::Atom)
+    } else if value.is_infinite() {
+        ("-INFINITY".to_string(), Precedence::Unary)
+    } else if value < 0.0 {
+        // `{:?}` always keeps a fractional part or an exponent, so it stays a `double`.
+        (format!("{:?}", value), Precedence::Unary)
+    } else {
+        (format!("{:?}", value), Precedence::Atom)
+    }
+}
+
+// Portugol strings have no escapes: a backslash is a backslash. Backslashes, quotes and raw
+// line breaks are escaped for C, and so is a `?` right after another, as `??` starts a trigraph
+// in C99.
+fn c_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    let mut previous = None;
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '?' if previous == Some('?') => escaped.push_str("\\?"),
+            _ => escaped.push(c),
+        }
+        previous = Some(c);
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transpiler::c::{CTranspiler, RUNTIME};
+    use crate::transpiler::Backend;
+
+    #[test]
+    fn statements() {
+        expect_main(vec![
+            // Integer arithmetic is checked.
+            (
+                "imprima 1 + 2 * 3;",
+                "printf(\"%lld\", pt_add(1, pt_mul(2, 3)));\n",
+            ),
+            ("imprima 7 / 2;", "printf(\"%lld\", pt_div(7, 2));\n"),
+            ("imprima (1.0 + 2) * 3;", "pt_print_float((1.0 + 2) * 3);\n"),
+            ("imprima 7 / 2.0;", "pt_print_float(7 / 2.0);\n"),
+            (
+                r#"imprima "ola" + "!"; imprima;"#,
+                "pt_print_string(pt_concat(pt_str(\"ola\"), pt_str(\"!\")));\nprintf(\"\\n\");\n",
+            ),
+            (
+                "imprima 1 < 2 == VERDADEIRO;",
+                "printf(\"%s\", 1 < 2 == true ? \"true\" : \"false\");\n",
+            ),
+            ("let x = 1; x <- x - -1;", "x = 1;\nx = pt_sub(x, -1);\n"),
+            ("let x = 1; x <- -x;", "x = 1;\nx = pt_neg(x);\n"),
+            ("let x = 1.0; x <- -x;", "x = 1.0;\nx = -x;\n"),
+            // Portugol has no escapes.
+            (
+                r#"imprima "a\b\n??=?";"#,
+                "pt_print_string(pt_str(\"a\\\\b\\\\n?\\?=?\"));\n",
+            ),
+            (
+                "int i; real r; string s; leia i; leia r; leia s;",
+                "i = 0;
+r = 0.0;
+s = pt_str(\"\");
+i = pt_read_int();
+r = pt_read_float();
+s = pt_read_string();
+",
+            ),
+            (
+                "int i; enquanto (i < 3) { se (i == 1) { imprima i; } senao { i <- i + 1; } }",
+                "i = 0;
+while (i < 3) {
+    if (i == 1) {
+        printf(\"%lld\", i);
+    } else {
+        i = pt_add(i, 1);
+    }
+}
+",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn program() {
+        let input = "
+            let double = 10;
+            let dobro = funcao(x) { x * 2 };
+            let maior = funcao(a, b) {
+                se (a > b) { retorne a; }
+                b
+            };
+            imprima maior(dobro(double), 15);
+        ";
+        let expected = "
+long long double_;
+
+long long dobro(long long x);
+long long maior(long long a, long long b);
+
+long long dobro(long long x) {
+    return pt_mul(x, 2);
+}
+
+long long maior(long long a, long long b) {
+    if (a > b) {
+        return a;
+    }
+    return b;
+}
+
+int main(void) {
+    double_ = 10;
+    printf(\"%lld\", maior(dobro(double_), 15));
+    return 0;
+}
+";
+        assert_eq!(transpile(input), Ok(format!("{}{}", RUNTIME, expected)));
+    }
+
+    #[test]
+    fn library_names() {
+        // Names the C library declares are renamed when the program binds them.
+        let code = transpile("int abs; int exit; leia abs; exit <- abs; imprima exit;").unwrap();
+        assert!(code.ends_with(
+            "long long abs_;
+long long exit_;
+
+int main(void) {
+    abs_ = 0;
+    exit_ = 0;
+    abs_ = pt_read_int();
+    exit_ = abs_;
+    printf(\"%lld\", exit_);
+    return 0;
+}
+"
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let tests = vec![
+            ("let a = [1, 2];", "not supported in C: [1, 2]"),
+            (
+                "let x = 1; x <- \"a\";",
+                "`x` is used both as int and as string",
+            ),
+            ("se (1) { imprima 2; }", "condition is not a boolean: 1"),
+            (
+                "let f = funcao(x) { x }; imprima 1;",
+                "can't infer the type of parameter x of f",
+            ),
+            ("leia x;", "can't infer the type of x"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                transpile(input),
+                Err(expected.to_string()),
+                "for `{}`",
+                input
+            );
+        }
+    }
+
+    fn expect_main(tests: Vec<(&str, &str)>) {
+        for (input, expected) in tests {
+            let code = transpile(input).unwrap_or_else(|err| panic!("{} for `{}`", err, input));
+            let main = code.split("int main(void) {\n").nth(1).unwrap();
+            let main = main
+                .strip_suffix("    return 0;\n}\n")
+                .unwrap()
+                .lines()
+                .map(|line| format!("{}\n", line.strip_prefix("    ").unwrap_or(line)))
+                .collect::<String>();
+            assert_eq!(main, expected, "for `{}`", input);
+        }
+    }
+
+    fn transpile(input: &str) -> Result<String, String> {
+        let lexer = Lexer::new(input.to_owned());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty(), "for `{}`", input);
+        CTranspiler::new(program)
+            .map(|transpiler| transpiler.transpile_to_string())
+            .map_err(|err| err.to_string())
+    }
+}
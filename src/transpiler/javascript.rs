@@ -0,0 +1,851 @@
+use super::backend::{self, Backend};
+use super::mangle::{NameTable, Reserved};
+use super::{block_value, collect_locals, collect_types, contains, infer_type, Type};
+use crate::ast::{BlockStatement, Expression, Infix, Prefix, Program, Statement};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+// ES2020 backend, for Node. Portugol blocks don't introduce a scope, so the variables of the
+// program and of each function are declared with `let` at their top.
+
+// Only included when the program uses `leia`.
+const READ_LINE: &str = r#"const fs = require("fs");
+
+// Reads a line from stdin, without the line break.
+function pt_read_line() {
+    const buffer = Buffer.alloc(1);
+    const bytes = [];
+    for (;;) {
+        let read;
+        try {
+            read = fs.readSync(0, buffer, 0, 1, null);
+        } catch (err) {
+            if (err.code === "EAGAIN") {
+                continue;
+            }
+            if (err.code === "EOF") {
+                break;
+            }
+            throw err;
+        }
+        if (read === 0 || buffer[0] === 10) {
+            break;
+        }
+        bytes.push(buffer[0]);
+    }
+    return Buffer.from(bytes).toString().replace(/\r$/, "");
+}
+"#;
+
+// Only included when the program divides integers. Stops on a zero divisor like the
+// interpreter, instead of going on with `Infinity`.
+const INT_DIVISION: &str = r#"function pt_div(a, b) {
+    if (b === 0) {
+        throw new Error("division by zero");
+    }
+    return Math.trunc(a / b);
+}
+"#;
+
+const JAVASCRIPT: Reserved = Reserved {
+    keywords: &[
+        "Infinity",
+        "NaN",
+        "arguments",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "debugger",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "enum",
+        "eval",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "function",
+        "if",
+        "implements",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "package",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "typeof",
+        "undefined",
+        "var",
+        "void",
+        "while",
+        "with",
+        "yield",
+    ],
+    // `null` is a keyword in JavaScript, but Portugol programs use it as a value.
+    builtins: &[
+        "Buffer",
+        "Math",
+        "String",
+        "fs",
+        "null",
+        "parseFloat",
+        "parseInt",
+        "process",
+        "pt_div",
+        "pt_read_line",
+        "require",
+    ],
+    ascii: false,
+};
+
+// JavaScript operator precedence, from the loosest to the tightest binding.
+// https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Operators/Operator_precedence
+#[derive(PartialEq, PartialOrd, Clone, Copy)]
+enum Precedence {
+    Assignment,  // x = y, (x) => y
+    Conditional, // c ? x : y
+    Equality,    // ===, !==
+    Relational,  // <, >
+    Sum,         // +, -
+    Product,     // *, /
+    Unary,       // -x, !x
+    Postfix,     // f(x), a[i]
+    Atom,        // literals and names
+}
+
+pub struct JavaScriptTranspiler {
+    program: Program,
+    // `None` when a name is used with more than one type.
+    types: HashMap<String, Option<Type>>,
+    names: NameTable,
+    // Top level functions defined with `function` declarations.
+    functions: HashSet<String>,
+    annotate: bool,
+}
+
+impl JavaScriptTranspiler {
+    pub fn new(program: Program) -> Self {
+        let names = NameTable::new(&program, &JAVASCRIPT);
+        let program = names.rename(&program);
+        let mut types = HashMap::new();
+        collect_types(&program.statements, &mut types);
+        let functions = function_declarations(&program.statements);
+        JavaScriptTranspiler {
+            program,
+            types,
+            names,
+            functions,
+            annotate: false,
+        }
+    }
+
+    // The body of a function: the declarations of its variables, then its statements.
+    fn body(
+        &self,
+        out: &mut dyn Write,
+        body: &BlockStatement,
+        parameters: &[String],
+        indent_level: usize,
+    ) -> io::Result<()> {
+        let functions = function_declarations(&body.statements);
+        self.declarations(out, &body.statements, parameters, &functions, indent_level)?;
+        self.block(out, body, &functions, indent_level, true)
+    }
+
+    // Declares the variables bound in a body, except for parameters and functions.
+    fn declarations(
+        &self,
+        out: &mut dyn Write,
+        statements: &[Statement],
+        parameters: &[String],
+        functions: &HashSet<String>,
+        indent_level: usize,
+    ) -> io::Result<()> {
+        let mut locals = HashSet::new();
+        collect_locals(statements, &mut locals);
+        let mut locals = locals
+            .into_iter()
+            .filter(|name| !parameters.contains(name) && !functions.contains(name))
+            .collect::<Vec<String>>();
+        locals.sort();
+        if !locals.is_empty() {
+            writeln!(
+                out,
+                "{}let {};",
+                self.indent(indent_level),
+                locals.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+
+    // With `implicit_return`, the value of the last expression is returned, as Portugol
+    // functions do. `functions` are the names defined with `function` declarations.
+    fn block(
+        &self,
+        out: &mut dyn Write,
+        block: &BlockStatement,
+        functions: &HashSet<String>,
+        indent_level: usize,
+        implicit_return: bool,
+    ) -> io::Result<()> {
+        for (i, stmt) in block.statements.iter().enumerate() {
+            let is_last = implicit_return && i == block.statements.len() - 1;
+            let line = block.line(i).filter(|_| self.annotate);
+            backend::annotated(out, self.comment(), line, |out| {
+                self.scoped_statement(out, stmt, functions, indent_level, is_last)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn scoped_statement(
+        &self,
+        out: &mut dyn Write,
+        stmt: &Statement,
+        functions: &HashSet<String>,
+        indent_level: usize,
+        implicit_return: bool,
+    ) -> io::Result<()> {
+        let spaces = self.indent(indent_level);
+        match stmt {
+            Statement::Let(name, Expression::FunctionLiteral(parameters, body))
+                if functions.contains(name) =>
+            {
+                writeln!(
+                    out,
+                    "{}function {}({}) {{",
+                    spaces,
+                    name,
+                    parameters.join(", ")
+                )?;
+                self.body(out, body, parameters, indent_level + 1)?;
+                writeln!(out, "{}}}", spaces)?
+            }
+            Statement::Let(name, value) => writeln!(
+                out,
+                "{}{} = {};",
+                spaces,
+                name,
+                self.expression_at(value, indent_level)
+            )?,
+            Statement::Return(None) => writeln!(out, "{}return;", spaces)?,
+            Statement::Return(Some(exp)) => writeln!(
+                out,
+                "{}return {};",
+                spaces,
+                self.expression_at(exp, indent_level)
+            )?,
+            Statement::Print(None) => writeln!(out, "{}process.stdout.write(\"\\n\");", spaces)?,
+            // `imprima` doesn't end the line by itself.
+            Statement::Print(Some(exp)) => writeln!(
+                out,
+                "{}process.stdout.write(String({}));",
+                spaces,
+                self.operand(exp, indent_level, Precedence::Assignment)
+            )?,
+            Statement::Read(name) => {
+                let input = match self.types.get(name).cloned().flatten() {
+                    Some(Type::Int) => "parseInt(pt_read_line(), 10)",
+                    Some(Type::Float) => "parseFloat(pt_read_line())",
+                    _ => "pt_read_line()",
+                };
+                writeln!(out, "{}{} = {};", spaces, name, input)?
+            }
+            Statement::Expression(Expression::If(condition, consequence, alternative)) => {
+                writeln!(
+                    out,
+                    "{}if ({}) {{",
+                    spaces,
+                    self.expression_at(condition, indent_level)
+                )?;
+                self.block(
+                    out,
+                    consequence,
+                    functions,
+                    indent_level + 1,
+                    implicit_return,
+                )?;
+                if let Some(alt) = alternative {
+                    writeln!(out, "{}}} else {{", spaces)?;
+                    self.block(out, alt, functions, indent_level + 1, implicit_return)?;
+                }
+                writeln!(out, "{}}}", spaces)?
+            }
+            Statement::Expression(Expression::While(condition, consequence)) => {
+                writeln!(
+                    out,
+                    "{}while ({}) {{",
+                    spaces,
+                    self.expression_at(condition, indent_level)
+                )?;
+                self.block(out, consequence, functions, indent_level + 1, false)?;
+                writeln!(out, "{}}}", spaces)?
+            }
+            Statement::Expression(exp @ Expression::Assign(_, _)) => {
+                writeln!(out, "{}{};", spaces, self.expression_at(exp, indent_level))?
+            }
+            Statement::Expression(exp) if implicit_return => writeln!(
+                out,
+                "{}return {};",
+                spaces,
+                self.expression_at(exp, indent_level)
+            )?,
+            Statement::Expression(exp) => {
+                writeln!(out, "{}{};", spaces, self.expression_at(exp, indent_level))?
+            }
+            // Declarations start with the same default values the evaluator uses.
+            Statement::Int(name) => writeln!(out, "{}{} = 0;", spaces, name)?,
+            Statement::Float(name) => writeln!(out, "{}{} = 0.0;", spaces, name)?,
+            Statement::String(name) => writeln!(out, "{}{} = \"\";", spaces, name)?,
+        }
+        Ok(())
+    }
+
+    // Functions and `se` blocks inside the expression are indented from `indent_level`.
+    fn expression_at(&self, exp: &Expression, indent_level: usize) -> String {
+        self.expression_with_precedence(exp, indent_level).0
+    }
+
+    // Renders `exp`, parenthesised if it binds looser than `precedence`.
+    fn operand(&self, exp: &Expression, indent_level: usize, precedence: Precedence) -> String {
+        let (code, p) = self.expression_with_precedence(exp, indent_level);
+        if p < precedence {
+            format!("({})", code)
+        } else {
+            code
+        }
+    }
+
+    fn expression_with_precedence(
+        &self,
+        exp: &Expression,
+        indent_level: usize,
+    ) -> (String, Precedence) {
+        match exp {
+            Expression::Identifier(name) => (name.to_string(), Precedence::Atom),
+            Expression::IntegerLiteral(value) if *value < 0 => {
+                (value.to_string(), Precedence::Unary)
+            }
+            Expression::IntegerLiteral(value) => (value.to_string(), Precedence::Atom),
+            Expression::FloatLiteral(value) => javascript_float(*value),
+            Expression::StringLiteral(s) => (javascript_string(s), Precedence::Atom),
+            Expression::Boolean(value) => (value.to_string(), Precedence::Atom),
+            // Default values of the types, the same the evaluator uses.
+            Expression::Int => ("0".to_string(), Precedence::Atom),
+            Expression::Float => ("0.0".to_string(), Precedence::Atom),
+            Expression::String => ("\"\"".to_string(), Precedence::Atom),
+            Expression::Array(values) => (
+                format!("[{}]", self.comma_separated(values, indent_level)),
+                Precedence::Atom,
+            ),
+            Expression::Hash(pairs) => {
+                let items = pairs
+                    .iter()
+                    .map(|(k, v)| {
+                        let key = match k {
+                            Expression::IntegerLiteral(_) | Expression::StringLiteral(_) => {
+                                self.expression_at(k, indent_level)
+                            }
+                            _ => format!("[{}]", self.expression_at(k, indent_level)),
+                        };
+                        let value = self.operand(v, indent_level, Precedence::Assignment);
+                        format!("{}: {}", key, value)
+                    })
+                    .collect::<Vec<String>>();
+                (format!("{{{}}}", items.join(", ")), Precedence::Atom)
+            }
+            Expression::Index(left, index) => (
+                format!(
+                    "{}[{}]",
+                    self.operand(left, indent_level, Precedence::Postfix),
+                    self.expression_at(index, indent_level)
+                ),
+                Precedence::Postfix,
+            ),
+            Expression::Call(function, arguments, _) => {
+                match (function.as_ref(), arguments.as_slice()) {
+                    // The builtin, unless the program defines its own `len`.
+                    (Expression::Identifier(name), [argument])
+                        if name == "len" && !self.types.contains_key(name) =>
+                    {
+                        (
+                            format!(
+                                "{}.length",
+                                self.operand(argument, indent_level, Precedence::Postfix)
+                            ),
+                            Precedence::Postfix,
+                        )
+                    }
+                    _ => (
+                        format!(
+                            "{}({})",
+                            self.operand(function, indent_level, Precedence::Postfix),
+                            self.comma_separated(arguments, indent_level)
+                        ),
+                        Precedence::Postfix,
+                    ),
+                }
+            }
+            Expression::Prefix(prefix, right) => {
+                let operator = match prefix {
+                    Prefix::Bang => "!",
+                    Prefix::Minus => "-",
+                };
+                let right = self.operand(right, indent_level, Precedence::Unary);
+                // `--x` would be a decrement.
+                if right.starts_with('-') {
+                    (format!("{}({})", operator, right), Precedence::Unary)
+                } else {
+                    (format!("{}{}", operator, right), Precedence::Unary)
+                }
+            }
+            Expression::Infix(Infix::Assign, left, right) | Expression::Assign(left, right) => (
+                format!(
+                    "{} = {}",
+                    self.expression_at(left, indent_level),
+                    self.operand(right, indent_level, Precedence::Assignment)
+                ),
+                Precedence::Assignment,
+            ),
+            Expression::Infix(infix, left, right) => {
+                self.infix_expression(infix, left, right, indent_level)
+            }
+            Expression::If(condition, consequence, alternative) => {
+                let alternative = match alternative {
+                    Some(alt) => block_value(alt)
+                        .map(|v| self.operand(v, indent_level, Precedence::Conditional)),
+                    None => Some("null".to_string()),
+                };
+                match (block_value(consequence), alternative) {
+                    (Some(cons), Some(alt)) => (
+                        format!(
+                            "{} ? {} : {}",
+                            self.operand(condition, indent_level, Precedence::Equality),
+                            self.operand(cons, indent_level, Precedence::Conditional),
+                            alt
+                        ),
+                        Precedence::Conditional,
+                    ),
+                    // Branches with statements run in a function called right away.
+                    _ => {
+                        let block = BlockStatement {
+                            statements: vec![Statement::Expression(exp.clone())],
+                            lines: vec![],
+                        };
+                        (
+                            format!("({})()", self.arrow_function(&[], &block, indent_level)),
+                            Precedence::Postfix,
+                        )
+                    }
+                }
+            }
+            // `enquanto` has no value.
+            Expression::While(_, _) => {
+                let block = BlockStatement {
+                    statements: vec![Statement::Expression(exp.clone()), Statement::Return(None)],
+                    lines: vec![],
+                };
+                (
+                    format!("({})()", self.arrow_function(&[], &block, indent_level)),
+                    Precedence::Postfix,
+                )
+            }
+            Expression::FunctionLiteral(parameters, body) => (
+                self.arrow_function(parameters, body, indent_level),
+                Precedence::Assignment,
+            ),
+        }
+    }
+
+    fn arrow_function(
+        &self,
+        parameters: &[String],
+        body: &BlockStatement,
+        indent_level: usize,
+    ) -> String {
+        match block_value(body) {
+            Some(value) => {
+                let value = self.operand(value, indent_level, Precedence::Assignment);
+                // A body starting with `{` would be read as a block.
+                if value.starts_with('{') {
+                    format!("({}) => ({})", parameters.join(", "), value)
+                } else {
+                    format!("({}) => {}", parameters.join(", "), value)
+                }
+            }
+            None => {
+                let mut out = vec![];
+                // Writing to a `Vec` never fails.
+                self.body(&mut out, body, parameters, indent_level + 1)
+                    .unwrap();
+                format!(
+                    "({}) => {{\n{}{}}}",
+                    parameters.join(", "),
+                    String::from_utf8(out).unwrap(),
+                    self.indent(indent_level)
+                )
+            }
+        }
+    }
+
+    fn infix_expression(
+        &self,
+        infix: &Infix,
+        left: &Expression,
+        right: &Expression,
+        indent_level: usize,
+    ) -> (String, Precedence) {
+        let (operator, precedence) = match infix {
+            Infix::Eq => ("===", Precedence::Equality),
+            Infix::NotEq => ("!==", Precedence::Equality),
+            Infix::Lt => ("<", Precedence::Relational),
+            Infix::Gt => (">", Precedence::Relational),
+            Infix::Plus => ("+", Precedence::Sum),
+            Infix::Minus => ("-", Precedence::Sum),
+            Infix::Asterisk => ("*", Precedence::Product),
+            Infix::Slash => ("/", Precedence::Product),
+            Infix::Assign => unreachable!("assignments are rendered with `=`"),
+        };
+        // JavaScript only has floats, integer division must be truncated.
+        if *infix == Infix::Slash && self.is_int_division(left, right) {
+            return (
+                format!(
+                    "pt_div({}, {})",
+                    self.operand(left, indent_level, Precedence::Assignment),
+                    self.operand(right, indent_level, Precedence::Assignment)
+                ),
+                Precedence::Postfix,
+            );
+        }
+        (
+            format!(
+                "{} {} {}",
+                self.operand(left, indent_level, precedence),
+                operator,
+                self.operand(right, indent_level, next(precedence))
+            ),
+            precedence,
+        )
+    }
+
+    fn is_int_division(&self, left: &Expression, right: &Expression) -> bool {
+        infer_type(left, &self.types) == Some(Type::Int)
+            && infer_type(right, &self.types) == Some(Type::Int)
+    }
+
+    fn uses_int_division(&self, statements: &[Statement]) -> bool {
+        contains(statements, &|_| false, &|exp| match exp {
+            Expression::Infix(Infix::Slash, left, right) => self.is_int_division(left, right),
+            _ => false,
+        })
+    }
+
+    fn comma_separated(&self, exps: &[Expression], indent_level: usize) -> String {
+        exps.iter()
+            .map(|exp| self.operand(exp, indent_level, Precedence::Assignment))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+impl Backend for JavaScriptTranspiler {
+    fn program(&self) -> &Program {
+        &self.program
+    }
+
+    fn names(&self) -> &NameTable {
+        &self.names
+    }
+
+    fn comment(&self) -> &'static str {
+        "//"
+    }
+
+    fn set_annotate(&mut self, annotate: bool) {
+        self.annotate = annotate;
+    }
+
+    fn prologue(&self, out: &mut dyn Write) -> io::Result<()> {
+        if uses_read(&self.program.statements) {
+            writeln!(out, "{}", READ_LINE)?;
+        }
+        if self.uses_int_division(&self.program.statements) {
+            writeln!(out, "{}", INT_DIVISION)?;
+        }
+        self.declarations(out, &self.program.statements, &[], &self.functions, 0)
+    }
+
+    fn statement(
+        &self,
+        out: &mut dyn Write,
+        stmt: &Statement,
+        line: Option<usize>,
+        indent_level: usize,
+    ) -> io::Result<()> {
+        let line = line.filter(|_| self.annotate);
+        backend::annotated(out, self.comment(), line, |out| {
+            self.scoped_statement(out, stmt, &self.functions, indent_level, false)
+        })
+    }
+
+    // Renders an expression as JavaScript source.
+    fn expression(&self, exp: &Expression) -> String {
+        self.expression_at(exp, 0)
+    }
+}
+
+// Functions that can be `function` declarations: the ones bound by a `let` directly in the
+// body and never bound again.
+fn function_declarations(statements: &[Statement]) -> HashSet<String> {
+    let mut bindings = HashMap::new();
+    count_bindings(statements, &mut bindings);
+    statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::Let(name, Expression::FunctionLiteral(_, _)) if bindings[name] == 1 => {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// How many times each name is bound in a body, without looking into nested functions.
+fn count_bindings(statements: &[Statement], bindings: &mut HashMap<String, usize>) {
+    for stmt in statements {
+        match stmt {
+            Statement::Let(name, _)
+            | Statement::Int(name)
+            | Statement::Float(name)
+            | Statement::String(name)
+            | Statement::Read(name) => *bindings.entry(name.clone()).or_default() += 1,
+            Statement::Expression(Expression::Assign(left, _)) => {
+                if let Expression::Identifier(name) = left.as_ref() {
+                    *bindings.entry(name.clone()).or_default() += 1;
+                }
+            }
+            Statement::Expression(Expression::If(_, consequence, alternative)) => {
+                count_bindings(&consequence.statements, bindings);
+                if let Some(alt) = alternative {
+                    count_bindings(&alt.statements, bindings);
+                }
+            }
+            Statement::Expression(Expression::While(_, consequence)) => {
+                count_bindings(&consequence.statements, bindings);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn uses_read(statements: &[Statement]) -> bool {
+    contains(
+        statements,
+        &|stmt| matches!(stmt, Statement::Read(_)),
+        &|_| false,
+    )
+}
+
+// The precedence right above `precedence`, used for the right operand of left-associative
+// operators.
+fn next(precedence: Precedence) -> Precedence {
+    match precedence {
+        Precedence::Assignment => Precedence::Conditional,
+        Precedence::Conditional => Precedence::Equality,
+        Precedence::Equality => Precedence::Relational,
+        Precedence::Relational => Precedence::Sum,
+        Precedence::Sum => Precedence::Product,
+        Precedence::Product => Precedence::Unary,
+        Precedence::Unary => Precedence::Postfix,
+        Precedence::Postfix | Precedence::Atom => Precedence::Atom,
+    }
+}
+
+fn javascript_float(value: f64) -> (String, Precedence) {
+    if value.is_nan() {
+        ("NaN".to_string(), Precedence::Atom)
+    } else if value.is_infinite() && value > 0.0 {
+        ("Infinity".to_string(), Precedence::Atom)
+    } else if value.is_infinite() {
+        ("-Infinity".to_string(), Precedence::Unary)
+    } else if value < 0.0 {
+        (format!("{:?}", value), Precedence::Unary)
+    } else {
+        (format!("{:?}", value), Precedence::Atom)
+    }
+}
+
+// Portugol strings have no escapes: a backslash is a backslash. Backslashes, quotes and raw
+// line breaks are escaped for JavaScript.
+fn javascript_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transpiler::javascript::{JavaScriptTranspiler, INT_DIVISION, READ_LINE};
+    use crate::transpiler::Backend;
+
+    #[test]
+    fn expressions() {
+        expect_transpiled(vec![
+            ("1 + 2 * 3;", "1 + 2 * 3;\n"),
+            ("(1 + 2) * 3;", "(1 + 2) * 3;\n"),
+            ("1 == 2;", "1 === 2;\n"),
+            ("!(1 != 2);", "!(1 !== 2);\n"),
+            ("- -1;", "-(-1);\n"),
+            ("7 / 2.0;", "7 / 2.0;\n"),
+            ("a / b;", "a / b;\n"),
+            (r#"len("abc");"#, "\"abc\".length;\n"),
+            // Portugol has no escapes, and strings can span lines.
+            (r#""a\nb";"#, "\"a\\\\nb\";\n"),
+            ("\"a\nb\\\";", "\"a\\nb\\\\\";\n"),
+            (
+                r#"let h = {1: "a", "b": 2, x: 3};"#,
+                "let h;\nh = {1: \"a\", \"b\": 2, [x]: 3};\n",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn statements() {
+        expect_transpiled(vec![
+            (
+                "int i; enquanto (i < 3) { se (i == 1) { imprima i; } senao { i <- i + 1; } } imprima;",
+                "let i;
+i = 0;
+while (i < 3) {
+    if (i === 1) {
+        process.stdout.write(String(i));
+    } else {
+        i = i + 1;
+    }
+}
+process.stdout.write(\"\\n\");
+",
+            ),
+            (
+                "se (VERDADEIRO) { let y = 1; } imprima y;",
+                "let y;
+if (true) {
+    y = 1;
+}
+process.stdout.write(String(y));
+",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn functions() {
+        expect_transpiled(vec![
+            (
+                "let soma = funcao(a, b) { let c = a + b; c }; soma(1, 2);",
+                "function soma(a, b) {
+    let c;
+    c = a + b;
+    return c;
+}
+soma(1, 2);
+",
+            ),
+            // Bound twice, so not a declaration.
+            (
+                "let f = funcao(x) { x * 2 }; f <- funcao(x) { x };",
+                "let f;
+f = (x) => x * 2;
+f = (x) => x;
+",
+            ),
+            (
+                "let somador = funcao(x) { funcao(y) { x + y } }; imprima somador(1)(2);",
+                "function somador(x) {
+    return (y) => x + y;
+}
+process.stdout.write(String(somador(1)(2)));
+",
+            ),
+            (
+                "map(a, funcao(x) { imprima x; x });",
+                "map(a, (x) => {
+    process.stdout.write(String(x));
+    return x;
+});
+",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn int_division() {
+        let input = "int a; imprima 7 / a + 1;";
+        let expected = "let a;
+a = 0;
+process.stdout.write(String(pt_div(7, a) + 1));
+";
+        assert_eq!(
+            transpile(input),
+            format!("{}\n{}", INT_DIVISION, expected),
+            "for `{}`",
+            input
+        );
+    }
+
+    #[test]
+    fn read() {
+        let input = "int i; string s; leia i; leia s;";
+        let expected = "let i, s;
+i = 0;
+s = \"\";
+i = parseInt(pt_read_line(), 10);
+s = pt_read_line();
+";
+        assert_eq!(
+            transpile(input),
+            format!("{}\n{}", READ_LINE, expected),
+            "for `{}`",
+            input
+        );
+    }
+
+    fn expect_transpiled(tests: Vec<(&str, &str)>) {
+        for (input, expected) in tests {
+            assert_eq!(transpile(input), expected, "for `{}`", input);
+        }
+    }
+
+    fn transpile(input: &str) -> String {
+        let lexer = Lexer::new(input.to_owned());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty(), "for `{}`", input);
+        JavaScriptTranspiler::new(program).transpile_to_string()
+    }
+}
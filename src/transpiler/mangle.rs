@@ -0,0 +1,429 @@
+use crate::ast::{BlockStatement, Expression, Program, Statement};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+// The names a target language doesn't let a program use as-is.
+pub struct Reserved {
+    // Can never be used as names.
+    pub keywords: &'static [&'static str],
+    // Can be used as names, but a program binding one of them would shadow it, breaking the
+    // generated code or the program's own uses of it.
+    pub builtins: &'static [&'static str],
+    // Whether names must be plain ASCII.
+    pub ascii: bool,
+}
+
+pub const PYTHON: Reserved = Reserved {
+    keywords: PYTHON_KEYWORDS,
+    builtins: PYTHON_BUILTINS,
+    ascii: false,
+};
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+// `print`, `input`, `int`, ... are used by the generated code.
+const PYTHON_BUILTINS: &[&str] = &[
+    "abs",
+    "all",
+    "any",
+    "bool",
+    "bytes",
+    "callable",
+    "chr",
+    "dict",
+    "dir",
+    "divmod",
+    "enumerate",
+    "exit",
+    "filter",
+    "float",
+    "format",
+    "frozenset",
+    "getattr",
+    "globals",
+    "hasattr",
+    "hash",
+    "help",
+    "hex",
+    "id",
+    "input",
+    "int",
+    "isinstance",
+    "issubclass",
+    "iter",
+    "len",
+    "list",
+    "locals",
+    "map",
+    "max",
+    "min",
+    "next",
+    "object",
+    "oct",
+    "open",
+    "ord",
+    "pow",
+    "print",
+    "pt_repr",
+    "pt_str",
+    "quit",
+    "range",
+    "repr",
+    "reversed",
+    "round",
+    "set",
+    "setattr",
+    "slice",
+    "sorted",
+    "str",
+    "sum",
+    "super",
+    "tuple",
+    "type",
+    "vars",
+    "zip",
+];
+
+// Maps Portugol names to the names used for them in the generated code, for the whole program.
+//
+// A name is renamed when it is a keyword, contains characters the target language doesn't
+// accept in names (the lexer accepts `$` and emoji), or is bound by the program and shadows a
+// builtin. Renamed names never collide with another name of the program.
+#[derive(Debug, Default)]
+pub struct NameTable {
+    names: BTreeMap<String, String>,
+}
+
+impl NameTable {
+    pub fn new(program: &Program, reserved: &Reserved) -> Self {
+        let mut used = HashSet::new();
+        let mut bound = HashSet::new();
+        collect_names(&program.statements, &mut used, &mut bound);
+
+        let mut candidates = used
+            .iter()
+            .filter(|name| {
+                reserved.keywords.contains(&name.as_str())
+                    || !is_identifier(name, reserved.ascii)
+                    || (bound.contains(*name) && reserved.builtins.contains(&name.as_str()))
+            })
+            .cloned()
+            .collect::<Vec<String>>();
+        // Sort so that the same program always gets the same names.
+        candidates.sort();
+
+        let mut names = BTreeMap::new();
+        let mut taken = HashSet::new();
+        for name in candidates {
+            let mut mangled = escape(&name, reserved.ascii);
+            if reserved.keywords.contains(&mangled.as_str())
+                || reserved.builtins.contains(&mangled.as_str())
+            {
+                mangled.push('_');
+            }
+            while used.contains(&mangled) || taken.contains(&mangled) {
+                mangled.push('_');
+            }
+            taken.insert(mangled.clone());
+            names.insert(name, mangled);
+        }
+        NameTable { names }
+    }
+
+    pub fn get<'a>(&'a self, name: &'a str) -> &'a str {
+        self.names.get(name).map(|n| n.as_str()).unwrap_or(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn rename(&self, program: &Program) -> Program {
+        Program {
+            statements: self.rename_statements(&program.statements),
+            lines: program.lines.clone(),
+        }
+    }
+
+    fn rename_statements(&self, statements: &[Statement]) -> Vec<Statement> {
+        statements
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Let(name, exp) => {
+                    Statement::Let(self.get(name).to_string(), self.rename_expression(exp))
+                }
+                Statement::Int(name) => Statement::Int(self.get(name).to_string()),
+                Statement::Float(name) => Statement::Float(self.get(name).to_string()),
+                Statement::String(name) => Statement::String(self.get(name).to_string()),
+                Statement::Read(name) => Statement::Read(self.get(name).to_string()),
+                Statement::Return(exp) => {
+                    Statement::Return(exp.as_ref().map(|e| self.rename_expression(e)))
+                }
+                Statement::Print(exp) => {
+                    Statement::Print(exp.as_ref().map(|e| self.rename_expression(e)))
+                }
+                Statement::Expression(exp) => Statement::Expression(self.rename_expression(exp)),
+            })
+            .collect()
+    }
+
+    fn rename_block(&self, block: &BlockStatement) -> BlockStatement {
+        BlockStatement {
+            statements: self.rename_statements(&block.statements),
+            lines: block.lines.clone(),
+        }
+    }
+
+    fn rename_expression(&self, exp: &Expression) -> Expression {
+        let rename = |e: &Expression| Box::new(self.rename_expression(e));
+        match exp {
+            Expression::Identifier(name) => Expression::Identifier(self.get(name).to_string()),
+            Expression::Array(values) => {
+                Expression::Array(values.iter().map(|v| self.rename_expression(v)).collect())
+            }
+            Expression::Hash(pairs) => Expression::Hash(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (self.rename_expression(k), self.rename_expression(v)))
+                    .collect(),
+            ),
+            Expression::Index(left, index) => Expression::Index(rename(left), rename(index)),
+            Expression::Prefix(prefix, right) => Expression::Prefix(prefix.clone(), rename(right)),
+            Expression::Infix(infix, left, right) => {
+                Expression::Infix(infix.clone(), rename(left), rename(right))
+            }
+            Expression::If(condition, consequence, alternative) => Expression::If(
+                rename(condition),
+                self.rename_block(consequence),
+                alternative.as_ref().map(|alt| self.rename_block(alt)),
+            ),
+            Expression::While(condition, consequence) => {
+                Expression::While(rename(condition), self.rename_block(consequence))
+            }
+            Expression::Assign(left, right) => Expression::Assign(rename(left), rename(right)),
+            Expression::FunctionLiteral(parameters, body) => Expression::FunctionLiteral(
+                parameters.iter().map(|p| self.get(p).to_string()).collect(),
+                self.rename_block(body),
+            ),
+            Expression::Call(function, arguments, position) => Expression::Call(
+                rename(function),
+                arguments
+                    .iter()
+                    .map(|a| self.rename_expression(a))
+                    .collect(),
+                *position,
+            ),
+            Expression::IntegerLiteral(_)
+            | Expression::FloatLiteral(_)
+            | Expression::StringLiteral(_)
+            | Expression::Boolean(_)
+            | Expression::Int
+            | Expression::Float
+            | Expression::String => exp.clone(),
+        }
+    }
+}
+
+// One `portugol -> python` pair per line, for debugging.
+impl fmt::Display for NameTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, mangled) in &self.names {
+            writeln!(f, "{} -> {}", name, mangled)?;
+        }
+        Ok(())
+    }
+}
+
+// Generates names for temporaries that don't collide with the names of a program.
+pub struct FreshNames {
+    used: HashSet<String>,
+    counter: usize,
+}
+
+impl FreshNames {
+    pub fn new(program: &Program) -> Self {
+        let mut used = HashSet::new();
+        collect_names(&program.statements, &mut used, &mut HashSet::new());
+        FreshNames { used, counter: 0 }
+    }
+
+    // `prefix_1`, `prefix_2`, ...
+    pub fn fresh(&mut self, prefix: &str) -> String {
+        loop {
+            self.counter += 1;
+            let name = format!("{}_{}", prefix, self.counter);
+            if self.used.insert(name.clone()) {
+                return name;
+            }
+        }
+    }
+}
+
+fn is_identifier(name: &str, ascii: bool) -> bool {
+    name.chars().all(|ch| is_identifier_char(ch, ascii))
+}
+
+// A conservative subset of what the target languages accept. The lexer already makes sure
+// names don't start with a digit.
+fn is_identifier_char(ch: char, ascii: bool) -> bool {
+    ch == '_' || ch.is_ascii_alphanumeric() || (!ascii && ch.is_alphabetic())
+}
+
+// `$x` -> `_u24x`, `😀` -> `_u1F600`
+fn escape(name: &str, ascii: bool) -> String {
+    let mut escaped = String::new();
+    for ch in name.chars() {
+        if is_identifier_char(ch, ascii) {
+            escaped.push(ch);
+        } else {
+            escaped.push_str(&format!("_u{:X}", ch as u32));
+        }
+    }
+    escaped
+}
+
+// `used` gets every name in the program, `bound` only the ones the program defines.
+fn collect_names(
+    statements: &[Statement],
+    used: &mut HashSet<String>,
+    bound: &mut HashSet<String>,
+) {
+    for stmt in statements {
+        match stmt {
+            Statement::Let(name, exp) => {
+                used.insert(name.clone());
+                bound.insert(name.clone());
+                collect_expression_names(exp, used, bound);
+            }
+            Statement::Int(name)
+            | Statement::Float(name)
+            | Statement::String(name)
+            | Statement::Read(name) => {
+                used.insert(name.clone());
+                bound.insert(name.clone());
+            }
+            Statement::Return(Some(exp))
+            | Statement::Print(Some(exp))
+            | Statement::Expression(exp) => collect_expression_names(exp, used, bound),
+            Statement::Return(None) | Statement::Print(None) => {}
+        }
+    }
+}
+
+fn collect_expression_names(
+    exp: &Expression,
+    used: &mut HashSet<String>,
+    bound: &mut HashSet<String>,
+) {
+    match exp {
+        Expression::Identifier(name) => {
+            used.insert(name.clone());
+        }
+        Expression::Array(values) => {
+            for value in values {
+                collect_expression_names(value, used, bound);
+            }
+        }
+        Expression::Hash(pairs) => {
+            for (k, v) in pairs {
+                collect_expression_names(k, used, bound);
+                collect_expression_names(v, used, bound);
+            }
+        }
+        Expression::Index(left, right) | Expression::Infix(_, left, right) => {
+            collect_expression_names(left, used, bound);
+            collect_expression_names(right, used, bound);
+        }
+        Expression::Assign(left, right) => {
+            if let Expression::Identifier(name) = left.as_ref() {
+                bound.insert(name.clone());
+            }
+            collect_expression_names(left, used, bound);
+            collect_expression_names(right, used, bound);
+        }
+        Expression::Prefix(_, right) => collect_expression_names(right, used, bound),
+        Expression::If(condition, consequence, alternative) => {
+            collect_expression_names(condition, used, bound);
+            collect_names(&consequence.statements, used, bound);
+            if let Some(alt) = alternative {
+                collect_names(&alt.statements, used, bound);
+            }
+        }
+        Expression::While(condition, consequence) => {
+            collect_expression_names(condition, used, bound);
+            collect_names(&consequence.statements, used, bound);
+        }
+        Expression::FunctionLiteral(parameters, body) => {
+            for parameter in parameters {
+                used.insert(parameter.clone());
+                bound.insert(parameter.clone());
+            }
+            collect_names(&body.statements, used, bound);
+        }
+        Expression::Call(function, arguments, _) => {
+            collect_expression_names(function, used, bound);
+            for argument in arguments {
+                collect_expression_names(argument, used, bound);
+            }
+        }
+        Expression::IntegerLiteral(_)
+        | Expression::FloatLiteral(_)
+        | Expression::StringLiteral(_)
+        | Expression::Boolean(_)
+        | Expression::Int
+        | Expression::Float
+        | Expression::String => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transpiler::mangle::{NameTable, PYTHON};
+
+    #[test]
+    fn mangled_names() {
+        let input = "
+            let list = [1];
+            let def = 2;
+            let print_ = 3;
+            let $total = 4;
+            let 😀 = 5;
+            imprima len(list);
+        ";
+        let lexer = Lexer::new(input.to_owned());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(parser.errors().is_empty());
+
+        let names = NameTable::new(&program, &PYTHON);
+        assert_eq!(
+            names.to_string(),
+            "$total -> _u24total
+def -> def_
+list -> list_
+\u{1F600} -> _u1F600
+"
+        );
+        // `len` is only called, not bound, so it's still Python's builtin.
+        assert_eq!(names.get("len"), "len");
+        assert_eq!(names.get("print_"), "print_");
+    }
+
+    #[test]
+    fn collisions() {
+        let input = "let print = 1; let print_ = 2; let print__ = 3;";
+        let lexer = Lexer::new(input.to_owned());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+
+        let names = NameTable::new(&program, &PYTHON);
+        assert_eq!(names.get("print"), "print___");
+    }
+}
@@ -0,0 +1,1349 @@
+use crate::ast::{BlockStatement, Expression, Infix, Prefix, Program, Statement};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
+
+mod backend;
+pub mod c;
+pub mod javascript;
+mod mangle;
+mod source_map;
+
+pub use backend::{Backend, BackendError};
+use c::CTranspiler;
+use javascript::JavaScriptTranspiler;
+use mangle::FreshNames;
+pub use mangle::NameTable;
+pub(crate) use source_map::json_string;
+pub use source_map::{SourceMap, SourceMapError};
+
+// Only included when the program prints values Python shows differently from the interpreter,
+// like booleans and floats.
+const PRINT_VALUE: &str = r#"def pt_str(value):
+    if isinstance(value, str):
+        return value
+    return pt_repr(value)
+
+def pt_repr(value):
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if isinstance(value, float):
+        if value != value:
+            return "NaN"
+        return "%.0f" % value if value.is_integer() else repr(value)
+    if isinstance(value, str):
+        return '"' + value + '"'
+    if isinstance(value, list):
+        return "[" + ", ".join(pt_repr(v) for v in value) + "]"
+    if isinstance(value, dict):
+        return "{" + ", ".join(sorted(pt_repr(k) + ": " + pt_repr(v) for k, v in value.items())) + "}"
+    if value is None:
+        return "null"
+    return str(value)
+"#;
+
+// Only included when the program divides integers. `//` rounds toward negative infinity, the
+// interpreter truncates toward zero.
+const INT_DIVISION: &str = r#"def pt_div(a, b):
+    quotient = abs(a) // abs(b)
+    return quotient if (a < 0) == (b < 0) else -quotient
+"#;
+
+// The backends `--target` can select, by name. The first one is the default.
+pub const TARGETS: &[&str] = &["python", "c", "javascript"];
+
+pub fn backend(target: &str, program: Program) -> Result<Box<dyn Backend>, BackendError> {
+    match target {
+        "python" => {
+            reject_returning_values(&program)?;
+            Ok(Box::new(Transpiler::new(program)))
+        }
+        "c" => {
+            reject_returning_values(&program)?;
+            CTranspiler::new(program)
+                .map(|backend| Box::new(backend) as Box<dyn Backend>)
+                .map_err(|err| BackendError::Rejected(err.to_string()))
+        }
+        "javascript" => {
+            reject_returning_values(&program)?;
+            Ok(Box::new(JavaScriptTranspiler::new(program)))
+        }
+        _ => Err(BackendError::UnknownTarget(target.to_string())),
+    }
+}
+
+// In the interpreter, a `retorne` in a `se` or `enquanto` used as a value returns from the
+// function once the statement holding the value ends, and only then. No target has anything
+// like it, so these programs are rejected rather than translated to something else.
+fn reject_returning_values(program: &Program) -> Result<(), BackendError> {
+    match returning_value(&program.statements) {
+        Some(exp) => Err(BackendError::Rejected(format!(
+            "`retorne` in a `se` or `enquanto` used as a value ({})",
+            exp
+        ))),
+        None => Ok(()),
+    }
+}
+
+// The first `se` or `enquanto` used as a value with a `retorne` in its blocks.
+fn returning_value(statements: &[Statement]) -> Option<&Expression> {
+    statements.iter().find_map(|stmt| match stmt {
+        // Used as statements, `se` and `enquanto` can return.
+        Statement::Expression(Expression::If(condition, consequence, alternative)) => {
+            returning_value_in(condition)
+                .or_else(|| returning_value(&consequence.statements))
+                .or_else(|| {
+                    alternative
+                        .as_ref()
+                        .and_then(|alt| returning_value(&alt.statements))
+                })
+        }
+        Statement::Expression(Expression::While(condition, consequence)) => {
+            returning_value_in(condition).or_else(|| returning_value(&consequence.statements))
+        }
+        Statement::Let(_, exp)
+        | Statement::Return(Some(exp))
+        | Statement::Print(Some(exp))
+        | Statement::Expression(exp) => returning_value_in(exp),
+        _ => None,
+    })
+}
+
+fn returning_value_in(exp: &Expression) -> Option<&Expression> {
+    match exp {
+        Expression::If(condition, consequence, alternative) => {
+            if returns(consequence) || alternative.as_ref().is_some_and(returns) {
+                return Some(exp);
+            }
+            returning_value_in(condition)
+                .or_else(|| returning_value(&consequence.statements))
+                .or_else(|| {
+                    alternative
+                        .as_ref()
+                        .and_then(|alt| returning_value(&alt.statements))
+                })
+        }
+        Expression::While(condition, consequence) => {
+            if returns(consequence) {
+                return Some(exp);
+            }
+            returning_value_in(condition).or_else(|| returning_value(&consequence.statements))
+        }
+        // Returning from a function is what `retorne` is for.
+        Expression::FunctionLiteral(_, body) => returning_value(&body.statements),
+        Expression::Call(function, arguments, _) => {
+            returning_value_in(function).or_else(|| arguments.iter().find_map(returning_value_in))
+        }
+        Expression::Array(values) => values.iter().find_map(returning_value_in),
+        Expression::Hash(pairs) => pairs
+            .iter()
+            .find_map(|(k, v)| returning_value_in(k).or_else(|| returning_value_in(v))),
+        Expression::Index(left, right)
+        | Expression::Infix(_, left, right)
+        | Expression::Assign(left, right) => {
+            returning_value_in(left).or_else(|| returning_value_in(right))
+        }
+        Expression::Prefix(_, right) => returning_value_in(right),
+        _ => None,
+    }
+}
+
+// Whether `block` has a `retorne`, outside of the functions it defines.
+fn returns(block: &BlockStatement) -> bool {
+    block.statements.iter().any(|stmt| match stmt {
+        Statement::Return(_) => true,
+        Statement::Expression(Expression::If(_, consequence, alternative)) => {
+            returns(consequence) || alternative.as_ref().is_some_and(returns)
+        }
+        Statement::Expression(Expression::While(_, consequence)) => returns(consequence),
+        _ => false,
+    })
+}
+
+// Python operator precedence, from the loosest to the tightest binding.
+// https://docs.python.org/3/reference/expressions.html#operator-precedence
+#[derive(PartialEq, PartialOrd, Clone, Copy)]
+enum Precedence {
+    Walrus,      // x := y
+    Lambda,      // lambda x: y
+    Conditional, // x if c else y
+    Not,         // not x
+    Comparison,  // ==, !=, <, >
+    Sum,         // +, -
+    Product,     // *, /
+    Unary,       // -x
+    Primary,     // f(x), a[i]
+    Atom,        // literals and names
+}
+
+// What is statically known about the type of a value, used to choose between Python
+// operators that Portugol doesn't distinguish (like integer and float division).
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Type {
+    Int,
+    Float,
+    Str,
+    Bool,
+}
+
+pub struct Transpiler {
+    program: Program,
+    // `None` when a name is used with more than one type.
+    types: HashMap<String, Option<Type>>,
+    names: NameTable,
+    annotate: bool,
+}
+
+impl Transpiler {
+    pub fn new(program: Program) -> Self {
+        let names = NameTable::new(&program, &mangle::PYTHON);
+        let program = names.rename(&program);
+        let mut fresh = FreshNames::new(&program);
+        let (statements, lines) = lift_functions(&program.statements, &program.lines, &mut fresh);
+        let mut transpiler = Transpiler {
+            program: Program { statements, lines },
+            names,
+            types: HashMap::new(),
+            annotate: false,
+        };
+        let mut types = HashMap::new();
+        collect_types(&transpiler.program.statements, &mut types);
+        transpiler.types = types;
+        transpiler
+    }
+
+    // `functions` holds the local names of each enclosing function, the innermost last.
+    fn scoped_statement(
+        &self,
+        out: &mut dyn Write,
+        stmt: &Statement,
+        indent_level: usize,
+        functions: &[HashSet<String>],
+    ) -> io::Result<()> {
+        let spaces = self.indent(indent_level);
+        match stmt {
+            Statement::Let(name, Expression::FunctionLiteral(parameters, body)) => {
+                self.function(out, name, parameters, body, indent_level, functions)?
+            }
+            Statement::Let(ident, value) => {
+                writeln!(out, "{}{} = {}", spaces, ident, self.expression(value))?
+            }
+            Statement::Return(None) => writeln!(out, "{}return", spaces)?,
+            Statement::Return(Some(exp)) => {
+                writeln!(out, "{}return {}", spaces, self.expression(exp))?
+            }
+            Statement::Print(None) => writeln!(out, "{}print()", spaces)?,
+            // `imprima` doesn't end the line by itself.
+            Statement::Print(Some(exp)) if self.prints_as_python(exp) => {
+                writeln!(out, "{}print({}, end=\"\")", spaces, self.expression(exp))?
+            }
+            Statement::Print(Some(exp)) => writeln!(
+                out,
+                "{}print(pt_str({}), end=\"\")",
+                spaces,
+                self.expression(exp)
+            )?,
+            Statement::Read(name) => {
+                let input = match self.types.get(name).cloned().flatten() {
+                    Some(Type::Int) => "int(input())",
+                    Some(Type::Float) => "float(input())",
+                    _ => "input()",
+                };
+                writeln!(out, "{}{} = {}", spaces, name, input)?
+            }
+            Statement::Expression(exp) => match exp {
+                Expression::Assign(name, value) => writeln!(
+                    out,
+                    "{}{} = {}",
+                    spaces,
+                    self.expression(name),
+                    self.expression(value)
+                )?,
+                Expression::If(_, _, _) => {
+                    self.if_statement(out, exp, indent_level, functions, false)?
+                }
+                Expression::While(condition, consequence) => {
+                    writeln!(out, "{}while {}:", spaces, self.expression(condition))?;
+                    self.block(out, consequence, indent_level + 1, functions, false)?;
+                }
+                // Calls and any other expression are valid Python statements.
+                _ => writeln!(out, "{}{}", spaces, self.expression(exp))?,
+            },
+            // Declarations start with the same default values the evaluator uses.
+            Statement::Int(name) => writeln!(out, "{}{}: int = 0", spaces, name)?,
+            Statement::Float(name) => writeln!(out, "{}{}: float = 0.0", spaces, name)?,
+            Statement::String(name) => writeln!(out, "{}{}: str = \"\"", spaces, name)?,
+        }
+        Ok(())
+    }
+
+    // With `implicit_return`, the value of the last expression is returned, as Portugol
+    // functions do.
+    fn block(
+        &self,
+        out: &mut dyn Write,
+        block: &BlockStatement,
+        indent_level: usize,
+        functions: &[HashSet<String>],
+        implicit_return: bool,
+    ) -> io::Result<()> {
+        let statements = &block.statements;
+        // An empty Python block is a syntax error.
+        if statements.is_empty() && indent_level > 0 {
+            return writeln!(out, "{}pass", self.indent(indent_level));
+        }
+        for (i, stmt) in statements.iter().enumerate() {
+            let line = block.line(i).filter(|_| self.annotate);
+            backend::annotated(out, self.comment(), line, |out| match stmt {
+                Statement::Expression(exp) if implicit_return && i == statements.len() - 1 => {
+                    match exp {
+                        Expression::If(_, _, _) => {
+                            self.if_statement(out, exp, indent_level, functions, true)
+                        }
+                        Expression::While(_, _) | Expression::Assign(_, _) => {
+                            self.scoped_statement(out, stmt, indent_level, functions)
+                        }
+                        _ => writeln!(
+                            out,
+                            "{}return {}",
+                            self.indent(indent_level),
+                            self.expression(exp)
+                        ),
+                    }
+                }
+                _ => self.scoped_statement(out, stmt, indent_level, functions),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn if_statement(
+        &self,
+        out: &mut dyn Write,
+        exp: &Expression,
+        indent_level: usize,
+        functions: &[HashSet<String>],
+        implicit_return: bool,
+    ) -> io::Result<()> {
+        if let Expression::If(condition, consequence, alternative) = exp {
+            let spaces = self.indent(indent_level);
+            writeln!(out, "{}if {}:", spaces, self.expression(condition))?;
+            self.block(
+                out,
+                consequence,
+                indent_level + 1,
+                functions,
+                implicit_return,
+            )?;
+            if let Some(alts) = alternative {
+                writeln!(out, "{}else:", spaces)?;
+                self.block(out, alts, indent_level + 1, functions, implicit_return)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn function(
+        &self,
+        out: &mut dyn Write,
+        name: &str,
+        parameters: &[String],
+        body: &BlockStatement,
+        indent_level: usize,
+        functions: &[HashSet<String>],
+    ) -> io::Result<()> {
+        writeln!(
+            out,
+            "{}def {}({}):",
+            self.indent(indent_level),
+            name,
+            parameters.join(", ")
+        )?;
+
+        let mut locals = parameters.iter().cloned().collect::<HashSet<String>>();
+        collect_locals(&body.statements, &mut locals);
+        let mut assigned = BTreeSet::new();
+        collect_assigned(&body.statements, &mut assigned);
+
+        // Assigning to a captured variable would make it local in Python.
+        let (nonlocals, globals): (Vec<String>, Vec<String>) = assigned
+            .into_iter()
+            .filter(|name| !locals.contains(name))
+            .partition(|name| functions.iter().any(|f| f.contains(name)));
+        let spaces = self.indent(indent_level + 1);
+        if !nonlocals.is_empty() {
+            writeln!(out, "{}nonlocal {}", spaces, nonlocals.join(", "))?;
+        }
+        if !globals.is_empty() {
+            writeln!(out, "{}global {}", spaces, globals.join(", "))?;
+        }
+
+        let mut inner = functions.to_vec();
+        inner.push(locals);
+        self.block(out, body, indent_level + 1, &inner, true)
+    }
+
+    // Renders `exp`, parenthesised if it binds looser than `precedence`.
+    fn operand(&self, exp: &Expression, precedence: Precedence) -> String {
+        let (code, p) = self.expression_with_precedence(exp);
+        if p < precedence {
+            format!("({})", code)
+        } else {
+            code
+        }
+    }
+
+    fn expression_with_precedence(&self, exp: &Expression) -> (String, Precedence) {
+        match exp {
+            Expression::Identifier(name) => (name.to_string(), Precedence::Atom),
+            Expression::IntegerLiteral(value) if *value < 0 => {
+                (value.to_string(), Precedence::Unary)
+            }
+            Expression::IntegerLiteral(value) => (value.to_string(), Precedence::Atom),
+            Expression::FloatLiteral(value) => python_float(*value),
+            Expression::StringLiteral(s) => (python_string(s), Precedence::Atom),
+            Expression::Boolean(true) => ("True".to_string(), Precedence::Atom),
+            Expression::Boolean(false) => ("False".to_string(), Precedence::Atom),
+            // Default values of the types, the same the evaluator uses.
+            Expression::Int => ("0".to_string(), Precedence::Atom),
+            Expression::Float => ("0.0".to_string(), Precedence::Atom),
+            Expression::String => ("\"\"".to_string(), Precedence::Atom),
+            Expression::Array(values) => (
+                format!("[{}]", self.comma_separated(values)),
+                Precedence::Atom,
+            ),
+            Expression::Hash(pairs) => {
+                let items = pairs
+                    .iter()
+                    .map(|(k, v)| {
+                        format!(
+                            "{}: {}",
+                            self.operand(k, Precedence::Conditional),
+                            self.operand(v, Precedence::Conditional)
+                        )
+                    })
+                    .collect::<Vec<String>>();
+                (format!("{{{}}}", items.join(", ")), Precedence::Atom)
+            }
+            Expression::Index(left, index) => (
+                format!(
+                    "{}[{}]",
+                    self.operand(left, Precedence::Primary),
+                    self.expression(index)
+                ),
+                Precedence::Primary,
+            ),
+            Expression::Call(function, arguments, _) => (
+                format!(
+                    "{}({})",
+                    self.operand(function, Precedence::Primary),
+                    self.comma_separated(arguments)
+                ),
+                Precedence::Primary,
+            ),
+            Expression::Prefix(Prefix::Bang, right) => (
+                format!("not {}", self.operand(right, Precedence::Not)),
+                Precedence::Not,
+            ),
+            Expression::Prefix(Prefix::Minus, right) => (
+                format!("-{}", self.operand(right, Precedence::Unary)),
+                Precedence::Unary,
+            ),
+            Expression::Infix(Infix::Assign, left, right) | Expression::Assign(left, right) => (
+                format!(
+                    "{} := {}",
+                    self.expression(left),
+                    self.operand(right, Precedence::Conditional)
+                ),
+                Precedence::Walrus,
+            ),
+            Expression::Infix(infix, left, right) => self.infix_expression(infix, left, right),
+            Expression::If(condition, consequence, alternative) => {
+                let alternative = match alternative {
+                    Some(alt) => block_value(alt).map(|v| self.operand(v, Precedence::Conditional)),
+                    None => Some("None".to_string()),
+                };
+                match (block_value(consequence), alternative) {
+                    (Some(cons), Some(alt)) => (
+                        format!(
+                            "{} if {} else {}",
+                            self.operand(cons, Precedence::Not),
+                            self.operand(condition, Precedence::Not),
+                            alt
+                        ),
+                        Precedence::Conditional,
+                    ),
+                    // Unreachable after `lift_functions`, which moves branches with statements
+                    // into functions.
+                    _ => ("None".to_string(), Precedence::Atom),
+                }
+            }
+            // Unreachable after `lift_functions`, which moves loops used as values into
+            // functions.
+            Expression::While(_, _) => ("None".to_string(), Precedence::Atom),
+            Expression::FunctionLiteral(parameters, body) => match block_value(body) {
+                Some(value) => (
+                    format!(
+                        "lambda {}: {}",
+                        parameters.join(", "),
+                        self.operand(value, Precedence::Lambda)
+                    ),
+                    Precedence::Lambda,
+                ),
+                // Functions with statements need a `def`.
+                None => ("None".to_string(), Precedence::Atom),
+            },
+        }
+    }
+
+    fn infix_expression(
+        &self,
+        infix: &Infix,
+        left: &Expression,
+        right: &Expression,
+    ) -> (String, Precedence) {
+        let (operator, precedence) = match infix {
+            Infix::Eq => ("==", Precedence::Comparison),
+            Infix::NotEq => ("!=", Precedence::Comparison),
+            Infix::Lt => ("<", Precedence::Comparison),
+            Infix::Gt => (">", Precedence::Comparison),
+            Infix::Plus => ("+", Precedence::Sum),
+            Infix::Minus => ("-", Precedence::Sum),
+            Infix::Asterisk => ("*", Precedence::Product),
+            Infix::Slash => ("/", Precedence::Product),
+            Infix::Assign => unreachable!("assignments are rendered with `:=`"),
+        };
+        if *infix == Infix::Slash && self.is_int_division(left, right) {
+            return (
+                format!(
+                    "pt_div({}, {})",
+                    self.operand(left, Precedence::Conditional),
+                    self.operand(right, Precedence::Conditional)
+                ),
+                Precedence::Primary,
+            );
+        }
+        // Python chains comparisons (`a < b == c` is `a < b and b == c`), so nested
+        // comparisons always need parentheses. Other operators are left-associative.
+        let left_precedence = if precedence == Precedence::Comparison {
+            Precedence::Sum
+        } else {
+            precedence
+        };
+        (
+            format!(
+                "{} {} {}",
+                self.operand(left, left_precedence),
+                operator,
+                self.operand(right, next(precedence))
+            ),
+            precedence,
+        )
+    }
+
+    fn comma_separated(&self, exps: &[Expression]) -> String {
+        exps.iter()
+            .map(|exp| self.operand(exp, Precedence::Conditional))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    fn expression_type(&self, exp: &Expression) -> Option<Type> {
+        infer_type(exp, &self.types)
+    }
+
+    fn is_int_division(&self, left: &Expression, right: &Expression) -> bool {
+        self.expression_type(left) == Some(Type::Int)
+            && self.expression_type(right) == Some(Type::Int)
+    }
+
+    fn uses_int_division(&self, statements: &[Statement]) -> bool {
+        contains(statements, &|_| false, &|exp| match exp {
+            Expression::Infix(Infix::Slash, left, right) => self.is_int_division(left, right),
+            _ => false,
+        })
+    }
+
+    // Whether Python prints the value of `exp` like the interpreter does. Other values go
+    // through `pt_str`.
+    fn prints_as_python(&self, exp: &Expression) -> bool {
+        matches!(self.expression_type(exp), Some(Type::Int) | Some(Type::Str))
+    }
+
+    fn uses_print_value(&self, statements: &[Statement]) -> bool {
+        statements.iter().any(|stmt| match stmt {
+            Statement::Print(Some(exp)) => !self.prints_as_python(exp),
+            Statement::Let(_, Expression::FunctionLiteral(_, body)) => {
+                self.uses_print_value(&body.statements)
+            }
+            Statement::Expression(Expression::If(_, consequence, alternative)) => {
+                self.uses_print_value(&consequence.statements)
+                    || alternative
+                        .as_ref()
+                        .is_some_and(|alt| self.uses_print_value(&alt.statements))
+            }
+            Statement::Expression(Expression::While(_, consequence)) => {
+                self.uses_print_value(&consequence.statements)
+            }
+            _ => false,
+        })
+    }
+}
+
+fn collect_types(statements: &[Statement], types: &mut HashMap<String, Option<Type>>) {
+    for stmt in statements {
+        match stmt {
+            Statement::Int(name) => declare(types, name, Some(Type::Int)),
+            Statement::Float(name) => declare(types, name, Some(Type::Float)),
+            Statement::String(name) => declare(types, name, Some(Type::Str)),
+            Statement::Let(name, Expression::FunctionLiteral(_, body)) => {
+                declare(types, name, None);
+                collect_types(&body.statements, types);
+            }
+            Statement::Let(name, value) => {
+                let t = infer_type(value, types);
+                declare(types, name, t);
+            }
+            Statement::Expression(Expression::If(_, consequence, alternative)) => {
+                collect_types(&consequence.statements, types);
+                if let Some(alt) = alternative {
+                    collect_types(&alt.statements, types);
+                }
+            }
+            Statement::Expression(Expression::While(_, consequence)) => {
+                collect_types(&consequence.statements, types);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn infer_type(exp: &Expression, types: &HashMap<String, Option<Type>>) -> Option<Type> {
+    match exp {
+        Expression::IntegerLiteral(_) | Expression::Int => Some(Type::Int),
+        Expression::FloatLiteral(_) | Expression::Float => Some(Type::Float),
+        Expression::StringLiteral(_) | Expression::String => Some(Type::Str),
+        Expression::Boolean(_) | Expression::Prefix(Prefix::Bang, _) => Some(Type::Bool),
+        Expression::Identifier(name) => types.get(name).cloned().flatten(),
+        Expression::Prefix(Prefix::Minus, right) => infer_type(right, types),
+        Expression::Infix(infix, left, right) => match infix {
+            Infix::Eq | Infix::NotEq | Infix::Lt | Infix::Gt => Some(Type::Bool),
+            Infix::Assign => infer_type(right, types),
+            Infix::Plus | Infix::Minus | Infix::Asterisk | Infix::Slash => {
+                match (infer_type(left, types), infer_type(right, types)) {
+                    (Some(Type::Int), Some(Type::Int)) => Some(Type::Int),
+                    (Some(Type::Int), Some(Type::Float))
+                    | (Some(Type::Float), Some(Type::Int))
+                    | (Some(Type::Float), Some(Type::Float)) => Some(Type::Float),
+                    (Some(Type::Str), Some(Type::Str)) if *infix == Infix::Plus => Some(Type::Str),
+                    _ => None,
+                }
+            }
+        },
+        _ => None,
+    }
+}
+
+impl Backend for Transpiler {
+    fn program(&self) -> &Program {
+        &self.program
+    }
+
+    fn names(&self) -> &NameTable {
+        &self.names
+    }
+
+    fn comment(&self) -> &'static str {
+        "#"
+    }
+
+    fn set_annotate(&mut self, annotate: bool) {
+        self.annotate = annotate;
+    }
+
+    fn prologue(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.uses_print_value(&self.program.statements) {
+            writeln!(out, "{}", PRINT_VALUE)?;
+        }
+        if self.uses_int_division(&self.program.statements) {
+            writeln!(out, "{}", INT_DIVISION)?;
+        }
+        Ok(())
+    }
+
+    fn statement(
+        &self,
+        out: &mut dyn Write,
+        stmt: &Statement,
+        line: Option<usize>,
+        indent_level: usize,
+    ) -> io::Result<()> {
+        let line = line.filter(|_| self.annotate);
+        backend::annotated(out, self.comment(), line, |out| {
+            self.scoped_statement(out, stmt, indent_level, &[])
+        })
+    }
+
+    // Renders an expression as Python source.
+    fn expression(&self, exp: &Expression) -> String {
+        self.expression_with_precedence(exp).0
+    }
+}
+
+// Python lambdas can't hold statements, so anonymous functions are moved into named
+// functions defined right before the statement that uses them. So are the branches of a `se`
+// and the loops used as values (see `lift_branch`). Defining a function has no side effects,
+// so this doesn't change the order anything runs in. Lifted functions get the line of the
+// statement they come from.
+fn lift_functions(
+    statements: &[Statement],
+    lines: &[usize],
+    names: &mut FreshNames,
+) -> (Vec<Statement>, Vec<usize>) {
+    let mut result = vec![];
+    let mut result_lines = vec![];
+    for (i, stmt) in statements.iter().enumerate() {
+        let mut lifted = vec![];
+        let stmt = match stmt {
+            Statement::Let(name, Expression::FunctionLiteral(parameters, body)) => Statement::Let(
+                name.clone(),
+                Expression::FunctionLiteral(parameters.clone(), lift_block(body, names)),
+            ),
+            Statement::Let(name, exp) => {
+                Statement::Let(name.clone(), lift_expression(exp, &mut lifted, names))
+            }
+            Statement::Return(Some(exp)) => {
+                Statement::Return(Some(lift_expression(exp, &mut lifted, names)))
+            }
+            Statement::Print(Some(exp)) => {
+                Statement::Print(Some(lift_expression(exp, &mut lifted, names)))
+            }
+            // Used as statements, `se` and `enquanto` keep their blocks.
+            Statement::Expression(Expression::If(condition, consequence, alternative)) => {
+                Statement::Expression(Expression::If(
+                    Box::new(lift_expression(condition, &mut lifted, names)),
+                    lift_block(consequence, names),
+                    alternative.as_ref().map(|alt| lift_block(alt, names)),
+                ))
+            }
+            Statement::Expression(Expression::While(condition, consequence)) => {
+                Statement::Expression(Expression::While(
+                    Box::new(lift_expression(condition, &mut lifted, names)),
+                    lift_block(consequence, names),
+                ))
+            }
+            Statement::Expression(exp) => {
+                Statement::Expression(lift_expression(exp, &mut lifted, names))
+            }
+            _ => stmt.clone(),
+        };
+        if let Some(line) = lines.get(i) {
+            result_lines.extend(std::iter::repeat_n(*line, lifted.len() + 1));
+        }
+        result.append(&mut lifted);
+        result.push(stmt);
+    }
+    (result, result_lines)
+}
+
+fn lift_block(block: &BlockStatement, names: &mut FreshNames) -> BlockStatement {
+    let (statements, lines) = lift_functions(&block.statements, &block.lines, names);
+    BlockStatement { statements, lines }
+}
+
+fn lift_expression(
+    exp: &Expression,
+    lifted: &mut Vec<Statement>,
+    names: &mut FreshNames,
+) -> Expression {
+    let mut lift = |e: &Expression| Box::new(lift_expression(e, lifted, names));
+    match exp {
+        Expression::FunctionLiteral(parameters, body) => {
+            let name = names.fresh("_funcao");
+            let body = lift_block(body, names);
+            lifted.push(Statement::Let(
+                name.clone(),
+                Expression::FunctionLiteral(parameters.clone(), body),
+            ));
+            Expression::Identifier(name)
+        }
+        Expression::Array(values) => Expression::Array(
+            values
+                .iter()
+                .map(|v| lift_expression(v, lifted, names))
+                .collect(),
+        ),
+        Expression::Hash(pairs) => Expression::Hash(
+            pairs
+                .iter()
+                .map(|(k, v)| {
+                    (
+                        lift_expression(k, lifted, names),
+                        lift_expression(v, lifted, names),
+                    )
+                })
+                .collect(),
+        ),
+        Expression::Index(left, index) => Expression::Index(lift(left), lift(index)),
+        Expression::Prefix(prefix, right) => Expression::Prefix(prefix.clone(), lift(right)),
+        Expression::Infix(infix, left, right) => {
+            Expression::Infix(infix.clone(), lift(left), lift(right))
+        }
+        Expression::Assign(left, right) => Expression::Assign(left.clone(), lift(right)),
+        Expression::Call(function, arguments, position) => {
+            let function = lift(function);
+            Expression::Call(
+                function,
+                arguments
+                    .iter()
+                    .map(|a| lift_expression(a, lifted, names))
+                    .collect(),
+                *position,
+            )
+        }
+        Expression::If(condition, consequence, alternative) => {
+            let condition = lift(condition);
+            let consequence = lift_branch(consequence, lifted, names);
+            let alternative = alternative
+                .as_ref()
+                .map(|alt| lift_branch(alt, lifted, names));
+            Expression::If(condition, consequence, alternative)
+        }
+        // Runs the loop in a function, where it is a statement.
+        Expression::While(_, _) => {
+            let loop_block = BlockStatement {
+                statements: vec![Statement::Expression(exp.clone())],
+                lines: vec![],
+            };
+            lift_thunk(&loop_block, lifted, names)
+        }
+        _ => exp.clone(),
+    }
+}
+
+// A branch of a `se` used as a value. Python conditional expressions can only hold
+// expressions, so a branch with statements becomes a function called in its place, and only
+// runs when the branch is taken.
+fn lift_branch(
+    block: &BlockStatement,
+    lifted: &mut Vec<Statement>,
+    names: &mut FreshNames,
+) -> BlockStatement {
+    let statement = match block.statements.as_slice() {
+        [Statement::Expression(exp)] if block_value(block).is_some() => {
+            Statement::Expression(lift_expression(exp, lifted, names))
+        }
+        _ => Statement::Expression(lift_thunk(block, lifted, names)),
+    };
+    BlockStatement {
+        statements: vec![statement],
+        lines: block.lines.iter().take(1).copied().collect(),
+    }
+}
+
+// Moves `block` into a function without parameters and returns a call to it.
+fn lift_thunk(
+    block: &BlockStatement,
+    lifted: &mut Vec<Statement>,
+    names: &mut FreshNames,
+) -> Expression {
+    let name = names.fresh("_funcao");
+    let body = lift_block(block, names);
+    lifted.push(Statement::Let(
+        name.clone(),
+        Expression::FunctionLiteral(vec![], body),
+    ));
+    Expression::Call(Box::new(Expression::Identifier(name)), vec![], None)
+}
+
+// Names bound inside a function body, without looking into nested functions.
+fn collect_locals(statements: &[Statement], locals: &mut HashSet<String>) {
+    for stmt in statements {
+        match stmt {
+            Statement::Let(name, _)
+            | Statement::Int(name)
+            | Statement::Float(name)
+            | Statement::String(name) => {
+                locals.insert(name.clone());
+            }
+            Statement::Expression(Expression::If(_, consequence, alternative)) => {
+                collect_locals(&consequence.statements, locals);
+                if let Some(alt) = alternative {
+                    collect_locals(&alt.statements, locals);
+                }
+            }
+            Statement::Expression(Expression::While(_, consequence)) => {
+                collect_locals(&consequence.statements, locals);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Names assigned with `<-` or `leia` inside a function body, without looking into nested
+// functions.
+fn collect_assigned(statements: &[Statement], assigned: &mut BTreeSet<String>) {
+    for stmt in statements {
+        match stmt {
+            Statement::Read(name) => {
+                assigned.insert(name.clone());
+            }
+            Statement::Expression(Expression::Assign(left, _)) => {
+                if let Expression::Identifier(name) = left.as_ref() {
+                    assigned.insert(name.clone());
+                }
+            }
+            Statement::Expression(Expression::If(_, consequence, alternative)) => {
+                collect_assigned(&consequence.statements, assigned);
+                if let Some(alt) = alternative {
+                    collect_assigned(&alt.statements, assigned);
+                }
+            }
+            Statement::Expression(Expression::While(_, consequence)) => {
+                collect_assigned(&consequence.statements, assigned);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Whether a statement or an expression of `statements`, at any depth, is one `stmt_found` or
+// `exp_found` is looking for.
+fn contains(
+    statements: &[Statement],
+    stmt_found: &dyn Fn(&Statement) -> bool,
+    exp_found: &dyn Fn(&Expression) -> bool,
+) -> bool {
+    statements.iter().any(|stmt| {
+        stmt_found(stmt)
+            || match stmt {
+                Statement::Let(_, exp)
+                | Statement::Return(Some(exp))
+                | Statement::Print(Some(exp))
+                | Statement::Expression(exp) => expression_contains(exp, stmt_found, exp_found),
+                _ => false,
+            }
+    })
+}
+
+fn expression_contains(
+    exp: &Expression,
+    stmt_found: &dyn Fn(&Statement) -> bool,
+    exp_found: &dyn Fn(&Expression) -> bool,
+) -> bool {
+    let contains_exp = |exp: &Expression| expression_contains(exp, stmt_found, exp_found);
+    let contains_block =
+        |block: &BlockStatement| contains(&block.statements, stmt_found, exp_found);
+    exp_found(exp)
+        || match exp {
+            Expression::If(condition, consequence, alternative) => {
+                contains_exp(condition)
+                    || contains_block(consequence)
+                    || alternative.as_ref().is_some_and(contains_block)
+            }
+            Expression::While(condition, consequence) => {
+                contains_exp(condition) || contains_block(consequence)
+            }
+            Expression::FunctionLiteral(_, body) => contains_block(body),
+            Expression::Call(function, arguments, _) => {
+                contains_exp(function) || arguments.iter().any(contains_exp)
+            }
+            Expression::Array(values) => values.iter().any(contains_exp),
+            Expression::Hash(pairs) => pairs
+                .iter()
+                .any(|(k, v)| contains_exp(k) || contains_exp(v)),
+            Expression::Index(left, right)
+            | Expression::Infix(_, left, right)
+            | Expression::Assign(left, right) => contains_exp(left) || contains_exp(right),
+            Expression::Prefix(_, right) => contains_exp(right),
+            _ => false,
+        }
+}
+
+fn declare(types: &mut HashMap<String, Option<Type>>, name: &str, t: Option<Type>) {
+    let entry = types.entry(name.to_string()).or_insert(t);
+    if *entry != t {
+        *entry = None;
+    }
+}
+
+// The precedence right above `precedence`, used for the right operand of left-associative
+// operators.
+fn next(precedence: Precedence) -> Precedence {
+    match precedence {
+        Precedence::Walrus => Precedence::Lambda,
+        Precedence::Lambda => Precedence::Conditional,
+        Precedence::Conditional => Precedence::Not,
+        Precedence::Not => Precedence::Comparison,
+        Precedence::Comparison => Precedence::Sum,
+        Precedence::Sum => Precedence::Product,
+        Precedence::Product => Precedence::Unary,
+        Precedence::Unary => Precedence::Primary,
+        Precedence::Primary | Precedence::Atom => Precedence::Atom,
+    }
+}
+
+// The value of a block made of a single expression (or `retorne`), if it's that simple.
+fn block_value(block: &BlockStatement) -> Option<&Expression> {
+    match block.statements.as_slice() {
+        [Statement::Expression(exp)] | [Statement::Return(Some(exp))] => match exp {
+            Expression::If(_, _, _) | Expression::While(_, _) | Expression::Assign(_, _) => None,
+            _ => Some(exp),
+        },
+        _ => None,
+    }
+}
+
+fn python_float(value: f64) -> (String, Precedence) {
+    if value.is_nan() {
+        ("float(\"nan\")".to_string(), Precedence::Primary)
+    } else if value.is_infinite() && value > 0.0 {
+        ("float(\"inf\")".to_string(), Precedence::Primary)
+    } else if value.is_infinite() {
+        ("-float(\"inf\")".to_string(), Precedence::Unary)
+    } else if value < 0.0 {
+        // `{:?}` always keeps a fractional part or an exponent, like Python float literals.
+        (format!("{:?}", value), Precedence::Unary)
+    } else {
+        (format!("{:?}", value), Precedence::Atom)
+    }
+}
+
+// Portugol strings have no escapes: a backslash is a backslash. Backslashes, quotes and raw
+// line breaks are escaped for Python.
+fn python_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transpiler::{backend, Backend, Transpiler, TARGETS};
+
+    #[test]
+    fn transpile_to_writer() {
+        let input = "
+            int x;
+            leia x;
+            se (x > 1) {
+                imprima x;
+            } senao {
+                x <- 1;
+            }
+        ";
+        let expected = "x: int = 0
+x = int(input())
+if x > 1:
+    print(x, end=\"\")
+else:
+    x = 1
+";
+        let transpiler = Transpiler::new(parse(input));
+        let mut out = vec![];
+        transpiler.transpile(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(transpiler.transpile_to_string(), expected);
+    }
+
+    #[test]
+    fn expressions() {
+        expect_transpiled(vec![
+            ("let a = 1 + 2 * 3;", "a = 1 + 2 * 3\n"),
+            ("let a = (1 + 2) * 3;", "a = (1 + 2) * 3\n"),
+            ("let a = 1 - (2 - 3);", "a = 1 - (2 - 3)\n"),
+            ("let a = 1 - 2 - 3;", "a = 1 - 2 - 3\n"),
+            ("let a = -(1 + b);", "a = -(1 + b)\n"),
+            ("let a = !VERDADEIRO;", "a = not True\n"),
+            ("let a = !(b == FALSO);", "a = not b == False\n"),
+            ("let a = 1 < 2 == VERDADEIRO;", "a = (1 < 2) == True\n"),
+            ("let a = 2.0;", "a = 2.0\n"),
+            ("let a = [1, 2][0];", "a = [1, 2][0]\n"),
+            (
+                r#"let a = {"um": 1, "dois": [2]};"#,
+                "a = {\"um\": 1, \"dois\": [2]}\n",
+            ),
+            ("let a = f(1, g(2))[3];", "a = f(1, g(2))[3]\n"),
+            ("let a = se (b) { 1 } senao { 2 };", "a = 1 if b else 2\n"),
+        ]);
+    }
+
+    #[test]
+    fn if_expressions_with_statements() {
+        let input = "
+            int x;
+            let y = se (x == 0) { imprima(\"a\"); 1 } senao { 2 };
+            let f = funcao(n) { n + se (n > 1) { x <- n; 3 } };
+            let z = enquanto (x > 0) { x <- x - 1; };
+        ";
+        let expected = "x: int = 0
+def _funcao_1():
+    print(\"a\", end=\"\")
+    return 1
+y = _funcao_1() if x == 0 else 2
+def f(n):
+    def _funcao_2():
+        global x
+        x = n
+        return 3
+    return n + (_funcao_2() if n > 1 else None)
+def _funcao_3():
+    global x
+    while x > 0:
+        x = x - 1
+z = _funcao_3()
+";
+        expect_transpiled(vec![(input, expected)]);
+    }
+
+    #[test]
+    fn print_values() {
+        expect_transpiled(vec![
+            ("imprima 1 + 2;", "print(1 + 2, end=\"\")\n"),
+            ("imprima \"a\";", "print(\"a\", end=\"\")\n"),
+            (
+                "imprima 2.0; imprima 1 < 2;",
+                &format!(
+                    "{}\nprint(pt_str(2.0), end=\"\")\nprint(pt_str(1 < 2), end=\"\")\n",
+                    super::PRINT_VALUE
+                ),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn division() {
+        expect_transpiled(vec![
+            (
+                "int a; let b = -a / 2;",
+                &format!("{}\na: int = 0\nb = pt_div(-a, 2)\n", super::INT_DIVISION),
+            ),
+            ("real a; let b = a / 2;", "a: float = 0.0\nb = a / 2\n"),
+            ("let b = c / 2;", "b = c / 2\n"),
+        ]);
+    }
+
+    #[test]
+    fn strings() {
+        // Portugol has no escapes, and strings can span lines.
+        expect_transpiled(vec![
+            (r#"imprima "a\nb";"#, "print(\"a\\\\nb\", end=\"\")\n"),
+            ("imprima \"a\nb\\\";", "print(\"a\\nb\\\\\", end=\"\")\n"),
+        ]);
+    }
+
+    #[test]
+    fn returning_values() {
+        let tests = vec![
+            "let f = funcao(n) { let y = se (n > 0) { retorne 1; } senao { 2 }; retorne y + 10; };",
+            "let f = funcao(n) { imprima(se (n > 0) { se (n > 1) { retorne 1; } 2 }); };",
+            "let y = enquanto (VERDADEIRO) { retorne 1; };",
+        ];
+        for target in TARGETS {
+            for input in &tests {
+                match backend(target, parse(input)) {
+                    Ok(_) => panic!("expected an error for `{}` in {}", input, target),
+                    Err(err) => assert!(
+                        err.to_string()
+                            .starts_with("`retorne` in a `se` or `enquanto` used as a value"),
+                        "for `{}` in {}: {}",
+                        input,
+                        target,
+                        err
+                    ),
+                }
+            }
+        }
+        // `retorne` is fine in functions used as values, and in `se` used as a statement.
+        let input = "let f = se (a) { funcao() { retorne 1; } }; se (a) { retorne 2; }";
+        for target in ["python", "javascript"] {
+            assert!(backend(target, parse(input)).is_ok());
+        }
+    }
+
+    #[test]
+    fn expression_statements() {
+        expect_transpiled(vec![
+            ("f(1, 2);", "f(1, 2)\n"),
+            ("imprima;", "print()\n"),
+            (
+                "se (a) { } senao { f(); }",
+                "if a:\n    pass\nelse:\n    f()\n",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn functions() {
+        expect_transpiled(vec![
+            (
+                "let soma = funcao(a, b) { a + b };",
+                "def soma(a, b):\n    return a + b\n",
+            ),
+            ("let f = funcao() { }; f();", "def f():\n    pass\nf()\n"),
+            (
+                "let maior = funcao(a, b) { se (a > b) { a } senao { retorne b; } };",
+                "def maior(a, b):
+    if a > b:
+        return a
+    else:
+        return b
+",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn closures() {
+        let input = "
+            int total;
+            let contador = funcao() {
+                int n;
+                let incrementa = funcao(passo) {
+                    n <- n + passo;
+                    total <- total + passo;
+                    n
+                };
+                incrementa
+            };
+        ";
+        let expected = "total: int = 0
+def contador():
+    n: int = 0
+    def incrementa(passo):
+        nonlocal n
+        global total
+        n = n + passo
+        total = total + passo
+        return n
+    return incrementa
+";
+        expect_transpiled(vec![(input, expected)]);
+    }
+
+    #[test]
+    fn anonymous_functions() {
+        let input = "
+            let aplica = funcao(f, x) { f(x) };
+            imprima aplica(funcao(x) { imprima x; x * 2 }, 3);
+            let g = funcao() { funcao(y) { y } };
+        ";
+        let expected = "def aplica(f, x):
+    return f(x)
+def _funcao_1(x):
+    print(pt_str(x), end=\"\")
+    return x * 2
+print(pt_str(aplica(_funcao_1, 3)), end=\"\")
+def g():
+    def _funcao_2(y):
+        return y
+    return _funcao_2
+";
+        expect_transpiled(vec![(
+            input,
+            &format!("{}\n{}", super::PRINT_VALUE, expected),
+        )]);
+    }
+
+    #[test]
+    fn declarations() {
+        expect_transpiled(vec![
+            (
+                "int i; real r; string s;",
+                "i: int = 0\nr: float = 0.0\ns: str = \"\"\n",
+            ),
+            (
+                "int i; real r; string s; leia i; leia r; leia s;",
+                "i: int = 0
+r: float = 0.0
+s: str = \"\"
+i = int(input())
+r = float(input())
+s = input()
+",
+            ),
+            // Declared in a loop, reset on every iteration like in the evaluator.
+            (
+                "enquanto (VERDADEIRO) { int n; }",
+                "while True:\n    n: int = 0\n",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn mangled_names() {
+        expect_transpiled(vec![
+            (
+                "let print = 1; imprima print;",
+                "print_ = 1\nprint(print_, end=\"\")\n",
+            ),
+            (
+                "let lambda = funcao(str) { retorne str; }; let _funcao_1 = 0; lambda(funcao() { 1 });",
+                "def lambda_(str_):
+    return str_
+_funcao_1 = 0
+def _funcao_2():
+    return 1
+lambda_(_funcao_2)
+",
+            ),
+            ("let $x = len(\"a\");", "_u24x = len(\"a\")\n"),
+        ]);
+    }
+
+    #[test]
+    fn targets() {
+        let input = "imprima 1 + 2;";
+        let tests = vec![
+            ("python", "print(1 + 2, end=\"\")\n"),
+            ("javascript", "process.stdout.write(String(1 + 2));\n"),
+        ];
+        for (target, expected) in tests {
+            match backend(target, parse(input)) {
+                Ok(backend) => assert_eq!(backend.transpile_to_string(), expected),
+                Err(err) => panic!("{} for target `{}`", err, target),
+            }
+        }
+        let c = backend("c", parse(input)).unwrap().transpile_to_string();
+        assert!(c.ends_with(
+            "int main(void) {\n    printf(\"%lld\", pt_add(1, 2));\n    return 0;\n}\n"
+        ));
+
+        match backend("cobol", parse(input)) {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "unknown target `cobol`, expected one of: python, c, javascript"
+            ),
+        }
+    }
+
+    fn expect_transpiled(tests: Vec<(&str, &str)>) {
+        for (input, expected) in tests {
+            let transpiler = Transpiler::new(parse(input));
+            assert_eq!(
+                transpiler.transpile_to_string(),
+                expected,
+                "for `{}`",
+                input
+            );
+        }
+    }
+
+    fn parse(input: &str) -> crate::ast::Program {
+        let lexer = Lexer::new(input.to_owned());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        assert!(
+            parser.errors().is_empty(),
+            "for `{}`: {:?}",
+            input,
+            parser.errors()
+        );
+        program
+    }
+}
@@ -0,0 +1,399 @@
+use super::backend::ANNOTATION;
+use std::fmt;
+
+// Maps each line of the generated code back to the Portugol line it came from.
+//
+// Maps are built from annotated code (see `Backend::set_annotate`) and saved as JSON, like
+// `{"file":"out.py","source":"prog.por","lines":[null,1,2,2]}`, where `lines[i]` is the
+// Portugol line of the generated line `i + 1`.
+#[derive(Debug, PartialEq)]
+pub struct SourceMap {
+    // The generated file.
+    pub file: String,
+    // The Portugol file.
+    pub source: String,
+    pub lines: Vec<Option<usize>>,
+}
+
+impl SourceMap {
+    // Strips the annotations from `code`, returning the clean code and its map.
+    pub fn from_annotated(code: &str, comment: &str, file: &str, source: &str) -> (String, Self) {
+        let marker = format!("  {} {}", comment, ANNOTATION);
+        let mut clean = String::new();
+        let mut lines = vec![];
+        for line in code.lines() {
+            let annotation = line.rfind(&marker).and_then(|start| {
+                let number = line[start + marker.len()..].parse::<usize>().ok()?;
+                Some((start, number))
+            });
+            match annotation {
+                Some((start, number)) => {
+                    clean.push_str(&line[..start]);
+                    lines.push(Some(number));
+                }
+                None => {
+                    clean.push_str(line);
+                    lines.push(None);
+                }
+            }
+            clean.push('\n');
+        }
+        let map = SourceMap {
+            file: file.to_string(),
+            source: source.to_string(),
+            lines,
+        };
+        (clean, map)
+    }
+
+    // The Portugol line of a generated line, counting from 1. Lines without an annotation,
+    // like the `else:` of an `if`, belong to the closest annotated line above them.
+    pub fn portugol_line(&self, generated: usize) -> Option<usize> {
+        let end = generated.min(self.lines.len());
+        self.lines[..end].iter().rev().find_map(|line| *line)
+    }
+
+    pub fn to_json(&self) -> String {
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| match line {
+                Some(line) => line.to_string(),
+                None => "null".to_string(),
+            })
+            .collect::<Vec<String>>();
+        format!(
+            "{{\"file\":{},\"source\":{},\"lines\":[{}]}}\n",
+            json_string(&self.file),
+            json_string(&self.source),
+            lines.join(",")
+        )
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SourceMapError> {
+        let mut reader = JsonReader {
+            chars: json.chars().collect(),
+            position: 0,
+        };
+        let mut file = None;
+        let mut source = None;
+        let mut lines = None;
+        reader.expect('{')?;
+        loop {
+            let key = reader.string()?;
+            reader.expect(':')?;
+            match key.as_str() {
+                "file" => file = Some(reader.string()?),
+                "source" => source = Some(reader.string()?),
+                "lines" => lines = Some(reader.lines()?),
+                _ => return Err(SourceMapError(format!("unknown key `{}`", key))),
+            }
+            if reader.next() == Some('}') {
+                break;
+            }
+            reader.back();
+            reader.expect(',')?;
+        }
+        let missing = |key: &str| SourceMapError(format!("missing key `{}`", key));
+        Ok(SourceMap {
+            file: file.ok_or_else(|| missing("file"))?,
+            source: source.ok_or_else(|| missing("source"))?,
+            lines: lines.ok_or_else(|| missing("lines"))?,
+        })
+    }
+
+    // Rewrites the locations of a Python traceback, like `File "/tmp/out.py", line 7`, to the
+    // Portugol file and line. Python shows the path the script was run with, so any path that
+    // ends with the generated file matches. Other lines are kept as they are.
+    pub fn rewrite_traceback(&self, traceback: &str) -> String {
+        let mut out = String::new();
+        for line in traceback.lines() {
+            match self.rewrite_location(line) {
+                Some(rewritten) => out.push_str(&rewritten),
+                None => out.push_str(line),
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn rewrite_location(&self, line: &str) -> Option<String> {
+        let start = line.find("File \"")?;
+        let path_start = start + "File \"".len();
+        let path_end = path_start + line[path_start..].find('"')?;
+        let path = &line[path_start..path_end];
+        let is_file = path == self.file
+            || (path.ends_with(&self.file)
+                && path[..path.len() - self.file.len()].ends_with(['/', '\\']));
+        let rest = line[path_end..].strip_prefix("\", line ")?;
+        let number_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let generated = rest[..number_end].parse().ok()?;
+        if !is_file {
+            return None;
+        }
+        let portugol = self.portugol_line(generated)?;
+        Some(format!(
+            "{}File \"{}\", line {}{}",
+            &line[..start],
+            self.source,
+            portugol,
+            &rest[number_end..]
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct SourceMapError(String);
+
+impl fmt::Display for SourceMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid source map: {}", self.0)
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Reads the small subset of JSON that source maps use.
+struct JsonReader {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl JsonReader {
+    // The next character that isn't whitespace.
+    fn next(&mut self) -> Option<char> {
+        while let Some(c) = self.chars.get(self.position) {
+            self.position += 1;
+            if !c.is_whitespace() {
+                return Some(*c);
+            }
+        }
+        None
+    }
+
+    fn back(&mut self) {
+        self.position -= 1;
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SourceMapError> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(SourceMapError(format!(
+                "expected `{}`, found `{}`",
+                expected, c
+            ))),
+            None => Err(SourceMapError(format!(
+                "expected `{}`, found the end",
+                expected
+            ))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SourceMapError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let c = self.chars.get(self.position).copied();
+            self.position += 1;
+            match c {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let escaped = self.chars.get(self.position).copied();
+                    self.position += 1;
+                    match escaped {
+                        Some('n') => s.push('\n'),
+                        Some('t') => s.push('\t'),
+                        Some('u') => {
+                            let hex = self
+                                .chars
+                                .get(self.position..self.position + 4)
+                                .map(|hex| hex.iter().collect::<String>())
+                                .unwrap_or_default();
+                            self.position += 4;
+                            let c = u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| {
+                                    SourceMapError(format!("invalid escape `\\u{}`", hex))
+                                })?;
+                            s.push(c);
+                        }
+                        Some(c) => s.push(c),
+                        None => break,
+                    }
+                }
+                Some(c) => s.push(c),
+                None => break,
+            }
+        }
+        Err(SourceMapError("unterminated string".to_string()))
+    }
+
+    fn lines(&mut self) -> Result<Vec<Option<usize>>, SourceMapError> {
+        self.expect('[')?;
+        let mut lines = vec![];
+        if self.next() == Some(']') {
+            return Ok(lines);
+        }
+        self.back();
+        loop {
+            let mut value = String::new();
+            while let Some(c) = self.next() {
+                if c == ',' || c == ']' {
+                    self.back();
+                    break;
+                }
+                value.push(c);
+            }
+            match value.as_str() {
+                "null" => lines.push(None),
+                number => {
+                    lines.push(Some(number.parse().map_err(|_| {
+                        SourceMapError(format!("invalid line `{}`", number))
+                    })?))
+                }
+            }
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(lines),
+                _ => return Err(SourceMapError("unterminated lines".to_string())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceMap;
+    use crate::transpiler::{Backend, Transpiler};
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn transpile(input: &str) -> (String, SourceMap) {
+        let program = Parser::new(Lexer::new(input.to_string())).parse_program();
+        let mut transpiler = Transpiler::new(program);
+        transpiler.set_annotate(true);
+        let code = transpiler.transpile_to_string();
+        SourceMap::from_annotated(&code, transpiler.comment(), "out.py", "prog.por")
+    }
+
+    #[test]
+    fn annotations() {
+        let input = r#"int x;
+x = 1;
+
+se (x > 0) {
+    imprima("positivo");
+} senao {
+    imprima("negativo");
+}
+"#;
+        let program = Parser::new(Lexer::new(input.to_string())).parse_program();
+        let mut transpiler = Transpiler::new(program);
+        transpiler.set_annotate(true);
+        let expected = r#"x: int = 0  # portugol:1
+x = 1  # portugol:2
+if x > 0:  # portugol:4
+    print("positivo", end="")  # portugol:5
+else:
+    print("negativo", end="")  # portugol:7
+"#;
+        assert_eq!(transpiler.transpile_to_string(), expected);
+
+        let (code, map) = transpile(input);
+        assert_eq!(
+            code,
+            "x: int = 0\nx = 1\nif x > 0:\n    print(\"positivo\", end=\"\")\nelse:\n    print(\"negativo\", end=\"\")\n"
+        );
+        assert_eq!(
+            map.lines,
+            vec![Some(1), Some(2), Some(4), Some(5), None, Some(7)]
+        );
+        // `else:` belongs to the `if`, the closest annotated line above it.
+        assert_eq!(map.portugol_line(5), Some(5));
+        assert_eq!(map.portugol_line(6), Some(7));
+        assert_eq!(map.portugol_line(100), Some(7));
+        assert_eq!(map.portugol_line(0), None);
+    }
+
+    #[test]
+    fn json() {
+        let (_, map) = transpile("real x;\n\nx = 1.0 / 0.0;\n");
+        let json = map.to_json();
+        assert_eq!(
+            json,
+            "{\"file\":\"out.py\",\"source\":\"prog.por\",\"lines\":[1,3]}\n"
+        );
+        assert_eq!(SourceMap::from_json(&json).unwrap(), map);
+
+        let map = SourceMap {
+            file: "dir\\\"out\".py".to_string(),
+            source: "prog.por".to_string(),
+            lines: vec![None, Some(10)],
+        };
+        assert_eq!(SourceMap::from_json(&map.to_json()).unwrap(), map);
+
+        let tests = vec![
+            ("", "invalid source map: expected `{`, found the end"),
+            (
+                "{\"file\":\"a\",\"lines\":[]}",
+                "invalid source map: missing key `source`",
+            ),
+            (
+                "{\"file\":\"a\",\"source\":\"b\",\"lines\":[1,x]}",
+                "invalid source map: invalid line `x`",
+            ),
+            (
+                "{\"mappings\":\"\"}",
+                "invalid source map: unknown key `mappings`",
+            ),
+        ];
+        for (input, expected) in tests {
+            let err = SourceMap::from_json(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn traceback() {
+        let (_, map) = transpile("real x;\n\nx = 1.0 / 0.0;\n");
+        let traceback = r#"Traceback (most recent call last):
+  File "out.py", line 2, in <module>
+    x = 1.0 / 0.0
+ZeroDivisionError: float division by zero
+"#;
+        let expected = r#"Traceback (most recent call last):
+  File "prog.por", line 3, in <module>
+    x = 1.0 / 0.0
+ZeroDivisionError: float division by zero
+"#;
+        assert_eq!(map.rewrite_traceback(traceback), expected);
+        // Python shows the path the script was run with.
+        let absolute = "  File \"/tmp/out.py\", line 2, in <module>\n";
+        assert_eq!(
+            map.rewrite_traceback(absolute),
+            "  File \"prog.por\", line 3, in <module>\n"
+        );
+        // Other files are left alone.
+        for other in [
+            "  File \"/usr/lib/python3/x.py\", line 2, in f\n",
+            "  File \"/tmp/about.py\", line 2, in f\n",
+        ] {
+            assert_eq!(map.rewrite_traceback(other), other);
+        }
+    }
+}
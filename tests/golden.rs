@@ -0,0 +1,19 @@
+// Checks the example programs against their goldens. Run `yapc test --bless` to update them.
+use std::path::Path;
+use yapc::golden;
+
+#[test]
+fn examples() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let programs = golden::discover(&examples).unwrap();
+    assert!(!programs.is_empty());
+    let mut failures = vec![];
+    for program in programs {
+        for result in golden::check(&program, false).unwrap() {
+            if !result.passed() {
+                failures.push(format!("{}: {:?}", result.golden.display(), result.status));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}